@@ -0,0 +1,131 @@
+use crate::engine::standard_normal;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+
+/// Rewrites a training batch in place before the forward pass. `Trainer`
+/// applies its transforms to copies of each batch, so the dataset itself
+/// and anything evaluated outside `fit` are never augmented.
+pub trait Transform {
+    fn apply(&mut self, inputs: &mut [Vec<f64>], targets: &mut [Vec<f64>]);
+}
+
+/// Adds independent N(0, std²) noise to every feature.
+pub struct GaussianNoise {
+    std: f64,
+    rng: StdRng,
+}
+
+impl GaussianNoise {
+    pub fn new(std: f64, seed: u64) -> Self {
+        assert!(std >= 0.0, "Noise std must be non-negative.");
+        GaussianNoise {
+            std,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+}
+
+impl Transform for GaussianNoise {
+    fn apply(&mut self, inputs: &mut [Vec<f64>], _targets: &mut [Vec<f64>]) {
+        for x in inputs.iter_mut().flatten() {
+            *x += self.std * standard_normal(&mut self.rng);
+        }
+    }
+}
+
+/// Zeroes each feature independently with probability `p`. Unlike the
+/// `Dropout` module the survivors aren't rescaled, so a dropped feature
+/// looks like a missing one filled with zero.
+pub struct FeatureDropout {
+    p: f64,
+    rng: StdRng,
+}
+
+impl FeatureDropout {
+    pub fn new(p: f64, seed: u64) -> Self {
+        assert!(
+            (0.0..1.0).contains(&p),
+            "Dropout probability must be in [0, 1)."
+        );
+        FeatureDropout {
+            p,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+}
+
+impl Transform for FeatureDropout {
+    fn apply(&mut self, inputs: &mut [Vec<f64>], _targets: &mut [Vec<f64>]) {
+        for x in inputs.iter_mut().flatten() {
+            if self.rng.gen::<f64>() < self.p {
+                *x = 0.0;
+            }
+        }
+    }
+}
+
+/// Mixup (Zhang et al., 2018): every sample in the batch is blended with a
+/// random partner from the same batch, inputs and targets alike, with one
+/// weight `λ ~ Beta(alpha, alpha)` per batch. For classification give the
+/// targets as one-hot rows so the blend is a soft label, which
+/// `CrossEntropyLoss` accepts; blended class indices are rejected. Small
+/// `alpha` keeps most samples close to an original; 0.2 is a common choice.
+pub struct Mixup {
+    alpha: f64,
+    rng: StdRng,
+}
+
+impl Mixup {
+    pub fn new(alpha: f64, seed: u64) -> Self {
+        assert!(alpha > 0.0, "Mixup alpha must be positive.");
+        Mixup {
+            alpha,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    fn beta(&mut self) -> f64 {
+        let x = gamma(self.alpha, &mut self.rng);
+        let y = gamma(self.alpha, &mut self.rng);
+        x / (x + y)
+    }
+}
+
+impl Transform for Mixup {
+    fn apply(&mut self, inputs: &mut [Vec<f64>], targets: &mut [Vec<f64>]) {
+        let lambda = self.beta();
+        let mut partners: Vec<usize> = (0..inputs.len()).collect();
+        partners.shuffle(&mut self.rng);
+        for rows in [inputs, targets] {
+            let original = rows.to_vec();
+            for (row, &j) in rows.iter_mut().zip(partners.iter()) {
+                for (a, b) in row.iter_mut().zip(original[j].iter()) {
+                    *a = lambda * *a + (1.0 - lambda) * b;
+                }
+            }
+        }
+    }
+}
+
+/// A Gamma(shape, 1) draw by Marsaglia and Tsang's method, boosted for
+/// shapes below 1.
+fn gamma<R: Rng + ?Sized>(shape: f64, rng: &mut R) -> f64 {
+    if shape < 1.0 {
+        let u: f64 = rng.gen();
+        return gamma(shape + 1.0, rng) * u.powf(1.0 / shape);
+    }
+    let d = shape - 1.0 / 3.0;
+    let c = 1.0 / (9.0 * d).sqrt();
+    loop {
+        let x = standard_normal(rng);
+        let v = (1.0 + c * x).powi(3);
+        if v <= 0.0 {
+            continue;
+        }
+        let u: f64 = rng.gen();
+        if u.ln() < 0.5 * x * x + d - d * v + d * v.ln() {
+            return d * v;
+        }
+    }
+}
@@ -0,0 +1,132 @@
+//! Reproducible training benchmark, modeled on the moons demo of the
+//! original Python micrograd so the two can be timed on the same work.
+//!
+//! Everything is fixed: 100 two-moons points with noise 0.1 drawn from a
+//! `StdRng` seeded with 1337, labels -1/+1, an `MLP(2, [16, 16, 1])` with
+//! ReLU hidden layers seeded with 1337, full-batch SGD on the mean hinge
+//! loss plus `1e-4 * sum(p^2)`, and a learning rate decaying linearly from
+//! 1.0 to 0.1 over the run. Each step is one forward and backward pass over
+//! the whole dataset and one parameter update.
+//!
+//! Protocol:
+//!
+//! 1. `cargo run --release --bin bench -- --dump-data moons.csv` writes the
+//!    dataset (`x0,x1,y`) and prints the Rust figures.
+//! 2. In the Python repo, load `moons.csv` in place of `make_moons` and run
+//!    the demo's training loop for the same number of steps (the loop is
+//!    already this loss, regularizer and schedule), timing only the loop
+//!    with `time.perf_counter`.
+//! 3. Compare steps/sec. The initial weights differ between the two
+//!    implementations, so compare the final loss and accuracy only as a
+//!    sanity check: both should reach 100% accuracy.
+//!
+//! Run it on an otherwise idle machine and take the best of three runs;
+//! record the steps/sec alongside the commit to track regressions.
+
+use micrograd::loss::hinge;
+use micrograd::optim::{Optimizer, SGD};
+use micrograd::{Activation, Module, Value, MLP};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::env;
+use std::f64::consts::PI;
+use std::fs;
+use std::process;
+use std::time::Instant;
+
+const SEED: u64 = 1337;
+const N_SAMPLES: usize = 100;
+const NOISE: f64 = 0.1;
+
+const USAGE: &str = "usage: bench [--steps <n>] [--dump-data <csv>]";
+
+/// Two interleaved half circles, half the points on each, as in
+/// scikit-learn's `make_moons`, with Gaussian noise of standard deviation
+/// `NOISE` from the Box-Muller transform.
+fn moons() -> (Vec<Vec<f64>>, Vec<f64>) {
+    let mut rng = StdRng::seed_from_u64(SEED);
+    let mut normal = move || {
+        let u1: f64 = 1.0 - rng.gen::<f64>();
+        let u2: f64 = rng.gen();
+        (-2.0 * u1.ln()).sqrt() * (2.0 * PI * u2).cos()
+    };
+    let half = N_SAMPLES / 2;
+    let mut xs = Vec::with_capacity(N_SAMPLES);
+    let mut ys = Vec::with_capacity(N_SAMPLES);
+    for i in 0..N_SAMPLES {
+        let (outer, k) = (i < half, i % half);
+        let t = PI * k as f64 / (half - 1) as f64;
+        let (x, y, label) = if outer {
+            (t.cos(), t.sin(), -1.0)
+        } else {
+            (1.0 - t.cos(), 0.5 - t.sin(), 1.0)
+        };
+        xs.push(vec![x + NOISE * normal(), y + NOISE * normal()]);
+        ys.push(label);
+    }
+    (xs, ys)
+}
+
+fn main() {
+    let mut steps = 100;
+    let mut dump = None;
+    let mut args = env::args().skip(1);
+    while let Some(flag) = args.next() {
+        let value = args.next();
+        match (flag.as_str(), value) {
+            ("--steps", Some(v)) => match v.parse() {
+                Ok(n) if n > 0 => steps = n,
+                _ => {
+                    eprintln!("--steps must be a positive integer\n{}", USAGE);
+                    process::exit(2);
+                }
+            },
+            ("--dump-data", Some(path)) => dump = Some(path),
+            _ => {
+                eprintln!("{}", USAGE);
+                process::exit(2);
+            }
+        }
+    }
+
+    let (xs, ys) = moons();
+    if let Some(path) = dump {
+        let mut csv = String::from("x0,x1,y\n");
+        for (x, y) in xs.iter().zip(ys.iter()) {
+            csv.push_str(&format!("{},{},{}\n", x[0], x[1], y));
+        }
+        if let Err(e) = fs::write(&path, csv) {
+            eprintln!("error: {}: {}", path, e);
+            process::exit(1);
+        }
+    }
+
+    let model = MLP::new_seeded(2, vec![16, 16, 1], Activation::ReLU, SEED);
+    let mut sgd = SGD::new(model.parameters(), 1.0);
+    let mut final_loss = f64::NAN;
+    let mut accuracy = 0.0;
+    let start = Instant::now();
+    for k in 0..steps {
+        let scores: Vec<Value> = xs.iter().map(|x| model.call(x)[0].clone()).collect();
+        let loss = hinge(&scores, &ys) + model.l2_penalty(1e-4);
+        final_loss = loss.get_data();
+        let correct = scores
+            .iter()
+            .zip(ys.iter())
+            .filter(|(s, &y)| (s.get_data() > 0.0) == (y > 0.0))
+            .count();
+        accuracy = correct as f64 / ys.len() as f64;
+
+        sgd.zero_grad();
+        loss.backward();
+        sgd.set_lr(1.0 - 0.9 * k as f64 / steps as f64);
+        sgd.step();
+    }
+    let elapsed = start.elapsed().as_secs_f64();
+
+    println!("steps: {}", steps);
+    println!("seconds: {:.3}", elapsed);
+    println!("steps/sec: {:.1}", steps as f64 / elapsed);
+    println!("final loss: {:.6}", final_loss);
+    println!("accuracy: {:.0}%", 100.0 * accuracy);
+}
@@ -0,0 +1,113 @@
+use crate::engine::Value;
+use crate::loss::cross_entropy;
+use crate::optim::{Adam, Optimizer};
+
+/// One bin of a reliability diagram: samples whose top-class confidence
+/// falls in `[lower, upper)`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReliabilityBin {
+    pub lower: f64,
+    pub upper: f64,
+    pub count: usize,
+    /// Mean top-class probability of the samples in the bin.
+    pub confidence: f64,
+    /// Fraction of those samples whose top class is the label.
+    pub accuracy: f64,
+}
+
+fn top_class(probs: &[f64]) -> (usize, f64) {
+    probs
+        .iter()
+        .copied()
+        .enumerate()
+        .fold((0, f64::NEG_INFINITY), |best, (i, p)| {
+            if p > best.1 {
+                (i, p)
+            } else {
+                best
+            }
+        })
+}
+
+/// Bins predictions into `n_bins` equal-width confidence bins. Empty bins
+/// are kept, with zero confidence and accuracy.
+pub fn reliability_bins(
+    probs: &[Vec<f64>],
+    labels: &[usize],
+    n_bins: usize,
+) -> Vec<ReliabilityBin> {
+    assert_eq!(
+        probs.len(),
+        labels.len(),
+        "Number of predictions must match number of labels."
+    );
+    assert!(n_bins > 0, "Need at least one bin.");
+    let mut bins: Vec<ReliabilityBin> = (0..n_bins)
+        .map(|b| ReliabilityBin {
+            lower: b as f64 / n_bins as f64,
+            upper: (b + 1) as f64 / n_bins as f64,
+            count: 0,
+            confidence: 0.0,
+            accuracy: 0.0,
+        })
+        .collect();
+    for (p, &label) in probs.iter().zip(labels.iter()) {
+        let (class, confidence) = top_class(p);
+        let b = ((confidence * n_bins as f64) as usize).min(n_bins - 1);
+        bins[b].count += 1;
+        bins[b].confidence += confidence;
+        if class == label {
+            bins[b].accuracy += 1.0;
+        }
+    }
+    for bin in bins.iter_mut().filter(|bin| bin.count > 0) {
+        bin.confidence /= bin.count as f64;
+        bin.accuracy /= bin.count as f64;
+    }
+    bins
+}
+
+/// Expected calibration error: the count-weighted mean gap between
+/// confidence and accuracy over the reliability bins.
+pub fn expected_calibration_error(probs: &[Vec<f64>], labels: &[usize], n_bins: usize) -> f64 {
+    let n = probs.len();
+    if n == 0 {
+        return 0.0;
+    }
+    reliability_bins(probs, labels, n_bins)
+        .iter()
+        .map(|bin| bin.count as f64 * (bin.confidence - bin.accuracy).abs())
+        .sum::<f64>()
+        / n as f64
+}
+
+/// Softmax of `logits / temperature`.
+pub fn apply_temperature(logits: &[f64], temperature: f64) -> Vec<f64> {
+    let max = logits.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    let exps: Vec<f64> = logits
+        .iter()
+        .map(|z| ((z - max) / temperature).exp())
+        .collect();
+    let sum: f64 = exps.iter().sum();
+    exps.iter().map(|e| e / sum).collect()
+}
+
+/// Temperature scaling (Guo et al., 2017): fits the single temperature that
+/// minimizes the cross-entropy of `logits / T` on a validation set, with
+/// `steps` Adam steps on `log T`. The argmax, and so accuracy, is unchanged.
+pub fn fit_temperature(logits: &[Vec<f64>], labels: &[usize], steps: usize, lr: f64) -> f64 {
+    let log_t = Value::new(0.0);
+    let mut optimizer = Adam::new(vec![log_t.clone()], lr);
+    for _ in 0..steps {
+        let inv_t = (-&log_t).exp();
+        let scaled: Vec<Vec<Value>> = logits
+            .iter()
+            .map(|z| z.iter().map(|&zi| &inv_t * zi).collect())
+            .collect();
+        let loss = cross_entropy(&scaled, labels);
+        optimizer.zero_grad();
+        loss.backward();
+        optimizer.step();
+    }
+    log_t.get_data().exp()
+}
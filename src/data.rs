@@ -0,0 +1,1074 @@
+use crate::json::{self, Json};
+use rand::seq::SliceRandom;
+use rand::Rng;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+
+/// In-memory tabular dataset: one feature row and one target vector per
+/// sample, matching the shapes `Loss::call` takes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Dataset {
+    pub inputs: Vec<Vec<f64>>,
+    pub targets: Vec<Vec<f64>>,
+}
+
+impl Dataset {
+    pub fn new(inputs: Vec<Vec<f64>>, targets: Vec<Vec<f64>>) -> Self {
+        assert_eq!(
+            inputs.len(),
+            targets.len(),
+            "Number of inputs must match number of targets."
+        );
+        Dataset { inputs, targets }
+    }
+
+    /// Targets equal to the inputs, for training an autoencoder on
+    /// reconstruction.
+    pub fn reconstruction(inputs: Vec<Vec<f64>>) -> Self {
+        let targets = inputs.clone();
+        Dataset { inputs, targets }
+    }
+
+    pub fn len(&self) -> usize {
+        self.inputs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inputs.is_empty()
+    }
+
+    /// The samples at `indices`, in that order.
+    pub fn subset(&self, indices: &[usize]) -> Dataset {
+        Dataset {
+            inputs: indices.iter().map(|&i| self.inputs[i].clone()).collect(),
+            targets: indices.iter().map(|&i| self.targets[i].clone()).collect(),
+        }
+    }
+
+    /// Randomly splits into `(train, test)` with `test_fraction` of the
+    /// samples, rounded, in the test set. Both keep the dataset's order.
+    pub fn split<R: Rng + ?Sized>(&self, test_fraction: f64, rng: &mut R) -> (Dataset, Dataset) {
+        assert_fraction(test_fraction);
+        let mut order: Vec<usize> = (0..self.len()).collect();
+        order.shuffle(rng);
+        let n_test = (test_fraction * self.len() as f64).round() as usize;
+        self.split_at_indices(order.split_off(self.len() - n_test))
+    }
+
+    /// Like `split`, but takes `test_fraction` of each class separately so
+    /// both sides keep the class proportions. A sample's class is the
+    /// argmax of a multi-output target and the value of a single one. Any
+    /// class with at least two samples gets at least one on each side, so
+    /// no class goes missing from a small test set.
+    pub fn stratified_split<R: Rng + ?Sized>(
+        &self,
+        test_fraction: f64,
+        rng: &mut R,
+    ) -> (Dataset, Dataset) {
+        assert_fraction(test_fraction);
+        let mut classes: Vec<(f64, Vec<usize>)> = vec![];
+        for (i, target) in self.targets.iter().enumerate() {
+            let label = class_label(target);
+            match classes
+                .iter_mut()
+                .find(|(c, _)| c.total_cmp(&label).is_eq())
+            {
+                Some((_, members)) => members.push(i),
+                None => classes.push((label, vec![i])),
+            }
+        }
+        let mut test = vec![];
+        for (_, mut members) in classes {
+            members.shuffle(rng);
+            let n = members.len();
+            let mut n_test = (test_fraction * n as f64).round() as usize;
+            if n >= 2 && test_fraction > 0.0 && test_fraction < 1.0 {
+                n_test = n_test.clamp(1, n - 1);
+            }
+            test.extend_from_slice(&members[n - n_test..]);
+        }
+        self.split_at_indices(test)
+    }
+
+    fn split_at_indices(&self, mut test: Vec<usize>) -> (Dataset, Dataset) {
+        test.sort_unstable();
+        let mut in_test = vec![false; self.len()];
+        for &i in test.iter() {
+            in_test[i] = true;
+        }
+        let train: Vec<usize> = (0..self.len()).filter(|&i| !in_test[i]).collect();
+        (self.subset(&train), self.subset(&test))
+    }
+
+    /// FNV-1a hash of every input and target value's bits, for telling
+    /// whether two runs saw the same data. Row boundaries are part of the
+    /// hash.
+    pub fn fingerprint(&self) -> u64 {
+        let mut hash = Fnv::new();
+        for rows in [&self.inputs, &self.targets] {
+            for row in rows.iter() {
+                hash.write(&(row.len() as u64).to_le_bytes());
+                for x in row {
+                    hash.write(&x.to_bits().to_le_bytes());
+                }
+            }
+        }
+        hash.finish()
+    }
+
+    /// Writes the dataset as text, one sample per line with the inputs and
+    /// then the targets separated by a tab. Values round-trip exactly.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let join = |row: &[f64]| {
+            row.iter()
+                .map(|x| x.to_string())
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        let mut out = format!("{}\n", DATASET_HEADER);
+        for (x, y) in self.inputs.iter().zip(self.targets.iter()) {
+            out.push_str(&format!("{}\t{}\n", join(x), join(y)));
+        }
+        fs::write(path, out)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let text = fs::read_to_string(path)?;
+        let mut lines = text.lines();
+        if lines.next() != Some(DATASET_HEADER) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not a dataset file",
+            ));
+        }
+        let parse = |field: &str, line: usize| {
+            field
+                .split_whitespace()
+                .map(|v| {
+                    v.parse()
+                        .map_err(|_| invalid_data(line, format!("invalid number {:?}", v)))
+                })
+                .collect::<io::Result<Vec<f64>>>()
+        };
+        let (mut inputs, mut targets) = (vec![], vec![]);
+        for (i, line) in lines.enumerate() {
+            let (x, y) = line
+                .split_once('\t')
+                .ok_or_else(|| invalid_data(i + 2, "expected inputs and targets".to_string()))?;
+            inputs.push(parse(x, i + 2)?);
+            targets.push(parse(y, i + 2)?);
+        }
+        Ok(Dataset { inputs, targets })
+    }
+
+    pub fn n_features(&self) -> usize {
+        self.inputs.first().map_or(0, |row| row.len())
+    }
+}
+
+const DATASET_HEADER: &str = "micrograd-dataset v1";
+
+/// 64-bit FNV-1a, a small stable hash for content keys.
+struct Fnv(u64);
+
+impl Fnv {
+    fn new() -> Self {
+        Fnv(0xcbf2_9ce4_8422_2325)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            self.0 ^= b as u64;
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+/// Loads a preprocessed dataset from `cache_dir` if an earlier run already
+/// built it, and otherwise calls `build` on `source` and saves the result
+/// there. The cache key hashes the contents of `source` together with
+/// `recipe`, a description of the preprocessing (e.g. `"standardize,
+/// onehot 3"`), so editing either the data or the recipe rebuilds it;
+/// change the recipe whenever `build` changes.
+pub fn load_cached<P, Q, F>(source: P, recipe: &str, cache_dir: Q, build: F) -> io::Result<Dataset>
+where
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+    F: FnOnce(&Path) -> io::Result<Dataset>,
+{
+    let source = source.as_ref();
+    let mut hash = Fnv::new();
+    hash.write(&fs::read(source)?);
+    hash.write(&[0]);
+    hash.write(recipe.as_bytes());
+    let cache_dir = cache_dir.as_ref();
+    let path = cache_dir.join(format!("{:016x}.dataset", hash.finish()));
+    if path.exists() {
+        return Dataset::load(path);
+    }
+    let dataset = build(source)?;
+    fs::create_dir_all(cache_dir)?;
+    // Write then rename, so an interrupted run can't leave a partial file
+    // that later runs would trust.
+    let partial = path.with_extension("partial");
+    dataset.save(&partial)?;
+    fs::rename(partial, path)?;
+    Ok(dataset)
+}
+
+fn assert_fraction(fraction: f64) {
+    assert!(
+        (0.0..=1.0).contains(&fraction),
+        "Test fraction must be between 0 and 1."
+    );
+}
+
+/// The class a target encodes: the argmax of several outputs (one-hot or
+/// scores), or the value itself for a single output.
+pub(crate) fn class_label(target: &[f64]) -> f64 {
+    if target.len() == 1 {
+        return target[0];
+    }
+    let (argmax, _) = target
+        .iter()
+        .enumerate()
+        .fold((0, f64::NEG_INFINITY), |best, (i, &y)| {
+            if y > best.1 {
+                (i, y)
+            } else {
+                best
+            }
+        });
+    argmax as f64
+}
+
+fn invalid_data(line: usize, msg: String) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("line {}: {}", line, msg),
+    )
+}
+
+/// Parses libsvm/svmlight text (`label index:value ...`, 1-based indices)
+/// into dense rows. Missing entries are zero. With `n_features = None` the
+/// width is inferred from the largest index seen; `qid:` tokens and `#`
+/// comments are ignored.
+pub fn parse_libsvm(text: &str, n_features: Option<usize>) -> io::Result<Dataset> {
+    let mut sparse: Vec<Vec<(usize, f64)>> = vec![];
+    let mut targets: Vec<Vec<f64>> = vec![];
+    let mut max_index = 0;
+
+    for (i, raw) in text.lines().enumerate() {
+        let line_no = i + 1;
+        let line = raw.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+        let mut tokens = line.split_whitespace();
+        let label = tokens.next().unwrap_or("");
+        let label: f64 = label
+            .parse()
+            .map_err(|_| invalid_data(line_no, format!("invalid label {:?}", label)))?;
+
+        let mut row = vec![];
+        for token in tokens {
+            if token.starts_with("qid:") {
+                continue;
+            }
+            let (index, value) = token.split_once(':').ok_or_else(|| {
+                invalid_data(line_no, format!("expected index:value, got {:?}", token))
+            })?;
+            let index: usize = index
+                .parse()
+                .ok()
+                .filter(|&index| index >= 1)
+                .ok_or_else(|| invalid_data(line_no, format!("invalid index {:?}", index)))?;
+            let value: f64 = value
+                .parse()
+                .map_err(|_| invalid_data(line_no, format!("invalid value {:?}", value)))?;
+            if let Some(n) = n_features {
+                if index > n {
+                    return Err(invalid_data(
+                        line_no,
+                        format!("index {} exceeds {} features", index, n),
+                    ));
+                }
+            }
+            max_index = max_index.max(index);
+            row.push((index - 1, value));
+        }
+        sparse.push(row);
+        targets.push(vec![label]);
+    }
+
+    let width = n_features.unwrap_or(max_index);
+    let inputs = sparse
+        .into_iter()
+        .map(|row| {
+            let mut dense = vec![0.0; width];
+            for (j, value) in row {
+                dense[j] = value;
+            }
+            dense
+        })
+        .collect();
+    Ok(Dataset::new(inputs, targets))
+}
+
+pub fn load_libsvm<P: AsRef<Path>>(path: P, n_features: Option<usize>) -> io::Result<Dataset> {
+    parse_libsvm(&fs::read_to_string(path)?, n_features)
+}
+
+/// Streams `(features, targets)` rows from a delimiter-separated file
+/// without loading it into memory. Columns listed in `target_columns` become
+/// the target vector, the rest are features. Empty fields parse as NaN;
+/// quoted fields are not supported.
+pub struct CsvStream<R: BufRead> {
+    lines: io::Lines<R>,
+    header: Option<String>,
+    target_columns: Vec<usize>,
+    delimiter: char,
+    line_no: usize,
+}
+
+impl CsvStream<BufReader<File>> {
+    pub fn open<P: AsRef<Path>>(
+        path: P,
+        has_header: bool,
+        target_columns: Vec<usize>,
+    ) -> io::Result<Self> {
+        Self::from_reader(
+            BufReader::new(File::open(path)?),
+            has_header,
+            target_columns,
+        )
+    }
+}
+
+impl<R: BufRead> CsvStream<R> {
+    pub fn from_reader(
+        reader: R,
+        has_header: bool,
+        target_columns: Vec<usize>,
+    ) -> io::Result<Self> {
+        let mut stream = CsvStream {
+            lines: reader.lines(),
+            header: None,
+            target_columns,
+            delimiter: ',',
+            line_no: 0,
+        };
+        if has_header {
+            if let Some(line) = stream.lines.next() {
+                stream.line_no += 1;
+                stream.header = Some(line?);
+            }
+        }
+        Ok(stream)
+    }
+
+    pub fn delimiter(mut self, delimiter: char) -> Self {
+        self.delimiter = delimiter;
+        self
+    }
+
+    pub fn header(&self) -> Option<Vec<String>> {
+        self.header.as_ref().map(|line| {
+            line.split(self.delimiter)
+                .map(|h| h.trim().to_string())
+                .collect()
+        })
+    }
+
+    fn parse_row(&self, line: &str) -> io::Result<(Vec<f64>, Vec<f64>)> {
+        let mut features = vec![];
+        let mut targets = vec![0.0; self.target_columns.len()];
+        let mut n_fields = 0;
+        for (j, field) in line.split(self.delimiter).enumerate() {
+            n_fields += 1;
+            let field = field.trim();
+            let value = if field.is_empty() {
+                f64::NAN
+            } else {
+                field.parse().map_err(|_| {
+                    invalid_data(
+                        self.line_no,
+                        format!("invalid number {:?} in column {}", field, j),
+                    )
+                })?
+            };
+            match self.target_columns.iter().position(|&t| t == j) {
+                Some(k) => targets[k] = value,
+                None => features.push(value),
+            }
+        }
+        if let Some(&missing) = self.target_columns.iter().find(|&&t| t >= n_fields) {
+            return Err(invalid_data(
+                self.line_no,
+                format!("target column {} missing from row", missing),
+            ));
+        }
+        Ok((features, targets))
+    }
+}
+
+impl<R: BufRead> Iterator for CsvStream<R> {
+    type Item = io::Result<(Vec<f64>, Vec<f64>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let line = match self.lines.next()? {
+                Ok(line) => line,
+                Err(e) => return Some(Err(e)),
+            };
+            self.line_no += 1;
+            if !line.trim().is_empty() {
+                return Some(self.parse_row(&line));
+            }
+        }
+    }
+}
+
+/// Loads a whole CSV into memory; see `CsvStream` for the row format.
+pub fn load_csv<P: AsRef<Path>>(
+    path: P,
+    has_header: bool,
+    target_columns: Vec<usize>,
+) -> io::Result<Dataset> {
+    let mut inputs = vec![];
+    let mut targets = vec![];
+    for row in CsvStream::open(path, has_header, target_columns)? {
+        let (x, y) = row?;
+        inputs.push(x);
+        targets.push(y);
+    }
+    Ok(Dataset::new(inputs, targets))
+}
+
+/// Parses a JSON batch of feature rows, `{"inputs": [[x, ...], ...]}`, as
+/// sent to `micrograd serve`. Every row must have the same width.
+pub fn parse_json_inputs(text: &str) -> io::Result<Vec<Vec<f64>>> {
+    let bad = |msg: String| io::Error::new(io::ErrorKind::InvalidData, msg);
+    let doc = json::parse(text).map_err(bad)?;
+    let rows = doc
+        .get("inputs")
+        .and_then(Json::as_array)
+        .ok_or_else(|| bad("expected an object with an \"inputs\" array".to_string()))?;
+    let rows = rows
+        .iter()
+        .enumerate()
+        .map(|(i, row)| {
+            row.as_f64_vec()
+                .ok_or_else(|| bad(format!("input {}: expected an array of numbers", i)))
+        })
+        .collect::<io::Result<Vec<_>>>()?;
+    if let Some(first) = rows.first() {
+        if let Some(i) = rows.iter().position(|row| row.len() != first.len()) {
+            return Err(bad(format!(
+                "input {}: expected {} features, got {}",
+                i,
+                first.len(),
+                rows[i].len()
+            )));
+        }
+    }
+    Ok(rows)
+}
+
+/// How missing (NaN) feature values are filled in.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Impute {
+    Mean,
+    Median,
+    Constant(f64),
+    /// Drops any sample with a missing feature or target.
+    DropRow,
+}
+
+/// Column-wise imputation fitted on training inputs, so the same fill values
+/// can be reused on validation and inference data.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Imputer {
+    strategy: Impute,
+    fill: Vec<f64>,
+}
+
+impl Imputer {
+    /// Computes per-column fill values from the non-missing entries. Under
+    /// `Mean` and `Median`, columns with no observed values fall back to 0.
+    pub fn fit(inputs: &[Vec<f64>], strategy: Impute) -> Self {
+        let width = inputs.first().map_or(0, |row| row.len());
+        let fill = (0..width)
+            .map(|j| {
+                let mut observed: Vec<f64> = inputs
+                    .iter()
+                    .map(|row| row[j])
+                    .filter(|x| !x.is_nan())
+                    .collect();
+                match strategy {
+                    Impute::Mean | Impute::Median if observed.is_empty() => 0.0,
+                    Impute::Mean => observed.iter().sum::<f64>() / observed.len() as f64,
+                    Impute::Median => {
+                        observed.sort_by(f64::total_cmp);
+                        let mid = observed.len() / 2;
+                        if observed.len().is_multiple_of(2) {
+                            (observed[mid - 1] + observed[mid]) / 2.0
+                        } else {
+                            observed[mid]
+                        }
+                    }
+                    Impute::Constant(c) => c,
+                    Impute::DropRow => f64::NAN,
+                }
+            })
+            .collect();
+        Imputer { strategy, fill }
+    }
+
+    /// Fills one feature row, or returns `None` if the row should be dropped.
+    pub fn transform_row(&self, row: &[f64]) -> Option<Vec<f64>> {
+        assert_eq!(
+            row.len(),
+            self.fill.len(),
+            "Row width must match the fitted data."
+        );
+        if self.strategy == Impute::DropRow {
+            return if row.iter().any(|x| x.is_nan()) {
+                None
+            } else {
+                Some(row.to_vec())
+            };
+        }
+        Some(
+            row.iter()
+                .zip(self.fill.iter())
+                .map(|(&x, &fill)| if x.is_nan() { fill } else { x })
+                .collect(),
+        )
+    }
+
+    /// Per-column fill values; NaN under `DropRow`.
+    pub fn fill_values(&self) -> &[f64] {
+        &self.fill
+    }
+
+    pub fn transform(&self, dataset: &Dataset) -> Dataset {
+        let mut inputs = vec![];
+        let mut targets = vec![];
+        for (x, y) in dataset.inputs.iter().zip(dataset.targets.iter()) {
+            if self.strategy == Impute::DropRow && y.iter().any(|t| t.is_nan()) {
+                continue;
+            }
+            if let Some(x) = self.transform_row(x) {
+                inputs.push(x);
+                targets.push(y.clone());
+            }
+        }
+        Dataset::new(inputs, targets)
+    }
+}
+
+/// Approximately shuffles a stream by sampling uniformly from a bounded
+/// buffer, so arbitrarily long iterators can be shuffled in fixed memory.
+/// Larger buffers give a closer approximation to a full shuffle.
+pub struct ShuffleBuffer<I: Iterator, R: Rng> {
+    inner: I,
+    buffer: Vec<I::Item>,
+    capacity: usize,
+    rng: R,
+}
+
+impl<I: Iterator, R: Rng> ShuffleBuffer<I, R> {
+    pub fn new(inner: I, capacity: usize, rng: R) -> Self {
+        assert!(capacity > 0, "Shuffle buffer capacity must be positive.");
+        ShuffleBuffer {
+            inner,
+            buffer: Vec::with_capacity(capacity),
+            capacity,
+            rng,
+        }
+    }
+}
+
+impl<I: Iterator, R: Rng> Iterator for ShuffleBuffer<I, R> {
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.buffer.len() < self.capacity {
+            match self.inner.next() {
+                Some(item) => self.buffer.push(item),
+                None => break,
+            }
+        }
+        if self.buffer.is_empty() {
+            return None;
+        }
+        let i = self.rng.gen_range(0..self.buffer.len());
+        Some(self.buffer.swap_remove(i))
+    }
+}
+
+#[cfg(feature = "parquet")]
+pub use parquet::load_parquet;
+
+/// A reader for the subset of Parquet that unconfigured writers commonly
+/// produce with compression and dictionaries turned off: flat schemas of
+/// numeric and boolean columns, PLAIN-encoded v1 or v2 data pages, and
+/// optional columns whose nulls become NaN. The footer and page headers
+/// are Thrift compact protocol, decoded by hand.
+#[cfg(feature = "parquet")]
+mod parquet {
+    use super::Dataset;
+    use std::fs;
+    use std::io;
+    use std::path::Path;
+
+    const MAGIC: &[u8] = b"PAR1";
+    /// Deeper Thrift nesting than this only shows up in corrupt files.
+    const MAX_DEPTH: usize = 64;
+
+    fn error(msg: String) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, msg)
+    }
+
+    /// A decoded Thrift value; structs keep their fields by id. Booleans,
+    /// doubles and maps are skipped over as `Other`, since no field read
+    /// here has those types.
+    enum Thrift {
+        Int(i64),
+        Binary(Vec<u8>),
+        List(Vec<Thrift>),
+        Struct(Vec<(i16, Thrift)>),
+        Other,
+    }
+
+    impl Thrift {
+        fn field(&self, id: i16) -> Option<&Thrift> {
+            match self {
+                Thrift::Struct(fields) => fields.iter().find(|(f, _)| *f == id).map(|(_, v)| v),
+                _ => None,
+            }
+        }
+
+        fn int(&self, id: i16) -> Option<i64> {
+            match self.field(id) {
+                Some(Thrift::Int(v)) => Some(*v),
+                _ => None,
+            }
+        }
+
+        fn list(&self, id: i16) -> &[Thrift] {
+            match self.field(id) {
+                Some(Thrift::List(items)) => items,
+                _ => &[],
+            }
+        }
+
+        fn string(&self, id: i16) -> Option<String> {
+            match self.field(id) {
+                Some(Thrift::Binary(bytes)) => Some(String::from_utf8_lossy(bytes).into_owned()),
+                _ => None,
+            }
+        }
+
+        /// A non-negative size or count field.
+        fn size(&self, id: i16, what: &str) -> io::Result<usize> {
+            self.int(id)
+                .and_then(|v| usize::try_from(v).ok())
+                .ok_or_else(|| error(format!("missing or invalid {}", what)))
+        }
+    }
+
+    struct Reader<'a> {
+        bytes: &'a [u8],
+        pos: usize,
+        depth: usize,
+    }
+
+    impl<'a> Reader<'a> {
+        fn new(bytes: &'a [u8]) -> Self {
+            Reader {
+                bytes,
+                pos: 0,
+                depth: 0,
+            }
+        }
+
+        fn take(&mut self, n: usize) -> io::Result<&'a [u8]> {
+            let end = self
+                .pos
+                .checked_add(n)
+                .filter(|&end| end <= self.bytes.len())
+                .ok_or_else(|| error("unexpected end of Parquet data".to_string()))?;
+            let bytes = &self.bytes[self.pos..end];
+            self.pos = end;
+            Ok(bytes)
+        }
+
+        fn byte(&mut self) -> io::Result<u8> {
+            Ok(self.take(1)?[0])
+        }
+
+        fn varint(&mut self) -> io::Result<u64> {
+            let mut value = 0u64;
+            for shift in (0..64).step_by(7) {
+                let b = self.byte()?;
+                value |= ((b & 0x7f) as u64) << shift;
+                if b & 0x80 == 0 {
+                    return Ok(value);
+                }
+            }
+            Err(error("varint is too long".to_string()))
+        }
+
+        fn zigzag(&mut self) -> io::Result<i64> {
+            let v = self.varint()?;
+            Ok((v >> 1) as i64 ^ -((v & 1) as i64))
+        }
+
+        fn length(&mut self) -> io::Result<usize> {
+            usize::try_from(self.varint()?).map_err(|_| error("length is too large".to_string()))
+        }
+
+        fn value(&mut self, kind: u8) -> io::Result<Thrift> {
+            Ok(match kind {
+                // Booleans in field headers carry their value in the type.
+                1 | 2 => Thrift::Other,
+                3 => Thrift::Int(self.byte()? as i8 as i64),
+                4..=6 => Thrift::Int(self.zigzag()?),
+                7 => {
+                    self.take(8)?;
+                    Thrift::Other
+                }
+                8 => {
+                    let n = self.length()?;
+                    Thrift::Binary(self.take(n)?.to_vec())
+                }
+                9 | 10 => {
+                    let header = self.byte()?;
+                    let n = match header >> 4 {
+                        15 => self.length()?,
+                        n => n as usize,
+                    };
+                    let mut items = Vec::with_capacity(n.min(1024));
+                    for _ in 0..n {
+                        items.push(self.element(header & 0x0f)?);
+                    }
+                    Thrift::List(items)
+                }
+                11 => {
+                    let n = self.length()?;
+                    if n > 0 {
+                        let kinds = self.byte()?;
+                        for _ in 0..n {
+                            self.element(kinds >> 4)?;
+                            self.element(kinds & 0x0f)?;
+                        }
+                    }
+                    Thrift::Other
+                }
+                12 => self.structure()?,
+                _ => return Err(error(format!("unknown Thrift type {}", kind))),
+            })
+        }
+
+        /// A list, set or map element; booleans take a byte each there.
+        fn element(&mut self, kind: u8) -> io::Result<Thrift> {
+            match kind {
+                1 | 2 => {
+                    self.byte()?;
+                    Ok(Thrift::Other)
+                }
+                _ => self.value(kind),
+            }
+        }
+
+        fn structure(&mut self) -> io::Result<Thrift> {
+            self.depth += 1;
+            if self.depth > MAX_DEPTH {
+                return Err(error("Thrift structs are nested too deeply".to_string()));
+            }
+            let mut fields = Vec::new();
+            let mut last = 0i16;
+            loop {
+                let header = self.byte()?;
+                if header == 0 {
+                    self.depth -= 1;
+                    return Ok(Thrift::Struct(fields));
+                }
+                let id = match header >> 4 {
+                    0 => self.zigzag()? as i16,
+                    delta => last.wrapping_add(delta as i16),
+                };
+                last = id;
+                fields.push((id, self.value(header & 0x0f)?));
+            }
+        }
+    }
+
+    /// A leaf of the schema, in the order column chunks appear.
+    struct Column {
+        name: String,
+        kind: i64,
+        optional: bool,
+    }
+
+    const BOOLEAN: i64 = 0;
+    const INT32: i64 = 1;
+    const INT64: i64 = 2;
+    const FLOAT: i64 = 4;
+    const DOUBLE: i64 = 5;
+
+    fn columns(meta: &Thrift) -> io::Result<Vec<Column>> {
+        let schema = meta.list(2);
+        if schema.is_empty() {
+            return Err(error("Parquet footer has no schema".to_string()));
+        }
+        schema[1..]
+            .iter()
+            .map(|element| {
+                let name = element
+                    .string(4)
+                    .ok_or_else(|| error("schema element has no name".to_string()))?;
+                if element.int(5).unwrap_or(0) > 0 {
+                    return Err(error(format!(
+                        "column {:?} is nested; only flat schemas are supported",
+                        name
+                    )));
+                }
+                let optional = match element.int(3).unwrap_or(0) {
+                    0 => false,
+                    1 => true,
+                    _ => {
+                        return Err(error(format!(
+                            "column {:?} is repeated; only flat schemas are supported",
+                            name
+                        )))
+                    }
+                };
+                let kind = element
+                    .int(1)
+                    .ok_or_else(|| error(format!("column {:?} has no type", name)))?;
+                Ok(Column {
+                    name,
+                    kind,
+                    optional,
+                })
+            })
+            .collect()
+    }
+
+    /// Reads the named columns of a Parquet file into a dataset: `inputs`
+    /// become the feature columns and `targets` the target columns, each in
+    /// the order given, so table columns can be selected and reordered
+    /// without a CSV round trip. Numeric and boolean columns are read as
+    /// `f64`, with nulls as NaN for `Imputer` to fill. Compressed or
+    /// dictionary-encoded columns, and nested schemas, are an
+    /// `InvalidData` error; write such files with compression and
+    /// dictionaries disabled.
+    pub fn load_parquet<P: AsRef<Path>>(
+        path: P,
+        inputs: &[&str],
+        targets: &[&str],
+    ) -> io::Result<Dataset> {
+        let bytes = fs::read(path)?;
+        let n = bytes.len();
+        if n < 12 || &bytes[..4] != MAGIC || &bytes[n - 4..] != MAGIC {
+            return Err(error("not a Parquet file".to_string()));
+        }
+        let len = u32::from_le_bytes(bytes[n - 8..n - 4].try_into().unwrap()) as usize;
+        let start = (n - 8)
+            .checked_sub(len)
+            .filter(|&start| start >= 4)
+            .ok_or_else(|| error("invalid Parquet footer length".to_string()))?;
+        let meta = Reader::new(&bytes[start..n - 8]).structure()?;
+        let columns = columns(&meta)?;
+        let selected = inputs
+            .iter()
+            .chain(targets)
+            .map(|name| {
+                columns
+                    .iter()
+                    .position(|c| c.name == *name)
+                    .ok_or_else(|| error(format!("no column named {:?}", name)))
+            })
+            .collect::<io::Result<Vec<usize>>>()?;
+
+        let mut values = vec![Vec::new(); selected.len()];
+        for group in meta.list(4) {
+            let chunks = group.list(1);
+            for (out, &c) in values.iter_mut().zip(&selected) {
+                let column = &columns[c];
+                let chunk = chunks
+                    .get(c)
+                    .and_then(|chunk| chunk.field(3))
+                    .ok_or_else(|| {
+                        error(format!(
+                            "row group has no metadata for column {:?}",
+                            column.name
+                        ))
+                    })?;
+                read_chunk(&bytes, chunk, column, out)
+                    .map_err(|e| error(format!("column {:?}: {}", column.name, e)))?;
+            }
+        }
+        let n_rows = values.first().map_or(0, Vec::len);
+        if values.iter().any(|v| v.len() != n_rows) {
+            return Err(error("selected columns have different lengths".to_string()));
+        }
+        let rows = |range: std::ops::Range<usize>| -> Vec<Vec<f64>> {
+            (0..n_rows)
+                .map(|i| values[range.clone()].iter().map(|v| v[i]).collect())
+                .collect()
+        };
+        Ok(Dataset::new(
+            rows(0..inputs.len()),
+            rows(inputs.len()..selected.len()),
+        ))
+    }
+
+    /// Appends the values of one column chunk to `out`.
+    fn read_chunk(
+        bytes: &[u8],
+        meta: &Thrift,
+        column: &Column,
+        out: &mut Vec<f64>,
+    ) -> io::Result<()> {
+        let codec = meta.int(4).unwrap_or(0);
+        if codec != 0 {
+            return Err(error(format!(
+                "compressed with codec {}; only uncompressed files are supported",
+                codec
+            )));
+        }
+        let num_values = meta.size(5, "value count")?;
+        let mut pos = meta.size(9, "data page offset")?;
+        let mut read = 0;
+        while read < num_values {
+            let mut reader = Reader::new(bytes.get(pos..).unwrap_or(&[]));
+            let header = reader.structure()?;
+            let size = header.size(3, "page size")?;
+            let page = reader.take(size)?;
+            pos += reader.pos;
+            let (n, encoding, defined, values) = match header.int(1) {
+                // DATA_PAGE: levels are prefixed with their byte length.
+                Some(0) => {
+                    let page_header = header
+                        .field(5)
+                        .ok_or_else(|| error("data page has no header".to_string()))?;
+                    let n = page_header.size(1, "page value count")?;
+                    let mut page = Reader::new(page);
+                    let defined = if column.optional {
+                        let len = u32::from_le_bytes(page.take(4)?.try_into().unwrap());
+                        definition_levels(page.take(len as usize)?, n)?
+                    } else {
+                        vec![true; n]
+                    };
+                    (n, page_header.int(2), defined, &page.bytes[page.pos..])
+                }
+                // DATA_PAGE_V2: levels come first, with their lengths in
+                // the header.
+                Some(3) => {
+                    let page_header = header
+                        .field(8)
+                        .ok_or_else(|| error("data page has no header".to_string()))?;
+                    let n = page_header.size(1, "page value count")?;
+                    let mut page = Reader::new(page);
+                    page.take(page_header.size(6, "repetition levels length")?)?;
+                    let levels = page.take(page_header.size(5, "definition levels length")?)?;
+                    let defined = if column.optional {
+                        definition_levels(levels, n)?
+                    } else {
+                        vec![true; n]
+                    };
+                    (n, page_header.int(4), defined, &page.bytes[page.pos..])
+                }
+                Some(2) => {
+                    return Err(error(
+                        "dictionary-encoded; only PLAIN encoding is supported".to_string(),
+                    ))
+                }
+                // Index pages carry nothing we read.
+                _ => continue,
+            };
+            if encoding != Some(0) {
+                return Err(error(format!(
+                    "encoding {:?} is not supported; only PLAIN is",
+                    encoding.unwrap_or(-1)
+                )));
+            }
+            let present = defined.iter().filter(|&&d| d).count();
+            let mut plain = plain_values(values, column.kind, present)?.into_iter();
+            out.extend(
+                defined
+                    .iter()
+                    .map(|&d| if d { plain.next().unwrap() } else { f64::NAN }),
+            );
+            read += n;
+        }
+        Ok(())
+    }
+
+    /// Decodes `n` one-bit definition levels from the RLE/bit-packed
+    /// hybrid encoding; `true` marks a non-null value.
+    fn definition_levels(bytes: &[u8], n: usize) -> io::Result<Vec<bool>> {
+        let mut reader = Reader::new(bytes);
+        let mut levels = Vec::with_capacity(n);
+        while levels.len() < n {
+            let header = reader.length()?;
+            let count = header >> 1;
+            if header & 1 == 0 {
+                // A run of `count` copies of one value.
+                let level = reader.byte()? & 1 == 1;
+                levels.extend(std::iter::repeat_n(level, count.min(n - levels.len())));
+            } else {
+                // `count` groups of eight bit-packed values.
+                for &byte in reader.take(count)? {
+                    levels.extend((0..8).map(|bit| (byte >> bit) & 1 == 1));
+                }
+            }
+        }
+        levels.truncate(n);
+        Ok(levels)
+    }
+
+    fn plain_values(bytes: &[u8], kind: i64, n: usize) -> io::Result<Vec<f64>> {
+        let truncated = || error("page has fewer values than its header says".to_string());
+        if kind == BOOLEAN {
+            if bytes.len() < n.div_ceil(8) {
+                return Err(truncated());
+            }
+            return Ok((0..n)
+                .map(|i| ((bytes[i / 8] >> (i % 8)) & 1) as f64)
+                .collect());
+        }
+        let width = match kind {
+            INT32 | FLOAT => 4,
+            INT64 | DOUBLE => 8,
+            _ => {
+                return Err(error(format!(
+                    "physical type {} is not numeric; only BOOLEAN, INT32, INT64, FLOAT and DOUBLE columns can be read",
+                    kind
+                )))
+            }
+        };
+        if bytes.len() / width < n {
+            return Err(truncated());
+        }
+        Ok(bytes
+            .chunks_exact(width)
+            .take(n)
+            .map(|b| match kind {
+                INT32 => i32::from_le_bytes(b.try_into().unwrap()) as f64,
+                INT64 => i64::from_le_bytes(b.try_into().unwrap()) as f64,
+                FLOAT => f32::from_le_bytes(b.try_into().unwrap()) as f64,
+                _ => f64::from_le_bytes(b.try_into().unwrap()),
+            })
+            .collect())
+    }
+}
@@ -0,0 +1,99 @@
+use crate::engine::Value;
+
+/// Backpropagates each loss separately and returns the gradient of every
+/// parameter per loss. Parameter grads are left zeroed.
+pub fn per_sample_grads(params: &[Value], losses: Vec<Value>) -> Vec<Vec<f64>> {
+    let grads = losses
+        .into_iter()
+        .map(|loss| {
+            for p in params {
+                p.update_grad(0.0);
+            }
+            loss.backward();
+            params.iter().map(|p| p.get_grad()).collect()
+        })
+        .collect();
+    for p in params {
+        p.update_grad(0.0);
+    }
+    grads
+}
+
+/// Estimates the simple gradient noise scale `B = tr(Sigma) / |G|^2` from
+/// per-sample losses over a batch, where `G` is the mean gradient and
+/// `Sigma` the per-sample gradient covariance. Batch sizes well below `B`
+/// are dominated by gradient noise; well above it they waste compute.
+pub fn gradient_noise_scale(params: &[Value], losses: Vec<Value>) -> f64 {
+    assert!(
+        losses.len() >= 2,
+        "Need at least two samples to estimate gradient variance."
+    );
+    let grads = per_sample_grads(params, losses);
+    let n = grads.len() as f64;
+
+    let mut mean = vec![0.0; params.len()];
+    for g in grads.iter() {
+        for (m, gj) in mean.iter_mut().zip(g.iter()) {
+            *m += gj / n;
+        }
+    }
+    let trace: f64 = grads
+        .iter()
+        .map(|g| {
+            g.iter()
+                .zip(mean.iter())
+                .map(|(gj, m)| (gj - m).powi(2))
+                .sum::<f64>()
+        })
+        .sum::<f64>()
+        / (n - 1.0);
+    let mean_sq_norm: f64 = mean.iter().map(|m| m.powi(2)).sum();
+    trace / mean_sq_norm
+}
+
+/// A starting learning rate for one parameter group; see `suggest_lr`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LrSuggestion {
+    pub group: String,
+    /// Root mean square of the group's parameters.
+    pub param_rms: f64,
+    /// Root mean square of the group's gradients.
+    pub grad_rms: f64,
+    /// `None` when the group's gradients are all zero.
+    pub lr: Option<f64>,
+}
+
+/// Relative weight change per step the suggestions aim for; around 1e-3 is
+/// the usual rule of thumb for a healthy update-to-weight ratio.
+pub const TARGET_UPDATE_RATIO: f64 = 1e-3;
+
+/// Backpropagates `loss` once and suggests, per group, the plain SGD
+/// learning rate at which the first step would change the group's weights
+/// by `TARGET_UPDATE_RATIO` of their size: `ratio * param_rms / grad_rms`.
+/// A heuristic for picking a starting point, strongest at telling groups
+/// that need very different rates apart. Parameter grads are left zeroed.
+pub fn suggest_lr(groups: &[(String, Vec<Value>)], loss: Value) -> Vec<LrSuggestion> {
+    for p in groups.iter().flat_map(|(_, params)| params) {
+        p.update_grad(0.0);
+    }
+    loss.backward();
+    let rms =
+        |xs: Vec<f64>| (xs.iter().map(|x| x * x).sum::<f64>() / xs.len().max(1) as f64).sqrt();
+    let suggestions = groups
+        .iter()
+        .map(|(group, params)| {
+            let param_rms = rms(params.iter().map(|p| p.get_data()).collect());
+            let grad_rms = rms(params.iter().map(|p| p.get_grad()).collect());
+            LrSuggestion {
+                group: group.clone(),
+                param_rms,
+                grad_rms,
+                lr: (grad_rms > 0.0).then(|| TARGET_UPDATE_RATIO * param_rms / grad_rms),
+            }
+        })
+        .collect();
+    for p in groups.iter().flat_map(|(_, params)| params) {
+        p.update_grad(0.0);
+    }
+    suggestions
+}
@@ -0,0 +1,1049 @@
+use rand::Rng;
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
+use std::f64;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::ops::{Add, Div, Mul, Neg, Sub};
+use std::rc::Rc;
+use std::vec;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Op {
+    Add,
+    Mul,
+    Tanh,
+    Exp,
+    Pow,
+    GradReverse(f64),
+    Selu,
+    HardSigmoid,
+    HardTanh,
+    Relu,
+    Log,
+    Sigmoid,
+    Sqrt,
+    Abs,
+    Sin,
+    Cos,
+    Max,
+    Min,
+}
+
+impl Op {
+    /// Number of operands a node with this op has.
+    pub fn arity(&self) -> usize {
+        match self {
+            Op::Add | Op::Mul | Op::Pow | Op::Max | Op::Min => 2,
+            _ => 1,
+        }
+    }
+}
+
+// Constants from Klambauer et al. (2017) that make SELU self-normalizing.
+pub const SELU_ALPHA: f64 = 1.673_263_242_354_377_3;
+pub const SELU_SCALE: f64 = 1.050_700_987_355_480_5;
+
+/// `exp` clamps its argument to `[-EXP_MAX_ARG, EXP_MAX_ARG]`, so results stay
+/// finite and nonzero (`e^700` is about `1e304`) instead of overflowing to
+/// inf or underflowing to 0 and turning later products and logs into NaN.
+pub const EXP_MAX_ARG: f64 = 700.0;
+
+/// How `pow` (and so division, which is `pow(-1)`), `ln` and `sqrt` treat
+/// inputs outside their domain: a zero base with a negative exponent, a
+/// negative base with a fractional exponent, a non-positive log argument or
+/// a negative square root argument.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DomainPolicy {
+    /// IEEE semantics: inf and NaN flow on silently. The default.
+    Propagate,
+    Panic,
+    /// Like `Propagate`, but records the first violation for
+    /// `take_domain_error`, so a training loop can stop with an error.
+    Error,
+    /// Moves inputs outside the domain or within `eps` of its boundary to
+    /// `eps` (`-eps` for a negative base near zero), in both the forward and
+    /// backward pass.
+    Stabilize(f64),
+}
+
+/// An op input outside its domain, recorded under `DomainPolicy::Error`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DomainError {
+    pub op: Op,
+    pub input: f64,
+}
+
+impl fmt::Display for DomainError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?} of {} is outside its domain", self.op, self.input)
+    }
+}
+
+impl std::error::Error for DomainError {}
+
+thread_local! {
+    static DOMAIN_POLICY: Cell<DomainPolicy> = const { Cell::new(DomainPolicy::Propagate) };
+    static DOMAIN_ERROR: RefCell<Option<DomainError>> = const { RefCell::new(None) };
+}
+
+/// Sets the policy for the current thread, returning the previous one. Set it
+/// before building a graph: the backward pass applies whatever is current.
+pub fn set_domain_policy(policy: DomainPolicy) -> DomainPolicy {
+    DOMAIN_POLICY.with(|p| p.replace(policy))
+}
+
+pub fn domain_policy() -> DomainPolicy {
+    DOMAIN_POLICY.with(|p| p.get())
+}
+
+/// Returns and clears the first violation recorded under
+/// `DomainPolicy::Error`.
+pub fn take_domain_error() -> Option<DomainError> {
+    DOMAIN_ERROR.with(|e| e.borrow_mut().take())
+}
+
+/// Applies the domain policy to the input `x` of a `Log` op or the base of a
+/// `Pow` op with the given exponent, returning the value to compute with.
+pub(crate) fn domain_input(op: Op, x: f64, exponent: f64) -> f64 {
+    let policy = domain_policy();
+    // Stabilizing also catches inputs within `eps` of the boundary.
+    let tol = match policy {
+        DomainPolicy::Stabilize(eps) => eps,
+        _ => 0.0,
+    };
+    let fractional = op == Op::Pow && x < 0.0 && exponent.fract() != 0.0;
+    let valid = match op {
+        Op::Log => x > tol,
+        // sqrt(0) is fine forward, but its derivative is infinite.
+        Op::Sqrt if tol > 0.0 => x > tol,
+        Op::Sqrt => x >= 0.0,
+        _ => !(fractional || x.abs() <= tol && exponent < 0.0),
+    };
+    if valid {
+        return x;
+    }
+    match policy {
+        DomainPolicy::Propagate => x,
+        DomainPolicy::Panic => panic!("{:?} of {} is outside its domain.", op, x),
+        DomainPolicy::Error => {
+            DOMAIN_ERROR.with(|e| {
+                e.borrow_mut().get_or_insert(DomainError { op, input: x });
+            });
+            x
+        }
+        DomainPolicy::Stabilize(eps) => {
+            if op == Op::Pow && !fractional && x.is_sign_negative() {
+                -eps
+            } else {
+                eps
+            }
+        }
+    }
+}
+
+/// A backward rule that overwrote an operand's grad instead of adding to it,
+/// found by `Value::audit_backward`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GradOverwrite {
+    pub op: Op,
+    /// The operand's prior grad plus the rule's own contribution.
+    pub expected: f64,
+    pub actual: f64,
+}
+
+/// A node-level difference between two graphs, found by
+/// `Value::diff_graph`. `left` and `right` index the paired nodes in each
+/// graph's topological order, as numbered by `to_dot` and `to_graphml`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NodeDiff {
+    pub left: usize,
+    pub right: usize,
+    pub kind: DiffKind,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DiffKind {
+    /// Different ops (`None` for a leaf). Operands aren't compared further.
+    Op(Option<Op>, Option<Op>),
+    /// Same op, but the data differs by more than the tolerance.
+    Data(f64, f64),
+    /// One graph shares a node where the other has two distinct ones, as
+    /// common subexpression elimination does.
+    Sharing,
+}
+
+#[derive(Debug, Clone)]
+struct _Value {
+    data: f64,
+    /// Operands, in order: empty for leaves, one for unary ops, two for
+    /// binary ones.
+    _prev: Vec<Value>,
+    _op: Option<Op>,
+    grad: f64,
+    /// Whether backward has to reach this node: set on parameters, off on
+    /// constants, and inherited by results from any of their operands.
+    requires_grad: bool,
+    /// Keeps the grad of an op result after backward; see `retain_grad`.
+    retain_grad: bool,
+}
+
+// Dropping a long chain of nodes recursively would overflow the stack, so
+// operands this node solely owns are unlinked iteratively instead.
+impl Drop for _Value {
+    fn drop(&mut self) {
+        let mut stack: Vec<Value> = std::mem::take(&mut self._prev);
+        while let Some(v) = stack.pop() {
+            if let Ok(cell) = Rc::try_unwrap(v.0) {
+                stack.append(&mut cell.borrow_mut()._prev);
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Value(Rc<RefCell<_Value>>);
+
+// Values compare and hash by node identity: two handles are equal when they
+// point at the same graph node, regardless of the data they hold.
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for Value {}
+
+impl Hash for Value {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        Rc::as_ptr(&self.0).hash(state);
+    }
+}
+
+impl Value {
+    /// A leaf that gets a gradient, such as a parameter or an input being
+    /// explained.
+    pub fn new(data: f64) -> Self {
+        Self::leaf(data, true)
+    }
+
+    /// A leaf that never needs a gradient. `backward` skips any subgraph
+    /// built only from constants; plain `f64` operands become constants.
+    pub fn constant(data: f64) -> Self {
+        Self::leaf(data, false)
+    }
+
+    fn leaf(data: f64, requires_grad: bool) -> Self {
+        Value(Rc::new(RefCell::new(_Value {
+            data,
+            _prev: vec![],
+            _op: None,
+            grad: 0.0,
+            requires_grad,
+            retain_grad: false,
+        })))
+    }
+
+    fn new_ext(data: f64, _children: Vec<Value>, _op: Option<Op>) -> Self {
+        debug_assert_eq!(
+            _op.as_ref().map_or(0, Op::arity),
+            _children.len(),
+            "Operand count must match the op's arity."
+        );
+        let requires_grad = _children.iter().any(|c| c.requires_grad());
+        Value(Rc::new(RefCell::new(_Value {
+            data,
+            _prev: _children,
+            _op,
+            grad: 0.0,
+            requires_grad,
+            retain_grad: false,
+        })))
+    }
+
+    pub fn requires_grad(&self) -> bool {
+        self.0.borrow().requires_grad
+    }
+
+    /// Turns gradient tracking on or off for a leaf, e.g. to freeze a
+    /// parameter. Only affects results built afterwards.
+    pub fn set_requires_grad(&self, requires_grad: bool) {
+        assert!(self.is_leaf(), "Only leaves can change requires_grad.");
+        self.0.borrow_mut().requires_grad = requires_grad;
+    }
+
+    /// Keeps this op result's grad after `backward`, which otherwise resets
+    /// the grads of intermediate nodes once it is done so stale values are
+    /// never mistaken for fresh ones. Leaf grads are always kept.
+    pub fn retain_grad(&self) {
+        self.0.borrow_mut().retain_grad = true;
+    }
+
+    /// True for parameters and constants, false for op results.
+    pub fn is_leaf(&self) -> bool {
+        self.0.borrow()._op.is_none()
+    }
+
+    pub fn update_data(&self, new_data: f64) {
+        let mut inner = self.0.borrow_mut();
+        inner.data = new_data;
+    }
+
+    pub fn update_grad(&self, new_grad: f64) {
+        let mut inner = self.0.borrow_mut();
+        inner.grad = new_grad;
+    }
+
+    pub fn get_data(&self) -> f64 {
+        self.0.borrow().data
+    }
+
+    /// The operands this value was computed from; empty for leaves.
+    pub fn get_prev(&self) -> Vec<Value> {
+        self.0.borrow()._prev.clone()
+    }
+
+    pub fn get_op(&self) -> Option<Op> {
+        self.0.borrow()._op.clone()
+    }
+
+    pub fn get_grad(&self) -> f64 {
+        self.0.borrow().grad
+    }
+
+    pub fn tanh(self) -> Self {
+        Self::new_ext(self.get_data().tanh(), vec![self.clone()], Some(Op::Tanh))
+    }
+
+    /// Overflow-safe: the argument is clamped to `±EXP_MAX_ARG`. The backward
+    /// pass uses the clamped output as the local derivative, so saturated
+    /// inputs still get a (large or tiny) finite gradient rather than inf.
+    pub fn exp(self) -> Self {
+        Self::new_ext(
+            self.get_data().clamp(-EXP_MAX_ARG, EXP_MAX_ARG).exp(),
+            vec![self.clone()],
+            Some(Op::Exp),
+        )
+    }
+
+    /// Non-positive inputs follow the current `DomainPolicy`.
+    pub fn ln(self) -> Self {
+        Self::new_ext(
+            domain_input(Op::Log, self.get_data(), 0.0).ln(),
+            vec![self.clone()],
+            Some(Op::Log),
+        )
+    }
+
+    /// Negative inputs follow the current `DomainPolicy`.
+    pub fn sqrt(self) -> Self {
+        Self::new_ext(
+            domain_input(Op::Sqrt, self.get_data(), 0.0).sqrt(),
+            vec![self.clone()],
+            Some(Op::Sqrt),
+        )
+    }
+
+    /// Backward uses the subgradient `sign(x)`, with 0 at `x = 0`.
+    pub fn abs(self) -> Self {
+        Self::new_ext(self.get_data().abs(), vec![self.clone()], Some(Op::Abs))
+    }
+
+    pub fn sin(self) -> Self {
+        Self::new_ext(self.get_data().sin(), vec![self.clone()], Some(Op::Sin))
+    }
+
+    pub fn cos(self) -> Self {
+        Self::new_ext(self.get_data().cos(), vec![self.clone()], Some(Op::Cos))
+    }
+
+    pub fn relu(self) -> Self {
+        Self::new_ext(self.get_data().max(0.0), vec![self.clone()], Some(Op::Relu))
+    }
+
+    /// Logistic sigmoid as a single node, evaluated without overflow for
+    /// large `|x|`.
+    pub fn sigmoid(self) -> Self {
+        let x = self.get_data();
+        let out = if x >= 0.0 {
+            1.0 / (1.0 + (-x).exp())
+        } else {
+            let e = x.exp();
+            e / (1.0 + e)
+        };
+        Self::new_ext(out, vec![self.clone()], Some(Op::Sigmoid))
+    }
+
+    pub fn selu(self) -> Self {
+        let x = self.get_data();
+        let out = if x > 0.0 {
+            SELU_SCALE * x
+        } else {
+            SELU_SCALE * SELU_ALPHA * (x.exp() - 1.0)
+        };
+        Self::new_ext(out, vec![self.clone()], Some(Op::Selu))
+    }
+
+    /// Piecewise-linear sigmoid, `clamp(x / 6 + 0.5, 0, 1)`.
+    pub fn hard_sigmoid(self) -> Self {
+        Self::new_ext(
+            (self.get_data() / 6.0 + 0.5).clamp(0.0, 1.0),
+            vec![self.clone()],
+            Some(Op::HardSigmoid),
+        )
+    }
+
+    /// Piecewise-linear tanh, `clamp(x, -1, 1)`.
+    pub fn hard_tanh(self) -> Self {
+        Self::new_ext(
+            self.get_data().clamp(-1.0, 1.0),
+            vec![self.clone()],
+            Some(Op::HardTanh),
+        )
+    }
+
+    /// Identity in the forward pass; scales the gradient by `-lambda` on the
+    /// way back.
+    pub fn grad_reverse(self, lambda: f64) -> Self {
+        Self::new_ext(
+            self.get_data(),
+            vec![self.clone()],
+            Some(Op::GradReverse(lambda)),
+        )
+    }
+
+    /// Zero bases with negative exponents and negative bases with fractional
+    /// exponents follow the current `DomainPolicy`. Both the base and the
+    /// exponent get gradients; use `powf` for a constant exponent.
+    pub fn pow(self, other: Self) -> Self {
+        let exponent = other.get_data();
+        Self::new_ext(
+            domain_input(Op::Pow, self.get_data(), exponent).powf(exponent),
+            vec![self.clone(), other.clone()],
+            Some(Op::Pow),
+        )
+    }
+
+    /// Sum of `values` as a balanced tree of `Add` nodes, so the graph is
+    /// only `log2(n)` deep instead of the `n` of a left fold. `0` when empty.
+    pub fn sum(values: &[Value]) -> Value {
+        if values.is_empty() {
+            return Value::constant(0.0);
+        }
+        let mut level = values.to_vec();
+        while level.len() > 1 {
+            level = level
+                .chunks(2)
+                .map(|pair| match pair {
+                    [a, b] => a + b,
+                    _ => pair[0].clone(),
+                })
+                .collect();
+        }
+        level.pop().unwrap()
+    }
+
+    pub fn mean(values: &[Value]) -> Value {
+        assert!(!values.is_empty(), "Cannot take the mean of no values.");
+        Value::sum(values) * (1.0 / values.len() as f64)
+    }
+
+    /// `self^exponent` for a constant exponent.
+    pub fn powf(self, exponent: f64) -> Self {
+        self.pow(Value::constant(exponent))
+    }
+
+    /// The larger operand; the gradient goes to the winner only, and to
+    /// `self` on ties.
+    pub fn max(self, other: Self) -> Self {
+        let data = self.get_data().max(other.get_data());
+        Self::new_ext(data, vec![self.clone(), other.clone()], Some(Op::Max))
+    }
+
+    /// The smaller operand, routing the gradient like `max`.
+    pub fn min(self, other: Self) -> Self {
+        let data = self.get_data().min(other.get_data());
+        Self::new_ext(data, vec![self.clone(), other.clone()], Some(Op::Min))
+    }
+
+    /// `exp(x_i) / sum_j exp(x_j)` built from graph ops, so gradients flow
+    /// back to every logit. The max logit is subtracted first, which keeps
+    /// `exp` from overflowing without changing the result.
+    pub fn softmax(logits: &[Value]) -> Vec<Value> {
+        // Shifting by the max is a constant offset, so it leaves grads intact.
+        let max = logits
+            .iter()
+            .map(|x| x.get_data())
+            .fold(f64::NEG_INFINITY, f64::max);
+        let exps: Vec<Value> = logits.iter().map(|x| (x - max).exp()).collect();
+        let inv_sum = Value::sum(&exps).powf(-1.0);
+        exps.into_iter().map(|e| e * &inv_sum).collect()
+    }
+
+    /// Samples `mu + exp(0.5 * log_var) * eps` with `eps ~ N(0, 1)`.
+    ///
+    /// The noise enters the graph as a constant, so gradients flow to `mu`
+    /// and `log_var` only (the reparameterization trick).
+    pub fn gaussian<R: Rng + ?Sized>(mu: Self, log_var: Self, rng: &mut R) -> Self {
+        let eps = standard_normal(rng);
+        let std = (log_var * 0.5).exp();
+        mu + std * eps
+    }
+
+    /// Gumbel-softmax (concrete) relaxation of sampling from `logits`.
+    ///
+    /// With `hard`, the forward value is the one-hot argmax while gradients
+    /// follow the soft sample (straight-through estimator).
+    pub fn gumbel_softmax<R: Rng + ?Sized>(
+        logits: &[Value],
+        temperature: f64,
+        hard: bool,
+        rng: &mut R,
+    ) -> Vec<Value> {
+        assert!(temperature > 0.0, "Temperature must be positive.");
+        let scaled: Vec<Value> = logits
+            .iter()
+            .map(|logit| {
+                let u: f64 = rng.gen_range(f64::MIN_POSITIVE..1.0);
+                let g = -(-u.ln()).ln();
+                (logit + g) * (1.0 / temperature)
+            })
+            .collect();
+
+        let soft = Value::softmax(&scaled);
+
+        if !hard {
+            return soft;
+        }
+        let argmax = soft.iter().enumerate().fold(0, |best, (i, y)| {
+            if y.get_data() > soft[best].get_data() {
+                i
+            } else {
+                best
+            }
+        });
+        soft.into_iter()
+            .enumerate()
+            .map(|(i, y)| {
+                let one_hot = if i == argmax { 1.0 } else { 0.0 };
+                let offset = one_hot - y.get_data();
+                y + offset
+            })
+            .collect()
+    }
+
+    /// Adds `delta` to the grad unless this value needs none. Binary rules
+    /// use it so a constant operand, such as the `2` in `2 * w`, never picks
+    /// up a gradient; a unary rule's operand always needs one, or backward
+    /// would have pruned the node.
+    fn accumulate_grad(&self, delta: f64) {
+        if self.requires_grad() {
+            self.update_grad(self.get_grad() + delta);
+        }
+    }
+
+    fn _backward(self) {
+        let children = self.get_prev();
+        if let Some(a) = children.first() {
+            match self.get_op() {
+                Some(Op::Add) => {
+                    let b = &children[1];
+                    a.accumulate_grad(self.get_grad());
+                    b.accumulate_grad(self.get_grad());
+                }
+                Some(Op::Mul) => {
+                    let b = &children[1];
+                    a.accumulate_grad(b.get_data() * self.get_grad());
+                    b.accumulate_grad(a.get_data() * self.get_grad());
+                }
+                Some(Op::Tanh) => {
+                    let t = self.get_data();
+                    a.update_grad(a.get_grad() + (1.0 - t * t) * self.get_grad());
+                }
+                Some(Op::Exp) => {
+                    a.update_grad(a.get_grad() + self.get_data() * self.get_grad());
+                }
+                Some(Op::Pow) => {
+                    let b = &children[1];
+                    let base = domain_input(Op::Pow, a.get_data(), b.get_data());
+                    a.accumulate_grad(
+                        b.get_data() * base.powf(b.get_data() - 1.0) * self.get_grad(),
+                    );
+                    if b.requires_grad() {
+                        // d(a^b)/db = a^b ln(a), which tends to 0 as a -> 0+ and
+                        // is undefined (NaN) for negative bases.
+                        let local = if base == 0.0 {
+                            0.0
+                        } else {
+                            self.get_data() * base.ln()
+                        };
+                        b.update_grad(b.get_grad() + local * self.get_grad());
+                    }
+                }
+                Some(Op::GradReverse(lambda)) => {
+                    a.update_grad(a.get_grad() - lambda * self.get_grad());
+                }
+                Some(Op::Log) => {
+                    let x = domain_input(Op::Log, a.get_data(), 0.0);
+                    a.update_grad(a.get_grad() + self.get_grad() / x);
+                }
+                Some(Op::Relu) => {
+                    let local = if self.get_data() > 0.0 { 1.0 } else { 0.0 };
+                    a.update_grad(a.get_grad() + local * self.get_grad());
+                }
+                Some(Op::Selu) => {
+                    let x = a.get_data();
+                    let local = if x > 0.0 {
+                        SELU_SCALE
+                    } else {
+                        SELU_SCALE * SELU_ALPHA * x.exp()
+                    };
+                    a.update_grad(a.get_grad() + local * self.get_grad());
+                }
+                Some(Op::HardSigmoid) => {
+                    // Subgradient: zero on the saturated pieces and at the kinks.
+                    let x = a.get_data();
+                    let local = if x > -3.0 && x < 3.0 { 1.0 / 6.0 } else { 0.0 };
+                    a.update_grad(a.get_grad() + local * self.get_grad());
+                }
+                Some(Op::HardTanh) => {
+                    let x = a.get_data();
+                    let local = if x > -1.0 && x < 1.0 { 1.0 } else { 0.0 };
+                    a.update_grad(a.get_grad() + local * self.get_grad());
+                }
+                Some(Op::Sigmoid) => {
+                    let s = self.get_data();
+                    a.update_grad(a.get_grad() + s * (1.0 - s) * self.get_grad());
+                }
+                Some(Op::Sqrt) => {
+                    a.update_grad(a.get_grad() + 0.5 / self.get_data() * self.get_grad());
+                }
+                Some(Op::Abs) => {
+                    let x = a.get_data();
+                    let local = if x > 0.0 {
+                        1.0
+                    } else if x < 0.0 {
+                        -1.0
+                    } else {
+                        0.0
+                    };
+                    a.update_grad(a.get_grad() + local * self.get_grad());
+                }
+                Some(Op::Sin) => {
+                    a.update_grad(a.get_grad() + a.get_data().cos() * self.get_grad());
+                }
+                Some(Op::Cos) => {
+                    a.update_grad(a.get_grad() - a.get_data().sin() * self.get_grad());
+                }
+                Some(op @ (Op::Max | Op::Min)) => {
+                    let b = &children[1];
+                    let a_wins = if op == Op::Max {
+                        a.get_data() >= b.get_data()
+                    } else {
+                        a.get_data() <= b.get_data()
+                    };
+                    let winner = if a_wins { a } else { b };
+                    winner.accumulate_grad(self.get_grad());
+                }
+                None => {}
+            }
+        }
+    }
+
+    /// Every node of the graph rooted at this value, operands before results
+    /// and the root last. The order is a depth-first post-order that visits
+    /// operands in argument order, so it depends only on how the graph was
+    /// built, never on addresses or hashing: the same construction gives the
+    /// same order on every run. Exports and `diff_graph` number nodes this
+    /// way, and backward walks it in reverse. Built with an explicit work
+    /// stack so deep graphs can't overflow the call stack.
+    pub fn topo_order(&self) -> Vec<Value> {
+        self.topo_where(|_| true)
+    }
+
+    /// Like `topo_order`, but only nodes that need a gradient, so backward
+    /// never walks constant-only subgraphs.
+    fn grad_topo(&self) -> Vec<Value> {
+        self.topo_where(Value::requires_grad)
+    }
+
+    fn topo_where(&self, keep: fn(&Value) -> bool) -> Vec<Value> {
+        let mut topo: Vec<Value> = vec![];
+        let mut visited: HashSet<Value> = HashSet::new();
+        // The flag marks nodes whose operands have already been pushed.
+        let mut stack: Vec<(Value, bool)> = vec![(self.clone(), false)];
+        while let Some((v, expanded)) = stack.pop() {
+            if expanded {
+                topo.push(v);
+                continue;
+            }
+            if !keep(&v) || !visited.insert(v.clone()) {
+                continue;
+            }
+            let prev = v.get_prev();
+            stack.push((v, true));
+            // Pushed in reverse so the first operand is explored first.
+            for child in prev.into_iter().rev() {
+                stack.push((child, false));
+            }
+        }
+        topo
+    }
+
+    pub fn backward(self) {
+        let mut topo = self.grad_topo();
+
+        self.update_grad(1.0);
+        topo.reverse();
+        for node in topo.iter() {
+            node.clone()._backward();
+        }
+        release_intermediate_grads(&topo);
+    }
+
+    /// Debug variant of `backward` that checks every rule accumulates. Each
+    /// node's rule first runs against zeroed operand grads to measure its
+    /// contribution, then for real; any operand whose grad doesn't come out
+    /// as prior plus contribution is reported. Grads end up exactly as
+    /// `backward` would leave them.
+    pub fn audit_backward(self) -> Vec<GradOverwrite> {
+        let mut topo = self.grad_topo();
+
+        self.update_grad(1.0);
+        topo.reverse();
+        let mut issues = vec![];
+        for node in topo.iter() {
+            let mut operands = node.get_prev();
+            // `x * x` lists one node twice; check it once.
+            operands.dedup();
+            let prior: Vec<f64> = operands.iter().map(|v| v.get_grad()).collect();
+            for v in operands.iter() {
+                v.update_grad(0.0);
+            }
+            node.clone()._backward();
+            let contribution: Vec<f64> = operands.iter().map(|v| v.get_grad()).collect();
+            for (v, &g) in operands.iter().zip(prior.iter()) {
+                v.update_grad(g);
+            }
+            node.clone()._backward();
+            for ((v, &g), &c) in operands.iter().zip(prior.iter()).zip(contribution.iter()) {
+                let (expected, actual) = (g + c, v.get_grad());
+                if (expected - actual).abs() > 1e-12 * expected.abs().max(1.0) {
+                    issues.push(GradOverwrite {
+                        op: node.get_op().unwrap(),
+                        expected,
+                        actual,
+                    });
+                }
+            }
+        }
+        release_intermediate_grads(&topo);
+        issues
+    }
+
+    /// A constant holding this value's data, cut off from its history.
+    pub fn detach(&self) -> Value {
+        Value::constant(self.get_data())
+    }
+
+    /// Unlinks every node in the graph from its operands, keeping data and
+    /// grads. Intermediate nodes are then freed as soon as their last handle
+    /// goes, even if some result (say, a loss kept for logging) outlives the
+    /// training step. Call after `backward`; leaves such as parameters are
+    /// untouched.
+    pub fn release_graph(&self) {
+        for node in self.topo_order() {
+            node.0.borrow_mut()._prev.clear();
+        }
+    }
+
+    /// Nodes of the graph in topological order, plus operand -> result
+    /// edges as index pairs.
+    fn graph_edges(&self) -> (Vec<Value>, Vec<(usize, usize)>) {
+        let nodes = self.topo_order();
+        let index: HashMap<Value, usize> = nodes
+            .iter()
+            .enumerate()
+            .map(|(i, v)| (v.clone(), i))
+            .collect();
+        let mut edges = vec![];
+        for (i, v) in nodes.iter().enumerate() {
+            for child in v.get_prev() {
+                edges.push((index[&child], i));
+            }
+        }
+        (nodes, edges)
+    }
+
+    /// Renders the graph rooted at this value as Graphviz DOT in the style of
+    /// micrograd's `draw_dot`: a record per value with its data and grad,
+    /// and a separate node for the op that produced it. Intermediate grads
+    /// only show for nodes marked with `retain_grad` before `backward`.
+    pub fn to_dot(&self) -> String {
+        let (nodes, edges) = self.graph_edges();
+        let mut out = String::from("digraph {\n  rankdir=LR;\n");
+        for (i, node) in nodes.iter().enumerate() {
+            out.push_str(&format!(
+                "  n{} [shape=record, label=\"{{ data {:.4} | grad {:.4} }}\"];\n",
+                i,
+                node.get_data(),
+                node.get_grad()
+            ));
+            if let Some(op) = node.get_op() {
+                out.push_str(&format!("  n{}_op [label=\"{:?}\"];\n", i, op));
+                out.push_str(&format!("  n{}_op -> n{};\n", i, i));
+            }
+        }
+        for (source, target) in edges {
+            out.push_str(&format!("  n{} -> n{}_op;\n", source, target));
+        }
+        out.push_str("}\n");
+        out
+    }
+
+    /// Exports the graph rooted at this value as GraphML, with data, grad and
+    /// op as node attributes and edges pointing from operands to results.
+    pub fn to_graphml(&self) -> String {
+        let (nodes, edges) = self.graph_edges();
+
+        let mut out = String::new();
+        out.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        out.push_str("<graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">\n");
+        out.push_str("  <key id=\"data\" for=\"node\" attr.name=\"data\" attr.type=\"double\"/>\n");
+        out.push_str("  <key id=\"grad\" for=\"node\" attr.name=\"grad\" attr.type=\"double\"/>\n");
+        out.push_str("  <key id=\"op\" for=\"node\" attr.name=\"op\" attr.type=\"string\"/>\n");
+        out.push_str("  <graph id=\"G\" edgedefault=\"directed\">\n");
+        for (i, node) in nodes.iter().enumerate() {
+            out.push_str(&format!("    <node id=\"n{}\">\n", i));
+            out.push_str(&format!(
+                "      <data key=\"data\">{}</data>\n",
+                node.get_data()
+            ));
+            out.push_str(&format!(
+                "      <data key=\"grad\">{}</data>\n",
+                node.get_grad()
+            ));
+            if let Some(op) = node.get_op() {
+                out.push_str(&format!("      <data key=\"op\">{:?}</data>\n", op));
+            }
+            out.push_str("    </node>\n");
+        }
+        for (source, target) in edges {
+            out.push_str(&format!(
+                "    <edge source=\"n{}\" target=\"n{}\"/>\n",
+                source, target
+            ));
+        }
+        out.push_str("  </graph>\n");
+        out.push_str("</graphml>\n");
+        out
+    }
+
+    /// Structurally compares the graph rooted at this value with the one
+    /// rooted at `other`, e.g. before and after a rewrite pass. Nodes are
+    /// paired from the roots down, operand by operand; an empty result means
+    /// the graphs have the same shape and ops, with data within `tol`.
+    pub fn diff_graph(&self, other: &Value, tol: f64) -> Vec<NodeDiff> {
+        let index = |root: &Value| -> HashMap<Value, usize> {
+            root.topo_order()
+                .into_iter()
+                .enumerate()
+                .map(|(i, v)| (v, i))
+                .collect()
+        };
+        let (left_index, right_index) = (index(self), index(other));
+        let mut pairs: HashMap<Value, Value> = HashMap::new();
+        let mut paired_right: HashSet<Value> = HashSet::new();
+        let mut diffs = vec![];
+        let mut stack = vec![(self.clone(), other.clone())];
+        while let Some((l, r)) = stack.pop() {
+            let mut report = |kind| {
+                diffs.push(NodeDiff {
+                    left: left_index[&l],
+                    right: right_index[&r],
+                    kind,
+                })
+            };
+            if let Some(seen) = pairs.get(&l) {
+                if *seen != r {
+                    report(DiffKind::Sharing);
+                }
+                continue;
+            }
+            if !paired_right.insert(r.clone()) {
+                report(DiffKind::Sharing);
+                continue;
+            }
+            pairs.insert(l.clone(), r.clone());
+            let (left_op, right_op) = (l.get_op(), r.get_op());
+            if left_op != right_op {
+                report(DiffKind::Op(left_op, right_op));
+                continue;
+            }
+            let (a, b) = (l.get_data(), r.get_data());
+            // Checked for equality first since equal infinities would give
+            // a NaN difference.
+            let same = a == b || (a - b).abs() <= tol || a.is_nan() && b.is_nan();
+            if !same {
+                report(DiffKind::Data(a, b));
+            }
+            // Same op, so the same number of operands. Pushed in reverse so
+            // diffs come out in operand order.
+            for pair in l.get_prev().into_iter().zip(r.get_prev()).rev() {
+                stack.push(pair);
+            }
+        }
+        diffs
+    }
+}
+
+/// Zeroes the grads of op results not marked with `retain_grad`.
+fn release_intermediate_grads(nodes: &[Value]) {
+    for node in nodes {
+        let mut inner = node.0.borrow_mut();
+        if inner._op.is_some() && !inner.retain_grad {
+            inner.grad = 0.0;
+        }
+    }
+}
+
+/// Draws from N(0, 1) with the Box-Muller transform.
+pub(crate) fn standard_normal<R: Rng + ?Sized>(rng: &mut R) -> f64 {
+    // 1 - u keeps the log argument in (0, 1].
+    let u1: f64 = 1.0 - rng.gen::<f64>();
+    let u2: f64 = rng.gen();
+    (-2.0 * u1.ln()).sqrt() * (2.0 * f64::consts::PI * u2).cos()
+}
+
+impl Add for Value {
+    type Output = Value;
+
+    fn add(self, other: Value) -> Value {
+        Value::new_ext(
+            self.get_data() + other.get_data(),
+            vec![self.clone(), other.clone()],
+            Some(Op::Add),
+        )
+    }
+}
+
+impl Mul for Value {
+    type Output = Value;
+
+    fn mul(self, other: Value) -> Value {
+        Value::new_ext(
+            self.get_data() * other.get_data(),
+            vec![self.clone(), other.clone()],
+            Some(Op::Mul),
+        )
+    }
+}
+
+impl Sub for Value {
+    type Output = Value;
+
+    fn sub(self, other: Value) -> Value {
+        self + (-other)
+    }
+}
+
+impl Div for Value {
+    type Output = Value;
+
+    fn div(self, other: Value) -> Value {
+        self * other.powf(-1.0)
+    }
+}
+
+impl Neg for Value {
+    type Output = Value;
+
+    fn neg(self) -> Value {
+        self * -1.0
+    }
+}
+
+impl Neg for &Value {
+    type Output = Value;
+
+    fn neg(self) -> Value {
+        -self.clone()
+    }
+}
+
+// Forwards the owned/borrowed and scalar operand combinations of a binary
+// op to its `Value op Value` impl; scalars become constant leaves.
+macro_rules! forward_binop {
+    ($imp:ident, $method:ident) => {
+        impl $imp<&Value> for Value {
+            type Output = Value;
+
+            fn $method(self, other: &Value) -> Value {
+                $imp::$method(self, other.clone())
+            }
+        }
+
+        impl $imp<Value> for &Value {
+            type Output = Value;
+
+            fn $method(self, other: Value) -> Value {
+                $imp::$method(self.clone(), other)
+            }
+        }
+
+        impl $imp<&Value> for &Value {
+            type Output = Value;
+
+            fn $method(self, other: &Value) -> Value {
+                $imp::$method(self.clone(), other.clone())
+            }
+        }
+
+        impl $imp<f64> for Value {
+            type Output = Value;
+
+            fn $method(self, other: f64) -> Value {
+                $imp::$method(self, Value::constant(other))
+            }
+        }
+
+        impl $imp<f64> for &Value {
+            type Output = Value;
+
+            fn $method(self, other: f64) -> Value {
+                $imp::$method(self.clone(), Value::constant(other))
+            }
+        }
+
+        impl $imp<Value> for f64 {
+            type Output = Value;
+
+            fn $method(self, other: Value) -> Value {
+                $imp::$method(Value::constant(self), other)
+            }
+        }
+
+        impl $imp<&Value> for f64 {
+            type Output = Value;
+
+            fn $method(self, other: &Value) -> Value {
+                $imp::$method(Value::constant(self), other.clone())
+            }
+        }
+    };
+}
+
+forward_binop!(Add, add);
+forward_binop!(Sub, sub);
+forward_binop!(Mul, mul);
+forward_binop!(Div, div);
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.get_data().fract() == 0.0 {
+            write!(f, "Value(data={:.1})", self.get_data())
+        } else {
+            write!(f, "Value(data={})", self.get_data())
+        }
+    }
+}
@@ -0,0 +1,101 @@
+use crate::data::Dataset;
+use crate::engine::Value;
+use crate::nn::{Module, MLP};
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+/// Gradient of output `output` with respect to each input feature at
+/// `input`. Parameter grads are left zeroed.
+fn input_gradient(model: &MLP, input: &[f64], output: usize) -> Vec<f64> {
+    let inputs: Vec<Value> = input.iter().map(|&x| Value::new(x)).collect();
+    let outputs = model.forward(&inputs);
+    assert!(output < outputs.len(), "Output index out of range.");
+    outputs[output].clone().backward();
+    model.zero_grad();
+    inputs.iter().map(|x| x.get_grad()).collect()
+}
+
+/// Vanilla gradient saliency: `|d output / d x_j|` for every feature.
+pub fn saliency(model: &MLP, input: &[f64], output: usize) -> Vec<f64> {
+    input_gradient(model, input, output)
+        .into_iter()
+        .map(f64::abs)
+        .collect()
+}
+
+/// Integrated gradients (Sundararajan et al., 2017) along the straight path
+/// from `baseline` to `input`, approximated with a `steps`-point midpoint
+/// Riemann sum. Attributions sum to roughly `f(input) - f(baseline)`.
+pub fn integrated_gradients(
+    model: &MLP,
+    input: &[f64],
+    baseline: &[f64],
+    output: usize,
+    steps: usize,
+) -> Vec<f64> {
+    assert_eq!(
+        input.len(),
+        baseline.len(),
+        "Baseline size must match input size."
+    );
+    assert!(steps > 0, "Integrated gradients needs at least one step.");
+    let mut total = vec![0.0; input.len()];
+    for k in 0..steps {
+        let alpha = (k as f64 + 0.5) / steps as f64;
+        let point: Vec<f64> = baseline
+            .iter()
+            .zip(input.iter())
+            .map(|(&b, &x)| b + alpha * (x - b))
+            .collect();
+        for (t, g) in total.iter_mut().zip(input_gradient(model, &point, output)) {
+            *t += g;
+        }
+    }
+    total
+        .iter()
+        .zip(input.iter().zip(baseline.iter()))
+        .map(|(&g, (&x, &b))| (x - b) * g / steps as f64)
+        .collect()
+}
+
+/// Model-agnostic permutation importance (Breiman, 2001): for each feature,
+/// the average drop in `score(predictions, targets)` over `n_repeats` random
+/// shuffles of that feature's column. `score` is higher-is-better, so pass
+/// a negated loss; `predict` maps one feature row to the model's outputs.
+pub fn permutation_importance<P, S, R>(
+    predict: P,
+    dataset: &Dataset,
+    score: S,
+    n_repeats: usize,
+    rng: &mut R,
+) -> Vec<f64>
+where
+    P: Fn(&[f64]) -> Vec<f64>,
+    S: Fn(&[Vec<f64>], &[Vec<f64>]) -> f64,
+    R: Rng + ?Sized,
+{
+    assert!(
+        n_repeats > 0,
+        "Permutation importance needs at least one repeat."
+    );
+    let evaluate = |inputs: &[Vec<f64>]| {
+        let preds: Vec<Vec<f64>> = inputs.iter().map(|x| predict(x)).collect();
+        score(&preds, &dataset.targets)
+    };
+    let baseline = evaluate(&dataset.inputs);
+    (0..dataset.n_features())
+        .map(|j| {
+            let mut column: Vec<f64> = dataset.inputs.iter().map(|row| row[j]).collect();
+            let mut inputs = dataset.inputs.clone();
+            let mut drop = 0.0;
+            for _ in 0..n_repeats {
+                column.shuffle(rng);
+                for (row, &x) in inputs.iter_mut().zip(column.iter()) {
+                    row[j] = x;
+                }
+                drop += baseline - evaluate(&inputs);
+            }
+            drop / n_repeats as f64
+        })
+        .collect()
+}
@@ -0,0 +1,172 @@
+use crate::engine::{standard_normal, Value};
+use crate::nn::{Module, MLP};
+use crate::optim::Optimizer;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+/// `ln(1 + e^x)`, written as `max(x, 0) + ln(1 + e^-|x|)` so it neither
+/// overflows nor loses precision for large `|x|`.
+fn softplus(x: Value) -> Value {
+    x.clone().relu() + ((-x.abs()).exp() + 1.0).ln()
+}
+
+/// Mean losses of one `GanTrainer::step`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GanStep {
+    pub d_loss: f64,
+    pub g_loss: f64,
+}
+
+/// Alternating GAN training (Goodfellow et al., 2014). The generator maps
+/// N(0, I) noise, as wide as its input, to samples; the discriminator
+/// outputs a single logit, so give it a linear output layer. Every `step`
+/// makes `d_steps` discriminator updates and then `g_steps` generator
+/// updates, each on a fresh batch, with the generator on the non-saturating
+/// loss `-ln D(G(z))`. Build each optimizer over its own network's
+/// parameters.
+pub struct GanTrainer<'a, OG: Optimizer, OD: Optimizer> {
+    generator: &'a MLP,
+    discriminator: &'a MLP,
+    g_optimizer: OG,
+    d_optimizer: OD,
+    batch_size: usize,
+    d_steps: usize,
+    g_steps: usize,
+    rng: StdRng,
+}
+
+impl<'a, OG: Optimizer, OD: Optimizer> GanTrainer<'a, OG, OD> {
+    /// Defaults to batches of 32 and one update of each network per step.
+    pub fn new(
+        generator: &'a MLP,
+        discriminator: &'a MLP,
+        g_optimizer: OG,
+        d_optimizer: OD,
+    ) -> Self {
+        assert_eq!(
+            generator.n_outputs(),
+            discriminator.n_inputs(),
+            "The discriminator must take the generator's samples."
+        );
+        assert_eq!(
+            discriminator.n_outputs(),
+            1,
+            "The discriminator must output a single logit."
+        );
+        GanTrainer {
+            generator,
+            discriminator,
+            g_optimizer,
+            d_optimizer,
+            batch_size: 32,
+            d_steps: 1,
+            g_steps: 1,
+            rng: StdRng::from_entropy(),
+        }
+    }
+
+    pub fn batch_size(mut self, batch_size: usize) -> Self {
+        assert!(batch_size > 0, "Batch size must be positive.");
+        self.batch_size = batch_size;
+        self
+    }
+
+    /// Discriminator updates per step; more than one is common when the
+    /// discriminator lags behind.
+    pub fn d_steps(mut self, d_steps: usize) -> Self {
+        self.d_steps = d_steps;
+        self
+    }
+
+    pub fn g_steps(mut self, g_steps: usize) -> Self {
+        self.g_steps = g_steps;
+        self
+    }
+
+    /// Draws the noise and real batches reproducibly from `seed`.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.rng = StdRng::seed_from_u64(seed);
+        self
+    }
+
+    fn noise(&mut self) -> Vec<f64> {
+        (0..self.generator.n_inputs())
+            .map(|_| standard_normal(&mut self.rng))
+            .collect()
+    }
+
+    /// `n` samples from the generator, without building a graph to keep.
+    pub fn generate(&mut self, n: usize) -> Vec<Vec<f64>> {
+        (0..n)
+            .map(|_| {
+                let z = self.noise();
+                self.generator
+                    .call(&z)
+                    .iter()
+                    .map(|v| v.get_data())
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// `-ln D(x) - ln(1 - D(G(z)))` averaged over a batch; the fakes enter
+    /// as constants so only the discriminator's graph is built.
+    fn discriminator_update(&mut self, real: &[Vec<f64>]) -> f64 {
+        let reals: Vec<&Vec<f64>> = (0..self.batch_size)
+            .map(|_| &real[self.rng.gen_range(0..real.len())])
+            .collect();
+        let fakes = self.generate(self.batch_size);
+        let mut terms = Vec::with_capacity(2 * self.batch_size);
+        for x in reals {
+            terms.push(softplus(-&self.discriminator.call(x)[0]));
+        }
+        for x in fakes.iter() {
+            terms.push(softplus(self.discriminator.call(x)[0].clone()));
+        }
+        let loss = Value::sum(&terms) * (1.0 / self.batch_size as f64);
+        let loss_value = loss.get_data();
+        self.d_optimizer.zero_grad();
+        loss.backward();
+        self.d_optimizer.step();
+        loss_value
+    }
+
+    /// `-ln D(G(z))` averaged over a batch. The discriminator's grads are
+    /// also written, but its optimizer zeroes them before its next update.
+    fn generator_update(&mut self) -> f64 {
+        let terms: Vec<Value> = (0..self.batch_size)
+            .map(|_| {
+                let z: Vec<Value> = self.noise().into_iter().map(Value::constant).collect();
+                let fake = self.generator.forward(&z);
+                softplus(-&self.discriminator.forward(&fake)[0])
+            })
+            .collect();
+        let loss = Value::mean(&terms);
+        let loss_value = loss.get_data();
+        self.g_optimizer.zero_grad();
+        loss.backward();
+        self.g_optimizer.step();
+        loss_value
+    }
+
+    /// One round of updates, with real batches drawn from `real` with
+    /// replacement. Reports each network's mean loss over its updates, NaN
+    /// when it made none.
+    pub fn step(&mut self, real: &[Vec<f64>]) -> GanStep {
+        assert!(!real.is_empty(), "Need real samples to train on.");
+        let d_loss = (0..self.d_steps)
+            .map(|_| self.discriminator_update(real))
+            .sum::<f64>()
+            / self.d_steps as f64;
+        let g_loss = (0..self.g_steps)
+            .map(|_| self.generator_update())
+            .sum::<f64>()
+            / self.g_steps as f64;
+        GanStep { d_loss, g_loss }
+    }
+
+    /// Runs `steps` steps and returns their losses.
+    pub fn fit(&mut self, real: &[Vec<f64>], steps: usize) -> Vec<GanStep> {
+        (0..steps).map(|_| self.step(real)).collect()
+    }
+}
@@ -0,0 +1,28 @@
+pub mod augment;
+pub mod calibration;
+pub mod data;
+pub mod diagnostics;
+pub mod engine;
+pub mod explain;
+pub mod gan;
+pub mod gradcheck;
+mod json;
+pub mod loss;
+pub mod lr_scheduler;
+#[cfg(feature = "monitor")]
+pub mod monitor;
+pub mod nn;
+pub mod optim;
+pub mod pinn;
+pub mod preprocess;
+pub mod regularize;
+pub mod rl;
+pub mod sampler;
+pub mod tape;
+pub mod train;
+
+pub use engine::{Op, Value};
+pub use nn::{
+    Activation, Autoencoder, Dropout, Init, LSTMCell, Layer, Module, Neuron, RNNCell,
+    RecurrentCell, Residual, Sequential, StochasticDepth, MLP, VAE,
+};
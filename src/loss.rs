@@ -0,0 +1,418 @@
+use crate::engine::Value;
+use std::ops::Range;
+
+/// How `Loss::call` combines per-sample losses into one `Value`. There is
+/// no `None`, since `call` returns a single value: use `Loss::per_sample`
+/// for the unreduced losses.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Reduction {
+    Sum,
+    Mean,
+}
+
+/// A loss over a batch of prediction vectors and their targets.
+///
+/// Implementors only define the per-sample loss; batching and reduction are
+/// shared, so the training loop can swap losses without changing shape.
+pub trait Loss {
+    fn reduction(&self) -> Reduction;
+
+    fn sample_loss(&self, pred: &[Value], target: &[f64]) -> Value;
+
+    /// Unreduced losses, one per sample, in batch order; `call` reduces
+    /// these.
+    fn per_sample(&self, preds: &[Vec<Value>], targets: &[Vec<f64>]) -> Vec<Value> {
+        assert_eq!(
+            preds.len(),
+            targets.len(),
+            "Number of predictions must match number of targets."
+        );
+        preds
+            .iter()
+            .zip(targets.iter())
+            .map(|(pred, target)| self.sample_loss(pred, target))
+            .collect()
+    }
+
+    fn call(&self, preds: &[Vec<Value>], targets: &[Vec<f64>]) -> Value {
+        let losses = self.per_sample(preds, targets);
+        match self.reduction() {
+            Reduction::Sum => Value::sum(&losses),
+            Reduction::Mean => Value::mean(&losses),
+        }
+    }
+
+    /// Named component losses over a batch, for losses made of several
+    /// parts such as `MultiTaskLoss`; `Trainer` averages them per epoch.
+    /// Defaults to none.
+    fn task_losses(&self, _preds: &[Vec<Value>], _targets: &[Vec<f64>]) -> Vec<(String, f64)> {
+        vec![]
+    }
+}
+
+pub struct MSELoss {
+    reduction: Reduction,
+}
+
+impl MSELoss {
+    pub fn new(reduction: Reduction) -> Self {
+        MSELoss { reduction }
+    }
+}
+
+impl Loss for MSELoss {
+    fn reduction(&self) -> Reduction {
+        self.reduction
+    }
+
+    /// `mse` over the sample's outputs.
+    fn sample_loss(&self, pred: &[Value], target: &[f64]) -> Value {
+        mse(pred, target)
+    }
+}
+
+pub struct MAELoss {
+    reduction: Reduction,
+}
+
+impl MAELoss {
+    pub fn new(reduction: Reduction) -> Self {
+        MAELoss { reduction }
+    }
+}
+
+impl Loss for MAELoss {
+    fn reduction(&self) -> Reduction {
+        self.reduction
+    }
+
+    fn sample_loss(&self, pred: &[Value], target: &[f64]) -> Value {
+        mae(pred, target)
+    }
+}
+
+/// `binary_cross_entropy`; expects probabilities, e.g. from a sigmoid
+/// output layer.
+pub struct BCELoss {
+    reduction: Reduction,
+}
+
+impl BCELoss {
+    pub fn new(reduction: Reduction) -> Self {
+        BCELoss { reduction }
+    }
+}
+
+impl Loss for BCELoss {
+    fn reduction(&self) -> Reduction {
+        self.reduction
+    }
+
+    fn sample_loss(&self, pred: &[Value], target: &[f64]) -> Value {
+        binary_cross_entropy(pred, target)
+    }
+}
+
+/// `hinge` against -1/+1 targets.
+pub struct HingeLoss {
+    reduction: Reduction,
+}
+
+impl HingeLoss {
+    pub fn new(reduction: Reduction) -> Self {
+        HingeLoss { reduction }
+    }
+}
+
+impl Loss for HingeLoss {
+    fn reduction(&self) -> Reduction {
+        self.reduction
+    }
+
+    fn sample_loss(&self, pred: &[Value], target: &[f64]) -> Value {
+        hinge(pred, target)
+    }
+}
+
+/// Cross-entropy of a sample's logits. Each target is either a single value
+/// holding the class index (`softmax_cross_entropy`) or a probability per
+/// output (`soft_cross_entropy`), such as the one-hot rows `Mixup` blends
+/// into soft labels.
+pub struct CrossEntropyLoss {
+    reduction: Reduction,
+}
+
+impl CrossEntropyLoss {
+    pub fn new(reduction: Reduction) -> Self {
+        CrossEntropyLoss { reduction }
+    }
+}
+
+impl Loss for CrossEntropyLoss {
+    fn reduction(&self) -> Reduction {
+        self.reduction
+    }
+
+    fn sample_loss(&self, pred: &[Value], target: &[f64]) -> Value {
+        if target.len() == pred.len() && target.len() > 1 {
+            return soft_cross_entropy(pred, target);
+        }
+        assert!(
+            target.len() == 1 && target[0] >= 0.0 && target[0].fract() == 0.0,
+            "Cross-entropy target must be a class index or one probability per output; \
+             mixing class indices (e.g. with Mixup) needs one-hot targets."
+        );
+        softmax_cross_entropy(pred, target[0] as usize)
+    }
+}
+
+struct Task {
+    name: String,
+    loss: Box<dyn Loss>,
+    outputs: Range<usize>,
+    targets: Range<usize>,
+    weight: f64,
+}
+
+/// Weighted sum of per-task losses for a model whose outputs are several
+/// heads side by side. Each `task` claims the next `n_outputs` predictions
+/// and `n_targets` target values, in the order added, and contributes its
+/// own `sample_loss` (its reduction is ignored) times its weight.
+///
+/// With `uncertainty_weighting` the weights are learned instead (Kendall et
+/// al., 2018): each task gets a parameter `s = ln σ²` and contributes
+/// `e^-s * loss + s`, so noisier tasks are down-weighted. Add `parameters`
+/// to the optimizer alongside the model's.
+pub struct MultiTaskLoss {
+    tasks: Vec<Task>,
+    reduction: Reduction,
+    log_vars: Option<Vec<Value>>,
+}
+
+impl MultiTaskLoss {
+    pub fn new(reduction: Reduction) -> Self {
+        MultiTaskLoss {
+            tasks: vec![],
+            reduction,
+            log_vars: None,
+        }
+    }
+
+    pub fn task<L: Loss + 'static>(
+        mut self,
+        name: &str,
+        loss: L,
+        n_outputs: usize,
+        n_targets: usize,
+        weight: f64,
+    ) -> Self {
+        let (outputs, targets) = self
+            .tasks
+            .last()
+            .map_or((0, 0), |t| (t.outputs.end, t.targets.end));
+        self.tasks.push(Task {
+            name: name.to_string(),
+            loss: Box::new(loss),
+            outputs: outputs..outputs + n_outputs,
+            targets: targets..targets + n_targets,
+            weight,
+        });
+        if let Some(log_vars) = self.log_vars.as_mut() {
+            log_vars.push(Value::new(0.0));
+        }
+        self
+    }
+
+    /// Learns the task weights; replaces the fixed ones.
+    pub fn uncertainty_weighting(mut self) -> Self {
+        self.log_vars = Some(self.tasks.iter().map(|_| Value::new(0.0)).collect());
+        self
+    }
+
+    /// The learned `ln σ²` per task; empty with fixed weights.
+    pub fn parameters(&self) -> Vec<Value> {
+        self.log_vars.clone().unwrap_or_default()
+    }
+
+    /// The weight each task's loss currently gets: `e^-s` when learned.
+    pub fn weights(&self) -> Vec<f64> {
+        match self.log_vars.as_ref() {
+            Some(log_vars) => log_vars.iter().map(|s| (-s.get_data()).exp()).collect(),
+            None => self.tasks.iter().map(|t| t.weight).collect(),
+        }
+    }
+
+    fn task_loss(&self, task: &Task, pred: &[Value], target: &[f64]) -> Value {
+        let (n_outputs, n_targets) = self
+            .tasks
+            .last()
+            .map_or((0, 0), |t| (t.outputs.end, t.targets.end));
+        assert!(
+            pred.len() == n_outputs && target.len() == n_targets,
+            "Tasks cover {} outputs and {} targets, got {} and {}.",
+            n_outputs,
+            n_targets,
+            pred.len(),
+            target.len()
+        );
+        task.loss
+            .sample_loss(&pred[task.outputs.clone()], &target[task.targets.clone()])
+    }
+}
+
+impl Loss for MultiTaskLoss {
+    fn reduction(&self) -> Reduction {
+        self.reduction
+    }
+
+    fn sample_loss(&self, pred: &[Value], target: &[f64]) -> Value {
+        assert!(!self.tasks.is_empty(), "A multi-task loss needs a task.");
+        let terms: Vec<Value> = self
+            .tasks
+            .iter()
+            .enumerate()
+            .map(|(i, task)| {
+                let loss = self.task_loss(task, pred, target);
+                match self.log_vars.as_ref() {
+                    Some(log_vars) => &(-&log_vars[i]).exp() * &loss + &log_vars[i],
+                    None => loss * task.weight,
+                }
+            })
+            .collect();
+        Value::sum(&terms)
+    }
+
+    /// Each task's unweighted loss, averaged over the batch.
+    fn task_losses(&self, preds: &[Vec<Value>], targets: &[Vec<f64>]) -> Vec<(String, f64)> {
+        self.tasks
+            .iter()
+            .map(|task| {
+                let total: f64 = preds
+                    .iter()
+                    .zip(targets.iter())
+                    .map(|(pred, target)| self.task_loss(task, pred, target).get_data())
+                    .sum();
+                (task.name.clone(), total / preds.len().max(1) as f64)
+            })
+            .collect()
+    }
+}
+
+// Guards the logs in the cross-entropy losses against probabilities of
+// exactly 0 or 1.
+const LOG_EPS: f64 = 1e-12;
+
+fn mean(values: Vec<Value>) -> Value {
+    assert!(
+        !values.is_empty(),
+        "Cannot take the mean of an empty batch."
+    );
+    Value::mean(&values)
+}
+
+fn check_batch(pred: &[Value], target: &[f64]) {
+    assert_eq!(
+        pred.len(),
+        target.len(),
+        "Number of predictions must match number of targets."
+    );
+}
+
+/// Mean squared error.
+pub fn mse(pred: &[Value], target: &[f64]) -> Value {
+    check_batch(pred, target);
+    mean(
+        pred.iter()
+            .zip(target.iter())
+            .map(|(y, &t)| (y - t).powf(2.0))
+            .collect(),
+    )
+}
+
+/// Mean absolute error.
+pub fn mae(pred: &[Value], target: &[f64]) -> Value {
+    check_batch(pred, target);
+    mean(
+        pred.iter()
+            .zip(target.iter())
+            .map(|(y, &t)| (y - t).abs())
+            .collect(),
+    )
+}
+
+/// Binary cross-entropy of probabilities `pred` (e.g. sigmoid outputs)
+/// against 0/1 targets.
+pub fn binary_cross_entropy(pred: &[Value], target: &[f64]) -> Value {
+    check_batch(pred, target);
+    mean(
+        pred.iter()
+            .zip(target.iter())
+            .map(|(p, &t)| {
+                let pos = (p + LOG_EPS).ln() * t;
+                let neg = (1.0 - p + LOG_EPS).ln() * (1.0 - t);
+                -(pos + neg)
+            })
+            .collect(),
+    )
+}
+
+/// Hinge loss `max(0, 1 - t * y)` for raw scores against -1/+1 targets.
+pub fn hinge(pred: &[Value], target: &[f64]) -> Value {
+    check_batch(pred, target);
+    mean(
+        pred.iter()
+            .zip(target.iter())
+            .map(|(y, &t)| (1.0 - y * t).relu())
+            .collect(),
+    )
+}
+
+/// `-ln(softmax(logits)[class])` for one sample, computed as
+/// `logsumexp(z) - z[class]` with the max logit subtracted for stability.
+/// Cheaper and more accurate than taking the log of `Value::softmax`, which
+/// underflows for confidently wrong predictions.
+pub fn softmax_cross_entropy(logits: &[Value], class: usize) -> Value {
+    assert!(class < logits.len(), "Class index out of range.");
+    let max = logits
+        .iter()
+        .map(|x| x.get_data())
+        .fold(f64::NEG_INFINITY, f64::max);
+    let exps: Vec<Value> = logits.iter().map(|x| (x - max).exp()).collect();
+    Value::sum(&exps).ln() + max - &logits[class]
+}
+
+/// `-sum_i p_i ln(softmax(logits)[i])` against a target distribution
+/// `probs`, e.g. a soft label, computed as `sum(p) logsumexp(z) - sum_i p_i
+/// z_i`. Equals `softmax_cross_entropy` for a one-hot `probs`.
+pub fn soft_cross_entropy(logits: &[Value], probs: &[f64]) -> Value {
+    assert_eq!(
+        logits.len(),
+        probs.len(),
+        "Target distribution must have one probability per logit."
+    );
+    let max = logits
+        .iter()
+        .map(|x| x.get_data())
+        .fold(f64::NEG_INFINITY, f64::max);
+    let exps: Vec<Value> = logits.iter().map(|x| (x - max).exp()).collect();
+    let log_sum = Value::sum(&exps).ln() + max;
+    let weighted: Vec<Value> = logits.iter().zip(probs).map(|(z, &p)| z * p).collect();
+    log_sum * probs.iter().sum::<f64>() - Value::sum(&weighted)
+}
+
+/// Multi-class cross-entropy of unnormalized `logits` (one vector per
+/// sample) against class indices: the mean of `softmax_cross_entropy`.
+pub fn cross_entropy(logits: &[Vec<Value>], classes: &[usize]) -> Value {
+    assert_eq!(
+        logits.len(),
+        classes.len(),
+        "Number of predictions must match number of targets."
+    );
+    mean(
+        logits
+            .iter()
+            .zip(classes.iter())
+            .map(|(z, &class)| softmax_cross_entropy(z, class))
+            .collect(),
+    )
+}
@@ -0,0 +1,109 @@
+use crate::optim::Optimizer;
+use std::f64;
+
+/// Learning rate as a function of the epoch, relative to the optimizer's
+/// initial rate `base`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Schedule {
+    /// `base * gamma^(epoch / step_size)`: decays by `gamma` every
+    /// `step_size` epochs.
+    StepLR { step_size: usize, gamma: f64 },
+    /// `base * gamma^epoch`.
+    ExponentialLR { gamma: f64 },
+    /// Follows half a cosine from `base` down to `eta_min` over `t_max`
+    /// epochs (Loshchilov & Hutter, 2017), then stays at `eta_min`.
+    CosineAnnealing { t_max: usize, eta_min: f64 },
+    /// Cosine annealing that restarts at `base` every `t_0` epochs (SGDR,
+    /// Loshchilov & Hutter, 2017), reaching its lowest rate in the last
+    /// epoch of each cycle.
+    CosineWarmRestarts { t_0: usize, eta_min: f64 },
+}
+
+impl Schedule {
+    pub fn lr(&self, base: f64, epoch: usize) -> f64 {
+        match *self {
+            Schedule::StepLR { step_size, gamma } => {
+                assert!(step_size > 0, "Step size must be positive.");
+                base * gamma.powi((epoch / step_size) as i32)
+            }
+            Schedule::ExponentialLR { gamma } => base * gamma.powi(epoch as i32),
+            Schedule::CosineAnnealing { t_max, eta_min } => {
+                assert!(t_max > 0, "t_max must be positive.");
+                let progress = epoch.min(t_max) as f64 / t_max as f64;
+                eta_min + 0.5 * (base - eta_min) * (1.0 + (f64::consts::PI * progress).cos())
+            }
+            Schedule::CosineWarmRestarts { t_0, eta_min } => {
+                assert!(t_0 > 0, "t_0 must be positive.");
+                let progress = (epoch % t_0) as f64 / t_0 as f64;
+                eta_min + 0.5 * (base - eta_min) * (1.0 + (f64::consts::PI * progress).cos())
+            }
+        }
+    }
+}
+
+/// Wraps an optimizer and sets its learning rate from a `Schedule`. Use it
+/// in place of the optimizer and call `step_epoch` once at the end of every
+/// epoch; `Trainer` does this itself.
+pub struct LrScheduler<O: Optimizer> {
+    optimizer: O,
+    schedule: Schedule,
+    base_lr: f64,
+    epoch: usize,
+}
+
+impl<O: Optimizer> LrScheduler<O> {
+    /// The optimizer's current learning rate is the schedule's base.
+    pub fn new(optimizer: O, schedule: Schedule) -> Self {
+        let base_lr = optimizer.lr();
+        LrScheduler {
+            optimizer,
+            schedule,
+            base_lr,
+            epoch: 0,
+        }
+    }
+
+    /// Advances to the next epoch and updates the learning rate.
+    pub fn step_epoch(&mut self) {
+        self.epoch += 1;
+        let lr = self.schedule.lr(self.base_lr, self.epoch);
+        self.optimizer.set_lr(lr);
+    }
+
+    pub fn epoch(&self) -> usize {
+        self.epoch
+    }
+
+    pub fn optimizer(&self) -> &O {
+        &self.optimizer
+    }
+
+    pub fn into_inner(self) -> O {
+        self.optimizer
+    }
+}
+
+impl<O: Optimizer> Optimizer for LrScheduler<O> {
+    fn step(&mut self) {
+        self.optimizer.step();
+    }
+
+    fn zero_grad(&self) {
+        self.optimizer.zero_grad();
+    }
+
+    fn lr(&self) -> f64 {
+        self.optimizer.lr()
+    }
+
+    /// Sets the schedule's base rate; the optimizer's rate becomes the
+    /// scheduled value for the current epoch.
+    fn set_lr(&mut self, lr: f64) {
+        self.base_lr = lr;
+        self.optimizer.set_lr(self.schedule.lr(lr, self.epoch));
+    }
+
+    fn end_epoch(&mut self) {
+        self.step_epoch();
+    }
+}
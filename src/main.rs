@@ -1,305 +1,817 @@
-use rand::distributions::Uniform;
-use rand::Rng;
-use std::cell::RefCell;
-use std::f64;
-use std::fmt;
-use std::rc::Rc;
-use std::vec;
-
-#[derive(Debug, Clone, PartialEq)]
-enum Op {
-    Add,
-    Mul,
-    Tanh,
-    Exp,
-    Pow,
-}
-
-#[derive(Debug, Clone, PartialEq)]
-struct _Value {
-    data: f64,
-    _prev: Option<(Value, Value)>,
-    _op: Option<Op>,
-    grad: f64,
-}
-
-#[derive(Debug, Clone, PartialEq)]
-struct Value(Rc<RefCell<_Value>>);
+use micrograd::data::{load_csv, parse_json_inputs, Dataset};
+use micrograd::loss::{BCELoss, CrossEntropyLoss, HingeLoss, Loss, MAELoss, MSELoss, Reduction};
+use micrograd::lr_scheduler::{LrScheduler, Schedule};
+use micrograd::nn::{Activation, Module, MLP};
+use micrograd::optim::{Adam, Optimizer, SGD};
+use micrograd::preprocess::{CategoricalEncoder, Pipeline};
+use micrograd::train::{PrintLoss, RunManifest, Trainer};
+use micrograd::Value;
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::process::{Child, Command};
+use std::thread;
+use std::time::Instant;
+
+const USAGE: &str = "usage: micrograd predict --model <file> --input <csv> --output <csv>
+                        [--encoder <file> --categorical <col,...>] [--pipeline <file>]
+                        [--delimiter <char>] [--probabilities]
+       micrograd serve --model <file> [--port <n>] [--host <addr>]
+       micrograd experiment <dir | config> [--out <dir>] [--jobs <n>]
+
+Without arguments, runs the training demo.";
 
-impl Value {
-    fn new(data: f64) -> Self {
-        Self::new_ext(data, None, None)
-    }
-
-    fn new_ext(data: f64, _children: Option<(Value, Value)>, _op: Option<Op>) -> Self {
-        Value(Rc::new(RefCell::new(_Value {
-            data,
-            _prev: _children,
-            _op,
-            grad: 0.0,
-        })))
-    }
-
-    pub fn update_data(&self, new_data: f64) {
-        let mut inner = self.0.borrow_mut();
-        inner.data = new_data;
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        None => demo(),
+        Some("predict") => {
+            if let Err(e) = predict(&args[1..]) {
+                eprintln!("error: {}", e);
+                process::exit(1);
+            }
+        }
+        Some("serve") => {
+            if let Err(e) = serve(&args[1..]) {
+                eprintln!("error: {}", e);
+                process::exit(1);
+            }
+        }
+        Some("experiment") => {
+            if let Err(e) = experiment(&args[1..]) {
+                eprintln!("error: {}", e);
+                process::exit(1);
+            }
+        }
+        Some(_) => {
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        }
     }
+}
 
-    pub fn update_grad(&self, new_grad: f64) {
-        let mut inner = self.0.borrow_mut();
-        inner.grad = new_grad;
-    }
+fn invalid_input(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg)
+}
 
-    pub fn get_data(&self) -> f64 {
-        self.0.borrow().data
+/// Streams the input CSV through the model, copying every row to the output
+/// with the model's outputs appended as `prediction` columns (and, with
+/// `--probabilities`, their sigmoid for one output or softmax for several as
+/// `probability` columns). The header names the columns; those listed in
+/// `--categorical` go through the saved encoder, the rest are parsed as
+/// numbers, with empty fields as NaN. Features are the numeric columns in
+/// file order followed by the encoded categorical ones; a saved `--pipeline`
+/// then fills in and standardizes them before they reach the model.
+fn predict(args: &[String]) -> io::Result<()> {
+    let mut model = None;
+    let mut input = None;
+    let mut output = None;
+    let mut encoder = None;
+    let mut pipeline = None;
+    let mut categorical: Vec<String> = vec![];
+    let mut delimiter = ',';
+    let mut probabilities = false;
+    let mut args = args.iter();
+    while let Some(flag) = args.next() {
+        if flag == "--probabilities" {
+            probabilities = true;
+            continue;
+        }
+        let value = args
+            .next()
+            .ok_or_else(|| invalid_input(format!("{} needs a value\n{}", flag, USAGE)))?;
+        match flag.as_str() {
+            "--model" => model = Some(value),
+            "--input" => input = Some(value),
+            "--output" => output = Some(value),
+            "--encoder" => encoder = Some(value),
+            "--pipeline" => pipeline = Some(value),
+            "--categorical" => categorical = value.split(',').map(|c| c.to_string()).collect(),
+            "--delimiter" => {
+                let mut chars = value.chars();
+                delimiter = match (chars.next(), chars.next()) {
+                    (Some(c), None) => c,
+                    _ => return Err(invalid_input("delimiter must be one character".to_string())),
+                };
+            }
+            _ => return Err(invalid_input(format!("unknown option {}\n{}", flag, USAGE))),
+        }
     }
-
-    pub fn get_prev(&self) -> Option<(Value, Value)> {
-        self.0.borrow()._prev.clone()
+    let (model, input, output) = match (model, input, output) {
+        (Some(m), Some(i), Some(o)) => (m, i, o),
+        _ => return Err(invalid_input(USAGE.to_string())),
+    };
+    let model = MLP::load(model)?;
+    let encoder = encoder.map(CategoricalEncoder::load).transpose()?;
+    if encoder.is_some() == categorical.is_empty() {
+        return Err(invalid_input(
+            "--encoder and --categorical must be given together".to_string(),
+        ));
     }
-
-    pub fn get_op(&self) -> Option<Op> {
-        self.0.borrow()._op.clone()
+    let pipeline = pipeline.map(Pipeline::load).transpose()?;
+
+    let mut lines = BufReader::new(File::open(input)?).lines();
+    let header = lines
+        .next()
+        .ok_or_else(|| invalid_input("input CSV is empty".to_string()))??;
+    let names: Vec<&str> = header.split(delimiter).map(|h| h.trim()).collect();
+    let mut is_categorical = vec![false; names.len()];
+    for column in categorical.iter() {
+        let j = names
+            .iter()
+            .position(|name| name == column)
+            .ok_or_else(|| invalid_input(format!("no column named {:?}", column)))?;
+        is_categorical[j] = true;
     }
 
-    pub fn get_grad(&self) -> f64 {
-        self.0.borrow().grad
+    let n_features = is_categorical.iter().filter(|&&c| !c).count()
+        + encoder.as_ref().map_or(0, |e| e.n_outputs());
+    if let Some(pipeline) = pipeline.as_ref() {
+        if n_features != pipeline.n_features() {
+            return Err(invalid_input(format!(
+                "line 1: the columns give {} features but the pipeline takes {}",
+                n_features,
+                pipeline.n_features()
+            )));
+        }
     }
-
-    fn tanh(self) -> Self {
-        Self::new_ext(
-            self.get_data().tanh(),
-            Some((self.clone(), self.clone())),
-            Some(Op::Tanh),
-        )
+    if n_features != model.n_inputs() {
+        return Err(invalid_input(format!(
+            "line 1: the columns give {} features but the model takes {}",
+            n_features,
+            model.n_inputs()
+        )));
     }
 
-    fn exp(self) -> Self {
-        Self::new_ext(
-            self.get_data().exp(),
-            Some((self.clone(), self.clone())),
-            Some(Op::Exp),
-        )
-    }
+    let n_outputs = model.n_outputs();
+    let mut out = BufWriter::new(File::create(output)?);
+    let write_rows = || -> io::Result<usize> {
+        write!(out, "{}", header)?;
+        for j in 0..n_outputs {
+            write!(
+                out,
+                "{}{}",
+                delimiter,
+                column_name("prediction", j, n_outputs)
+            )?;
+        }
+        if probabilities {
+            for j in 0..n_outputs {
+                write!(
+                    out,
+                    "{}{}",
+                    delimiter,
+                    column_name("probability", j, n_outputs)
+                )?;
+            }
+        }
+        writeln!(out)?;
 
-    fn add(self, other: Self) -> Self {
-        Self::new_ext(
-            self.get_data() + other.get_data(),
-            Some((self.clone(), other.clone())),
-            Some(Op::Add),
-        )
-    }
+        let mut rows = 0;
+        for (line_no, line) in lines.enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let fields: Vec<&str> = line.split(delimiter).map(|f| f.trim()).collect();
+            if fields.len() != names.len() {
+                return Err(invalid_input(format!(
+                    "line {}: expected {} fields, got {}",
+                    line_no + 2,
+                    names.len(),
+                    fields.len()
+                )));
+            }
+            let mut features = vec![];
+            let mut categories = vec![];
+            for (field, &is_cat) in fields.iter().zip(is_categorical.iter()) {
+                if is_cat {
+                    categories.push(field.to_string());
+                } else if field.is_empty() {
+                    features.push(f64::NAN);
+                } else {
+                    features.push(field.parse().map_err(|_| {
+                        invalid_input(format!("line {}: invalid number {:?}", line_no + 2, field))
+                    })?);
+                }
+            }
+            if let Some(encoder) = encoder.as_ref() {
+                features.extend(encoder.transform_row(&categories)?);
+            }
+            if let Some(pipeline) = pipeline.as_ref() {
+                features = pipeline.transform_row(&features);
+            }
+            let outputs = model.call(&features);
+            write!(out, "{}", line)?;
+            for y in outputs.iter() {
+                write!(out, "{}{}", delimiter, y.get_data())?;
+            }
+            if probabilities {
+                let probs = if outputs.len() == 1 {
+                    vec![outputs[0].clone().sigmoid()]
+                } else {
+                    Value::softmax(&outputs)
+                };
+                for p in probs {
+                    write!(out, "{}{}", delimiter, p.get_data())?;
+                }
+            }
+            writeln!(out)?;
+            rows += 1;
+        }
+        out.flush()?;
+        Ok(rows)
+    };
+    let rows = match write_rows() {
+        Ok(rows) => rows,
+        Err(e) => {
+            // Don't leave a half-written file that looks like valid output.
+            let _ = fs::remove_file(output);
+            return Err(e);
+        }
+    };
+    eprintln!("wrote {} predictions", rows);
+    Ok(())
+}
 
-    fn mul(self, other: Self) -> Self {
-        Self::new_ext(
-            self.get_data() * other.get_data(),
-            Some((self.clone(), other.clone())),
-            Some(Op::Mul),
-        )
+fn column_name(base: &str, j: usize, n: usize) -> String {
+    if n == 1 {
+        base.to_string()
+    } else {
+        format!("{}_{}", base, j)
     }
+}
 
-    fn neg(self) -> Self {
-        self.mul(Value::new(-1.0))
+/// Serves the model over HTTP, one connection at a time. `POST /predict`
+/// takes `{"inputs": [[x, ...], ...]}` and answers `{"outputs": [[y, ...],
+/// ...]}`, computed on the graph-free `predict_batch_parallel` path;
+/// `GET /` describes the model. Non-finite outputs are written as `null`.
+/// Binds to 127.0.0.1:8080 unless told otherwise.
+fn serve(args: &[String]) -> io::Result<()> {
+    let mut model = None;
+    let mut port = 8080u16;
+    let mut host = "127.0.0.1".to_string();
+    let mut args = args.iter();
+    while let Some(flag) = args.next() {
+        let value = args
+            .next()
+            .ok_or_else(|| invalid_input(format!("{} needs a value\n{}", flag, USAGE)))?;
+        match flag.as_str() {
+            "--model" => model = Some(value),
+            "--port" => {
+                port = value
+                    .parse()
+                    .map_err(|_| invalid_input(format!("invalid port {:?}", value)))?
+            }
+            "--host" => host = value.clone(),
+            _ => return Err(invalid_input(format!("unknown option {}\n{}", flag, USAGE))),
+        }
     }
-
-    fn sub(self, other: Self) -> Self {
-        self.add(other.neg())
+    let model = model.ok_or_else(|| invalid_input(USAGE.to_string()))?;
+    let model = MLP::load(model)?;
+    let n_threads = thread::available_parallelism().map_or(1, |n| n.get());
+
+    let listener = TcpListener::bind((host.as_str(), port))?;
+    eprintln!("serving on http://{}", listener.local_addr()?);
+    for stream in listener.incoming() {
+        // A bad client shouldn't take the server down.
+        if let Err(e) = stream.and_then(|s| handle(s, &model, n_threads)) {
+            eprintln!("connection error: {}", e);
+        }
     }
+    Ok(())
+}
 
-    fn pow(self, other: Self) -> Self {
-        Self::new_ext(
-            self.get_data().powf(other.get_data()),
-            Some((self.clone(), other.clone())),
-            Some(Op::Pow),
-        )
-    }
+const MAX_BODY: usize = 16 << 20;
 
-    fn div(self, other: Self) -> Self {
-        self.mul(other.pow(Value::new(-1.0)))
-    }
+fn handle(mut stream: TcpStream, model: &MLP, n_threads: usize) -> io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut parts = request_line.split_whitespace();
+    let (method, path) = (parts.next().unwrap_or(""), parts.next().unwrap_or(""));
 
-    fn _backward(self) {
-        if let Some(ref _prev) = self.get_prev() {
-            let (ref a, ref b) = *_prev;
-            // println!("{:?}", self.get_op());
-            match self.get_op() {
-                Some(Op::Add) => {
-                    a.update_grad(a.get_grad() + self.get_grad());
-                    b.update_grad(b.get_grad() + self.get_grad());
-                    // println!("a.grad: {}, b.grad: {}", a.get_grad(), b.get_grad());
-                }
-                Some(Op::Mul) => {
-                    a.update_grad(a.get_grad() + b.get_data() * self.get_grad());
-                    b.update_grad(b.get_grad() + a.get_data() * self.get_grad());
-                    // println!("a.grad: {}, b.grad: {}", a.get_grad(), b.get_grad());
-                }
-                Some(Op::Tanh) => {
-                    let t = a.get_data().tanh();
-                    a.update_grad((1.0 - t.powf(2.0)) * self.get_grad());
-                    // println!("a.grad: {}", a.get_grad());
-                }
-                Some(Op::Exp) => {
-                    a.update_grad(a.get_data().exp() * self.get_grad());
-                    // println!("a.grad: {}", a.get_grad());
-                }
-                Some(Op::Pow) => {
-                    a.update_grad(
-                        b.get_data() * a.get_data().powf(b.get_data() - 1.0) * self.get_grad(),
-                    );
-                    // println!("a.grad: {}", a.get_grad());
-                }
-                None => {}
+    let mut content_length = 0;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().unwrap_or(0);
             }
         }
     }
 
-    fn backward(self) {
-        let mut topo: Vec<Value> = vec![];
-        let mut visited: Vec<Value> = vec![];
-        fn build_topo(v: &Value, topo: &mut Vec<Value>, visited: &mut Vec<Value>) {
-            if !visited.iter().any(|x| x == v) {
-                visited.push(v.clone());
-                if let Some(ref _prev) = v.get_prev() {
-                    build_topo(&_prev.0, topo, visited);
-                    build_topo(&_prev.1, topo, visited);
-                }
-                topo.push(v.clone());
+    let (status, body) = match (method, path) {
+        ("GET", "/") => (
+            "200 OK",
+            format!(
+                "{{\"n_inputs\": {}, \"n_outputs\": {}}}",
+                model.n_inputs(),
+                model.n_outputs()
+            ),
+        ),
+        ("POST", "/predict") if content_length > MAX_BODY => (
+            "413 Payload Too Large",
+            error_json("request body too large"),
+        ),
+        ("POST", "/predict") => {
+            let mut body = vec![0; content_length];
+            reader.read_exact(&mut body)?;
+            match predict_json(&body, model, n_threads) {
+                Ok(outputs) => ("200 OK", outputs),
+                Err(e) => ("400 Bad Request", error_json(&e.to_string())),
             }
         }
-        build_topo(&self, &mut topo, &mut visited);
-
-        self.update_grad(1.0);
-        topo.reverse();
-        for node in topo {
-            node._backward();
-        }
-    }
+        (_, "/") | (_, "/predict") => ("405 Method Not Allowed", error_json("method not allowed")),
+        _ => ("404 Not Found", error_json("not found")),
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )?;
+    stream.flush()
 }
 
-impl fmt::Display for Value {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        if self.get_data().fract() == 0.0 {
-            write!(f, "Value(data={:.1})", self.get_data())
-        } else {
-            write!(f, "Value(data={})", self.get_data())
+fn predict_json(body: &[u8], model: &MLP, n_threads: usize) -> io::Result<String> {
+    let text =
+        std::str::from_utf8(body).map_err(|_| invalid_input("body is not UTF-8".to_string()))?;
+    let inputs = parse_json_inputs(text)?;
+    if let Some(row) = inputs.first() {
+        if row.len() != model.n_inputs() {
+            return Err(invalid_input(format!(
+                "inputs have {} features but the model takes {}",
+                row.len(),
+                model.n_inputs()
+            )));
         }
     }
+    let rows: Vec<String> = model
+        .predict_batch_parallel(&inputs, n_threads)
+        .iter()
+        .map(|row| {
+            let values: Vec<String> = row
+                .iter()
+                .map(|y| {
+                    if y.is_finite() {
+                        y.to_string()
+                    } else {
+                        "null".to_string()
+                    }
+                })
+                .collect();
+            format!("[{}]", values.join(", "))
+        })
+        .collect();
+    Ok(format!("{{\"outputs\": [{}]}}", rows.join(", ")))
 }
 
-struct Neuron {
-    w: Vec<Value>,
-    b: Value,
+fn error_json(msg: &str) -> String {
+    let escaped: String = msg
+        .chars()
+        .flat_map(|c| match c {
+            '"' => vec!['\\', '"'],
+            '\\' => vec!['\\', '\\'],
+            '\n' => vec!['\\', 'n'],
+            c if c.is_control() => vec![' '],
+            c => vec![c],
+        })
+        .collect();
+    format!("{{\"error\": \"{}\"}}", escaped)
 }
 
-impl Neuron {
-    fn new(nin: u16) -> Self {
-        let mut rng = rand::thread_rng();
-        let range = Uniform::new(-1.0, 1.0);
-
-        let w: Vec<Value> = (0..nin).map(|_| Value::new(rng.sample(&range))).collect();
-
-        let b = Value::new(rng.sample(&range));
+/// One run of `micrograd experiment`, read from `key = value` lines; `#`
+/// starts a comment. `data` is a CSV path relative to the config file and
+/// is the only required key:
+///
+/// ```text
+/// data = moons.csv
+/// header = true           # default false
+/// targets = 2             # target column indices, default the last column
+/// hidden = 16,16          # hidden layer widths, default 16
+/// activation = relu       # hidden activation, default tanh
+/// output_activation = linear
+/// outputs = 1             # default the number of target columns
+/// loss = mse              # mse, mae, bce, hinge or cross_entropy
+/// optimizer = adam        # adam or sgd
+/// lr = 0.01
+/// epochs = 100
+/// batch_size = 32         # default full batch
+/// seed = 0
+/// ```
+struct RunConfig {
+    data: PathBuf,
+    header: bool,
+    targets: Option<Vec<usize>>,
+    hidden: Vec<u16>,
+    activation: Activation,
+    output_activation: Activation,
+    outputs: Option<u16>,
+    loss: String,
+    optimizer: String,
+    lr: f64,
+    epochs: usize,
+    batch_size: Option<usize>,
+    seed: u64,
+}
 
-        Neuron { w, b }
+impl RunConfig {
+    fn load(path: &Path) -> io::Result<Self> {
+        let text = fs::read_to_string(path)?;
+        let mut config = RunConfig {
+            data: PathBuf::new(),
+            header: false,
+            targets: None,
+            hidden: vec![16],
+            activation: Activation::Tanh,
+            output_activation: Activation::Linear,
+            outputs: None,
+            loss: "mse".to_string(),
+            optimizer: "adam".to_string(),
+            lr: 0.01,
+            epochs: 100,
+            batch_size: None,
+            seed: 0,
+        };
+        let mut has_data = false;
+        for (i, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let bad = |msg: String| invalid_input(format!("{}:{}: {}", path.display(), i + 1, msg));
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| bad("expected key = value".to_string()))?;
+            let (key, value) = (key.trim(), value.trim());
+            let number = || bad(format!("invalid value {:?} for {}", value, key));
+            let list = || -> io::Result<Vec<usize>> {
+                value
+                    .split(',')
+                    .map(|v| v.trim().parse().map_err(|_| number()))
+                    .collect()
+            };
+            let activation = || Activation::from_name(value).ok_or_else(number);
+            match key {
+                "data" => {
+                    config.data = path.parent().unwrap_or(Path::new("")).join(value);
+                    has_data = true;
+                }
+                "header" => config.header = value.parse().map_err(|_| number())?,
+                "targets" => config.targets = Some(list()?),
+                "hidden" => {
+                    config.hidden = list()?
+                        .into_iter()
+                        .map(|w| u16::try_from(w).map_err(|_| number()))
+                        .collect::<io::Result<_>>()?
+                }
+                "activation" => config.activation = activation()?,
+                "output_activation" => config.output_activation = activation()?,
+                "outputs" => config.outputs = Some(value.parse().map_err(|_| number())?),
+                "loss" => match value {
+                    "mse" | "mae" | "bce" | "hinge" | "cross_entropy" => {
+                        config.loss = value.to_string()
+                    }
+                    _ => return Err(number()),
+                },
+                "optimizer" => match value {
+                    "adam" | "sgd" => config.optimizer = value.to_string(),
+                    _ => return Err(number()),
+                },
+                "lr" => config.lr = value.parse().map_err(|_| number())?,
+                "epochs" => config.epochs = value.parse().map_err(|_| number())?,
+                "batch_size" => match value.parse() {
+                    Ok(n) if n > 0 => config.batch_size = Some(n),
+                    _ => return Err(number()),
+                },
+                "seed" => config.seed = value.parse().map_err(|_| number())?,
+                _ => return Err(bad(format!("unknown key {:?}", key))),
+            }
+        }
+        if !has_data {
+            return Err(invalid_input(format!(
+                "{}: no data file given",
+                path.display()
+            )));
+        }
+        Ok(config)
     }
+}
 
-    pub fn call(&self, inputs: Vec<Value>) -> Value {
-        assert_eq!(
-            self.w.len(),
-            inputs.len(),
-            "Input size must match number of weights."
-        );
-
-        let wx = self
-            .w
-            .iter()
-            .zip(inputs.iter())
-            .map(|(weight, &ref input)| weight.clone().mul(input.clone()));
-
-        let act = wx
-            .into_iter()
-            .fold(Value::new(0.0), |acc, x| acc.add(x))
-            .add(self.b.clone());
-        act.tanh()
+/// Runs every `*.cfg` file in a directory, or a single config, writing each
+/// run's model, manifest and metrics to `<out>/<config name>/` (`<out>`
+/// defaults to `runs` beside the configs). With `--jobs` above 1 the runs
+/// go to that many child processes at once. A directory run ends with a
+/// table of every run, best final loss first, also saved as
+/// `<out>/summary.tsv`.
+fn experiment(args: &[String]) -> io::Result<()> {
+    let mut target = None;
+    let mut out = None;
+    let mut jobs = 1;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if !arg.starts_with("--") {
+            target = Some(PathBuf::from(arg));
+            continue;
+        }
+        let value = args
+            .next()
+            .ok_or_else(|| invalid_input(format!("{} needs a value\n{}", arg, USAGE)))?;
+        match arg.as_str() {
+            "--out" => out = Some(PathBuf::from(value)),
+            "--jobs" => match value.parse() {
+                Ok(n) if n > 0 => jobs = n,
+                _ => return Err(invalid_input(format!("invalid job count {:?}", value))),
+            },
+            _ => return Err(invalid_input(format!("unknown option {}\n{}", arg, USAGE))),
+        }
     }
-
-    fn parameters(&self) -> Vec<Value> {
-        let mut params: Vec<Value> = self.w.clone();
-        params.push(self.b.clone());
-        params
+    let target = target.ok_or_else(|| invalid_input(USAGE.to_string()))?;
+    if target.is_file() {
+        let out = out.unwrap_or_else(|| target.with_file_name("runs"));
+        return run_config(&target, &out);
     }
-}
 
-struct Layer {
-    neurons: Vec<Neuron>,
+    let mut configs: Vec<PathBuf> = fs::read_dir(&target)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<io::Result<_>>()?;
+    configs.retain(|p| p.extension().is_some_and(|e| e == "cfg"));
+    configs.sort();
+    if configs.is_empty() {
+        return Err(invalid_input(format!(
+            "no .cfg files in {}",
+            target.display()
+        )));
+    }
+    let out = out.unwrap_or_else(|| target.join("runs"));
+    let mut failed = vec![];
+    if jobs == 1 {
+        for config in configs.iter() {
+            if let Err(e) = run_config(config, &out) {
+                // Config errors already name the file.
+                eprintln!("error: {}", e);
+                failed.push(config.clone());
+            }
+        }
+    } else {
+        let exe = env::current_exe()?;
+        let mut running: Vec<(PathBuf, Child)> = vec![];
+        for config in configs.iter() {
+            if running.len() == jobs {
+                let (config, mut child) = running.remove(0);
+                if !child.wait()?.success() {
+                    failed.push(config);
+                }
+            }
+            let child = Command::new(&exe)
+                .arg("experiment")
+                .arg(config)
+                .arg("--out")
+                .arg(&out)
+                .spawn()?;
+            running.push((config.clone(), child));
+        }
+        for (config, mut child) in running {
+            if !child.wait()?.success() {
+                failed.push(config);
+            }
+        }
+    }
+    write_summary(&configs, &failed, &out)
 }
 
-impl Layer {
-    fn new(nin: u16, nout: u16) -> Self {
-        let neurons: Vec<Neuron> = (0..nout).map(|_| Neuron::new(nin)).collect();
-        Layer { neurons }
+/// Trains one config and writes `model.txt`, `manifest.json` and
+/// `metrics.txt` to its run folder.
+fn run_config(config_path: &Path, out: &Path) -> io::Result<()> {
+    let config = RunConfig::load(config_path)?;
+    let name = run_name(config_path);
+    let in_data =
+        |e: io::Error| io::Error::new(e.kind(), format!("{}: {}", config.data.display(), e));
+    let data = match config.targets.clone() {
+        Some(targets) => load_csv(&config.data, config.header, targets).map_err(in_data)?,
+        None => {
+            // Default to the last column, read from the first line.
+            let first = fs::read_to_string(&config.data)
+                .map_err(in_data)?
+                .lines()
+                .next()
+                .map_or(0, |line| line.split(',').count());
+            if first < 2 {
+                return Err(invalid_input(format!(
+                    "{}: need at least two columns",
+                    config.data.display()
+                )));
+            }
+            load_csv(&config.data, config.header, vec![first - 1]).map_err(in_data)?
+        }
+    };
+    if data.is_empty() {
+        return Err(invalid_input(format!("{}: no rows", config.data.display())));
     }
-
-    pub fn call(&self, inputs: Vec<Value>) -> Vec<Value> {
-        self.neurons
-            .iter()
-            .map(|neuron| neuron.call(inputs.clone()))
-            .collect()
+    let n_outputs = match (config.outputs, config.loss.as_str()) {
+        (Some(n), _) => n,
+        (None, "cross_entropy") => {
+            return Err(invalid_input(format!(
+                "{}: cross_entropy needs outputs = <number of classes>",
+                config_path.display()
+            )))
+        }
+        (None, _) => data.targets[0].len() as u16,
+    };
+    let mut builder = MLP::builder().input(data.n_features() as u16);
+    for &width in config.hidden.iter() {
+        builder = builder.hidden(width, config.activation);
     }
+    let model = builder
+        .output(n_outputs, config.output_activation)
+        .seed(config.seed)
+        .build();
+
+    let run_dir = out.join(&name);
+    fs::create_dir_all(&run_dir)?;
+    let start = Instant::now();
+    let (history, manifest) = match config.optimizer.as_str() {
+        "sgd" => train_run(
+            &model,
+            &data,
+            &config,
+            SGD::new(model.parameters(), config.lr),
+        ),
+        _ => train_run(
+            &model,
+            &data,
+            &config,
+            Adam::new(model.parameters(), config.lr),
+        ),
+    };
+    let seconds = start.elapsed().as_secs_f64();
+    let model_path = run_dir.join("model.txt");
+    model.save(&model_path)?;
+    manifest
+        .checkpoint(&model_path)
+        .save(run_dir.join("manifest.json"))?;
+    let final_loss = history.last().copied().unwrap_or(f64::NAN);
+    let best_loss = history.iter().copied().fold(f64::NAN, f64::min);
+    fs::write(
+        run_dir.join("metrics.txt"),
+        format!(
+            "final_loss {}\nbest_loss {}\nepochs {}\nseconds {}\n",
+            final_loss,
+            best_loss,
+            history.len(),
+            seconds
+        ),
+    )?;
+    println!("{}: final loss {} in {:.2}s", name, final_loss, seconds);
+    Ok(())
+}
 
-    fn parameters(&self) -> Vec<Value> {
-        self.neurons
-            .iter()
-            .map(|neuron| neuron.parameters())
-            .flatten()
-            .collect()
+fn train_run<O: Optimizer>(
+    model: &MLP,
+    data: &Dataset,
+    config: &RunConfig,
+    optimizer: O,
+) -> (Vec<f64>, RunManifest) {
+    fn fit<L: Loss, O: Optimizer>(
+        model: &MLP,
+        data: &Dataset,
+        config: &RunConfig,
+        loss: L,
+        optimizer: O,
+    ) -> (Vec<f64>, RunManifest) {
+        let mut trainer = Trainer::new(model, loss, optimizer, data)
+            .epochs(config.epochs)
+            .shuffle(config.seed);
+        if let Some(batch_size) = config.batch_size {
+            trainer = trainer.batch_size(batch_size);
+        }
+        let history = trainer.fit();
+        let manifest = trainer.manifest(&history);
+        (history, manifest)
+    }
+    let reduction = Reduction::Mean;
+    match config.loss.as_str() {
+        "mae" => fit(model, data, config, MAELoss::new(reduction), optimizer),
+        "bce" => fit(model, data, config, BCELoss::new(reduction), optimizer),
+        "hinge" => fit(model, data, config, HingeLoss::new(reduction), optimizer),
+        "cross_entropy" => fit(
+            model,
+            data,
+            config,
+            CrossEntropyLoss::new(reduction),
+            optimizer,
+        ),
+        _ => fit(model, data, config, MSELoss::new(reduction), optimizer),
     }
 }
 
-struct MLP {
-    layers: Vec<Layer>,
+fn run_name(config: &Path) -> String {
+    config
+        .file_stem()
+        .map_or("run".to_string(), |s| s.to_string_lossy().into_owned())
 }
 
-impl MLP {
-    fn new(nin: u16, nouts: Vec<u16>) -> Self {
-        let sz = vec![vec![nin], nouts].concat();
-        let layers: Vec<Layer> = sz.windows(2).map(|w| Layer::new(w[0], w[1])).collect();
-        MLP { layers }
+/// `key value` pairs from a run's `metrics.txt`.
+type Metrics = Vec<(String, String)>;
+
+/// Prints and saves the comparison table from each run's `metrics.txt`.
+fn write_summary(configs: &[PathBuf], failed: &[PathBuf], out: &Path) -> io::Result<()> {
+    let mut rows: Vec<(String, Option<Metrics>)> = configs
+        .iter()
+        .map(|config| {
+            let name = run_name(config);
+            let metrics = if failed.contains(config) {
+                None
+            } else {
+                fs::read_to_string(out.join(&name).join("metrics.txt"))
+                    .ok()
+                    .map(|text| {
+                        text.lines()
+                            .filter_map(|line| line.split_once(' '))
+                            .map(|(k, v)| (k.to_string(), v.to_string()))
+                            .collect()
+                    })
+            };
+            (name, metrics)
+        })
+        .collect();
+    let final_loss = |metrics: &Option<Metrics>| {
+        metrics
+            .as_ref()
+            .and_then(|m| m.iter().find(|(k, _)| k == "final_loss"))
+            .and_then(|(_, v)| v.parse::<f64>().ok())
+            .filter(|x| !x.is_nan())
+            .unwrap_or(f64::INFINITY)
+    };
+    rows.sort_by(|a, b| final_loss(&a.1).total_cmp(&final_loss(&b.1)));
+
+    let columns = ["final_loss", "best_loss", "epochs", "seconds"];
+    let mut table = vec![std::iter::once("run")
+        .chain(columns)
+        .map(|c| c.to_string())
+        .collect::<Vec<_>>()];
+    for (name, metrics) in rows {
+        let mut row = vec![name];
+        for column in columns {
+            row.push(match metrics.as_ref() {
+                Some(m) => m
+                    .iter()
+                    .find(|(k, _)| k == column)
+                    .map_or("-".to_string(), |(_, v)| v.clone()),
+                None => "failed".to_string(),
+            });
+        }
+        table.push(row);
     }
-
-    pub fn call(&self, inputs: &[f64]) -> Value {
-        let inputs: Vec<Value> = inputs.iter().map(|&x| Value::new(x)).collect();
-        let out = self
-            .layers
+    let tsv: String = table.iter().map(|row| row.join("\t") + "\n").collect();
+    fs::write(out.join("summary.tsv"), tsv)?;
+
+    let widths: Vec<usize> = (0..table[0].len())
+        .map(|j| table.iter().map(|row| row[j].len()).max().unwrap_or(0))
+        .collect();
+    println!();
+    for row in table.iter() {
+        let cells: Vec<String> = row
             .iter()
-            .fold(inputs.to_vec(), |acc, layer| layer.call(acc));
-        out[0].clone()
+            .zip(widths.iter())
+            .map(|(cell, &w)| format!("{:<w$}", cell, w = w))
+            .collect();
+        println!("{}", cells.join("  ").trim_end());
     }
-
-    fn parameters(&self) -> Vec<Value> {
-        self.layers
-            .iter()
-            .map(|layer| layer.parameters())
-            .flatten()
-            .collect()
+    if failed.is_empty() {
+        Ok(())
+    } else {
+        Err(io::Error::other(format!(
+            "{} of {} runs failed",
+            failed.len(),
+            configs.len()
+        )))
     }
 }
 
-fn main() {
+fn demo() {
     let x1 = Value::new(2.0);
     let x2 = Value::new(0.0);
 
     let w1 = Value::new(-3.0);
     let w2 = Value::new(1.0);
 
-    let b = Value::new(6.8813735870195432);
+    let b = Value::new(6.881_373_587_019_543);
 
-    let x1w1 = x1.clone().mul(w1.clone());
-    let x2w2 = x2.clone().mul(w2.clone());
+    let x1w1 = &x1 * &w1;
+    let x2w2 = &x2 * &w2;
 
-    let x1w1x2w2 = x1w1.clone().add(x2w2.clone());
+    let x1w1x2w2 = &x1w1 + &x2w2;
 
-    let n = x1w1x2w2.clone().add(b.clone());
+    let n = &x1w1x2w2 + &b;
 
     let o = n.clone().tanh();
     o.backward();
@@ -323,7 +835,7 @@ fn main() {
 
     // MLP Training
 
-    let n = MLP::new(3, [4, 4, 1].to_vec());
+    let n = MLP::new(3, [4, 4, 1].to_vec(), Activation::Tanh);
 
     let xs = [
         [2.0, 3.0, -1.0],
@@ -332,42 +844,32 @@ fn main() {
         [1.0, 1.0, -1.0],
     ];
     let ys = [1.0, -1.0, -1.0, 1.0];
-
-    let ypred: Vec<Value> = xs.iter().map(|row| n.call(row)).collect();
+    let dataset = Dataset::new(
+        xs.iter().map(|row| row.to_vec()).collect(),
+        ys.iter().map(|&y| vec![y]).collect(),
+    );
+    let criterion = MSELoss::new(Reduction::Sum);
+    let optimizer = LrScheduler::new(
+        SGD::new(n.parameters(), 0.1),
+        Schedule::CosineAnnealing {
+            t_max: 1000,
+            eta_min: 0.01,
+        },
+    );
+
+    let ypred: Vec<Vec<Value>> = xs.iter().map(|row| n.call(row)).collect();
     println!("\nypred before training:\n");
     for y in ypred {
-        println!("{}", y.get_data());
+        println!("{}", y[0].get_data());
     }
     println!("\nTraining...");
-    for _k in 0..1000 {
-        // Forward pass
-        let ypred: Vec<Value> = xs.iter().map(|row| n.call(row)).collect();
-        let squared_differences: Vec<Value> = ys
-            .iter()
-            .zip(ypred.iter())
-            .map(|(&ygt, &ref yout)| (yout.clone().sub(Value::new(ygt))).pow(Value::new(2.0)))
-            .collect();
-        let loss = squared_differences
-            .iter()
-            .fold(Value::new(0.0), |acc, x| acc.add(x.clone()));
-
-        // Print loss
-        println!("loss: {}", loss.get_data());
-
-        // Backward pass
-        for p in n.parameters() {
-            p.update_grad(0.0);
-        }
-        loss.clone().backward();
-
-        // Update parameters
-        for p in n.parameters() {
-            p.update_data(p.get_data() - 0.1 * p.get_grad());
-        }
-    }
-    let ypred: Vec<Value> = xs.iter().map(|row| n.call(row)).collect();
+    Trainer::new(&n, criterion, optimizer, &dataset)
+        .epochs(1000)
+        .callback(PrintLoss::new(100))
+        .fit();
+    let ypred: Vec<Vec<Value>> = xs.iter().map(|row| n.call(row)).collect();
     println!("\nypred after training:\n");
     for y in ypred {
-        println!("{}", y.get_data());
+        println!("{}", y[0].get_data());
     }
 }
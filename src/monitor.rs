@@ -0,0 +1,301 @@
+//! Callbacks that expose a running `Trainer::fit` over HTTP. Each binds its
+//! own listener and answers requests from a background thread that lives as
+//! long as the process, so training is never blocked by a slow client.
+//! Built with the `monitor` feature; `Dashboard` is for people and
+//! `MetricsExporter` for Prometheus.
+
+use crate::json;
+use crate::train::{BatchEnd, Callback, Control, EpochEnd};
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/// What the callbacks have seen so far.
+#[derive(Debug, Clone, Default)]
+struct Status {
+    steps: u64,
+    epochs: u64,
+    epoch: usize,
+    batch: usize,
+    loss: f64,
+    lr: f64,
+    grad_norm: f64,
+    epoch_losses: Vec<f64>,
+}
+
+type Shared = Arc<Mutex<Status>>;
+
+impl Status {
+    /// Nothing seen yet; the gauges start as NaN rather than a misleading 0.
+    fn shared() -> Shared {
+        Arc::new(Mutex::new(Status {
+            loss: f64::NAN,
+            lr: f64::NAN,
+            grad_norm: f64::NAN,
+            ..Status::default()
+        }))
+    }
+
+    fn batch_end(&mut self, stats: &BatchEnd) {
+        self.steps += 1;
+        self.epoch = stats.epoch;
+        self.batch = stats.batch;
+        self.loss = stats.loss;
+        self.lr = stats.lr;
+        self.grad_norm = stats.grad_norm;
+    }
+
+    fn epoch_end(&mut self, stats: &EpochEnd) {
+        self.epochs += 1;
+        self.epoch_losses.push(stats.loss);
+    }
+}
+
+/// Binds `addr` and serves `route(path, status)` to every GET request, as
+/// `(content type, body)`, or a 404 when it returns `None`.
+fn spawn_server<A, F>(addr: A, status: Shared, route: F) -> io::Result<SocketAddr>
+where
+    A: ToSocketAddrs,
+    F: Fn(&str, &Status) -> Option<(&'static str, String)> + Send + 'static,
+{
+    let listener = TcpListener::bind(addr)?;
+    let local = listener.local_addr()?;
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            // A failed response only affects that client.
+            let _ = respond(stream, &status, &route);
+        }
+    });
+    Ok(local)
+}
+
+fn respond<F>(mut stream: TcpStream, status: &Shared, route: &F) -> io::Result<()>
+where
+    F: Fn(&str, &Status) -> Option<(&'static str, String)>,
+{
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+            break;
+        }
+    }
+    let mut parts = request_line.split_whitespace();
+    let (method, path) = (parts.next().unwrap_or(""), parts.next().unwrap_or(""));
+    let snapshot = status.lock().unwrap_or_else(|e| e.into_inner()).clone();
+    let (code, content_type, body) = match route(path, &snapshot) {
+        _ if method != "GET" => (
+            "405 Method Not Allowed",
+            "text/plain",
+            "method not allowed\n".to_string(),
+        ),
+        Some((content_type, body)) => ("200 OK", content_type, body),
+        None => ("404 Not Found", "text/plain", "not found\n".to_string()),
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        code,
+        content_type,
+        body.len(),
+        body
+    )?;
+    stream.flush()
+}
+
+/// A sample value in the text exposition format, which spells the
+/// non-finite values `NaN`, `+Inf` and `-Inf`.
+fn prometheus_value(x: f64) -> String {
+    if x.is_nan() {
+        "NaN".to_string()
+    } else if x.is_infinite() {
+        if x > 0.0 { "+Inf" } else { "-Inf" }.to_string()
+    } else {
+        x.to_string()
+    }
+}
+
+fn prometheus_text(status: &Status) -> String {
+    let metrics: [(&str, &str, &str, String); 6] = [
+        (
+            "micrograd_steps_total",
+            "counter",
+            "Optimizer steps taken.",
+            status.steps.to_string(),
+        ),
+        (
+            "micrograd_epochs_total",
+            "counter",
+            "Epochs completed.",
+            status.epochs.to_string(),
+        ),
+        (
+            "micrograd_loss",
+            "gauge",
+            "Loss of the latest batch.",
+            prometheus_value(status.loss),
+        ),
+        (
+            "micrograd_epoch_loss",
+            "gauge",
+            "Mean batch loss of the latest epoch.",
+            prometheus_value(status.epoch_losses.last().copied().unwrap_or(f64::NAN)),
+        ),
+        (
+            "micrograd_learning_rate",
+            "gauge",
+            "Learning rate of the latest step.",
+            prometheus_value(status.lr),
+        ),
+        (
+            "micrograd_grad_norm",
+            "gauge",
+            "L2 norm of the gradients at the latest step.",
+            prometheus_value(status.grad_norm),
+        ),
+    ];
+    let mut out = String::new();
+    for (name, kind, help, value) in metrics {
+        out.push_str(&format!(
+            "# HELP {} {}\n# TYPE {} {}\n{} {}\n",
+            name, help, name, kind, name, value
+        ));
+    }
+    out
+}
+
+/// Serves training counters and gauges at `GET /metrics` in the Prometheus
+/// text format, for scraping into an existing monitoring stack: steps and
+/// epochs run, the latest batch and epoch losses, learning rate and
+/// gradient norm. Gauges read NaN until the first step.
+pub struct MetricsExporter {
+    status: Shared,
+    addr: SocketAddr,
+}
+
+impl MetricsExporter {
+    /// Starts serving on `addr`, e.g. `"127.0.0.1:9464"`; port 0 picks a
+    /// free one, see `local_addr`.
+    pub fn bind<A: ToSocketAddrs>(addr: A) -> io::Result<Self> {
+        let status = Status::shared();
+        let addr = spawn_server(addr, status.clone(), |path, status| {
+            (path == "/metrics").then(|| ("text/plain; version=0.0.4", prometheus_text(status)))
+        })?;
+        Ok(MetricsExporter { status, addr })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+}
+
+impl Callback for MetricsExporter {
+    fn on_batch_end(&mut self, stats: &BatchEnd) -> Control {
+        self.status.lock().unwrap().batch_end(stats);
+        Control::Continue
+    }
+
+    fn on_epoch_end(&mut self, stats: &EpochEnd) -> Control {
+        self.status.lock().unwrap().epoch_end(stats);
+        Control::Continue
+    }
+}
+
+fn status_json(status: &Status) -> String {
+    let history: Vec<String> = status
+        .epoch_losses
+        .iter()
+        .map(|&x| json::number(x))
+        .collect();
+    format!(
+        "{{\"steps\": {}, \"epoch\": {}, \"batch\": {}, \"loss\": {}, \"lr\": {}, \"grad_norm\": {}, \"history\": [{}]}}",
+        status.steps,
+        status.epoch,
+        status.batch,
+        json::number(status.loss),
+        json::number(status.lr),
+        json::number(status.grad_norm),
+        history.join(", ")
+    )
+}
+
+const DASHBOARD_HTML: &str = r#"<!doctype html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>micrograd training</title>
+<style>
+body { font-family: sans-serif; margin: 2em; }
+td { padding: 0 1em 0 0; }
+svg { border: 1px solid #ccc; }
+</style>
+</head>
+<body>
+<h1>micrograd training</h1>
+<table id="status"></table>
+<h2>epoch loss</h2>
+<svg id="plot" width="600" height="200"><polyline id="line" fill="none" stroke="steelblue" stroke-width="2"/></svg>
+<script>
+async function refresh() {
+  const s = await (await fetch("/status")).json();
+  const rows = [["step", s.steps], ["epoch", s.epoch], ["batch", s.batch],
+                ["loss", s.loss], ["lr", s.lr], ["grad norm", s.grad_norm]];
+  document.getElementById("status").innerHTML =
+    rows.map(([k, v]) => `<tr><td>${k}</td><td>${v}</td></tr>`).join("");
+  const h = s.history.filter(x => x !== null);
+  if (h.length > 0) {
+    const lo = Math.min(...h), hi = Math.max(...h), span = hi - lo || 1;
+    const dx = h.length > 1 ? 600 / (h.length - 1) : 0;
+    document.getElementById("line").setAttribute("points",
+      h.map((y, i) => `${i * dx},${190 - 180 * (y - lo) / span}`).join(" "));
+  }
+}
+refresh();
+setInterval(refresh, 1000);
+</script>
+</body>
+</html>
+"#;
+
+/// A live view of training in the browser: `GET /` is a page showing the
+/// latest step and plotting the epoch losses, refreshed every second from
+/// `GET /status`, which returns the same as JSON (`steps`, `epoch`,
+/// `batch`, `loss`, `lr`, `grad_norm` and the `history` of epoch losses,
+/// with `null` for values not seen yet).
+pub struct Dashboard {
+    status: Shared,
+    addr: SocketAddr,
+}
+
+impl Dashboard {
+    /// Starts serving on `addr`, e.g. `"127.0.0.1:8000"`; port 0 picks a
+    /// free one, see `local_addr`.
+    pub fn bind<A: ToSocketAddrs>(addr: A) -> io::Result<Self> {
+        let status = Status::shared();
+        let addr = spawn_server(addr, status.clone(), |path, status| match path {
+            "/" => Some(("text/html; charset=utf-8", DASHBOARD_HTML.to_string())),
+            "/status" => Some(("application/json", status_json(status))),
+            _ => None,
+        })?;
+        Ok(Dashboard { status, addr })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+}
+
+impl Callback for Dashboard {
+    fn on_batch_end(&mut self, stats: &BatchEnd) -> Control {
+        self.status.lock().unwrap().batch_end(stats);
+        Control::Continue
+    }
+
+    fn on_epoch_end(&mut self, stats: &EpochEnd) -> Control {
+        self.status.lock().unwrap().epoch_end(stats);
+        Control::Continue
+    }
+}
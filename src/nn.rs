@@ -0,0 +1,1919 @@
+use crate::engine::{self, Value, SELU_ALPHA, SELU_SCALE};
+use crate::json::{self, Json};
+use crate::loss;
+use crate::regularize;
+use rand::distributions::Uniform;
+use rand::rngs::StdRng;
+use rand::{Rng, RngCore, SeedableRng};
+use std::cell::RefCell;
+use std::fs;
+use std::io;
+use std::panic;
+use std::path::Path;
+use std::thread;
+
+const MLP_HEADER: &str = "micrograd-mlp v1";
+
+fn invalid_data(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// How `MLP::save_with_precision` stores weights. Half precisions shrink
+/// checkpoints about 4x and are widened back to `f64` on load, so only the
+/// saved values are rounded; training precision is unchanged.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Precision {
+    /// Exact decimal text. The default.
+    F64,
+    /// IEEE 754 half precision: 11 significant bits, range about `6.5e4`.
+    F16,
+    /// bfloat16: 8 significant bits but the full `f32` range.
+    BF16,
+}
+
+impl Precision {
+    fn name(self) -> &'static str {
+        match self {
+            Precision::F64 => "f64",
+            Precision::F16 => "f16",
+            Precision::BF16 => "bf16",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "f64" => Some(Precision::F64),
+            "f16" => Some(Precision::F16),
+            "bf16" => Some(Precision::BF16),
+            _ => None,
+        }
+    }
+
+    fn encode(self, x: f64) -> String {
+        match self {
+            Precision::F64 => x.to_string(),
+            Precision::F16 => format!("{:04x}", f16_bits(x)),
+            Precision::BF16 => format!("{:04x}", bf16_bits(x)),
+        }
+    }
+
+    fn decode(self, field: &str) -> Option<f64> {
+        match self {
+            Precision::F64 => field.parse().ok(),
+            Precision::F16 => u16::from_str_radix(field, 16).ok().map(f16_value),
+            Precision::BF16 => u16::from_str_radix(field, 16)
+                .ok()
+                .map(|h| f32::from_bits((h as u32) << 16) as f64),
+        }
+    }
+}
+
+/// Rounds via `f32` to the nearest half, ties to even.
+fn f16_bits(x: f64) -> u16 {
+    let bits = (x as f32).to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exp = ((bits >> 23) & 0xff) as i32;
+    let mant = bits & 0x7f_ffff;
+    if exp == 0xff {
+        let nan = if mant != 0 { 0x200 } else { 0 };
+        return sign | 0x7c00 | nan;
+    }
+    let round = |m: u32, shift: u32| {
+        let half = 1 << (shift - 1);
+        let rem = m & ((1 << shift) - 1);
+        let r = m >> shift;
+        if rem > half || rem == half && r & 1 == 1 {
+            r + 1
+        } else {
+            r
+        }
+    };
+    let e = exp - 127 + 15;
+    if e >= 0x1f {
+        return sign | 0x7c00;
+    }
+    if e <= 0 {
+        // Subnormal half; anything below half the smallest one rounds to 0.
+        if e < -10 {
+            return sign;
+        }
+        return sign | round(mant | 0x80_0000, (14 - e) as u32) as u16;
+    }
+    // A carry out of the mantissa correctly bumps the exponent, up to inf.
+    sign | round(((e as u32) << 23) | mant, 13) as u16
+}
+
+fn f16_value(h: u16) -> f64 {
+    let sign = if h & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exp = ((h >> 10) & 0x1f) as i32;
+    let mant = (h & 0x3ff) as f64;
+    sign * match exp {
+        0 => mant * 2f64.powi(-24),
+        0x1f if mant == 0.0 => f64::INFINITY,
+        0x1f => f64::NAN,
+        _ => (1.0 + mant / 1024.0) * 2f64.powi(exp - 15),
+    }
+}
+
+/// Rounds via `f32` to the nearest bfloat16, ties to even.
+fn bf16_bits(x: f64) -> u16 {
+    let bits = (x as f32).to_bits();
+    if (x as f32).is_nan() {
+        return ((bits >> 16) as u16) | 0x40;
+    }
+    ((bits + 0x7fff + ((bits >> 16) & 1)) >> 16) as u16
+}
+
+/// Post-update constraint on a neuron's incoming weights (the bias is left
+/// unconstrained).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Constraint {
+    /// Rescales the weight vector onto the ball of this L2 radius whenever
+    /// it grows past it.
+    MaxNorm(f64),
+    /// Clamps negative weights to zero.
+    NonNeg,
+}
+
+impl Constraint {
+    /// Applies the constraint to one neuron's incoming weight vector.
+    pub fn apply(&self, weights: &[Value]) {
+        match *self {
+            Constraint::MaxNorm(max_norm) => {
+                let norm = weights
+                    .iter()
+                    .map(|w| w.get_data().powi(2))
+                    .sum::<f64>()
+                    .sqrt();
+                if norm > max_norm {
+                    let scale = max_norm / norm;
+                    for w in weights.iter() {
+                        w.update_data(w.get_data() * scale);
+                    }
+                }
+            }
+            Constraint::NonNeg => {
+                for w in weights.iter() {
+                    w.update_data(w.get_data().max(0.0));
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Activation {
+    Tanh,
+    ReLU,
+    Sigmoid,
+    Linear,
+}
+
+impl Activation {
+    /// The lowercase name used in model files and configs, e.g. `"relu"`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Activation::Tanh => "tanh",
+            Activation::ReLU => "relu",
+            Activation::Sigmoid => "sigmoid",
+            Activation::Linear => "linear",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "tanh" => Some(Activation::Tanh),
+            "relu" => Some(Activation::ReLU),
+            "sigmoid" => Some(Activation::Sigmoid),
+            "linear" => Some(Activation::Linear),
+            _ => None,
+        }
+    }
+
+    /// `apply` on a plain number, for graph-free inference.
+    fn eval(self, x: f64) -> f64 {
+        match self {
+            Activation::Tanh => x.tanh(),
+            Activation::ReLU => x.max(0.0),
+            Activation::Sigmoid if x >= 0.0 => 1.0 / (1.0 + (-x).exp()),
+            Activation::Sigmoid => {
+                let e = x.exp();
+                e / (1.0 + e)
+            }
+            Activation::Linear => x,
+        }
+    }
+
+    pub fn apply(self, x: Value) -> Value {
+        match self {
+            Activation::Tanh => x.tanh(),
+            Activation::ReLU => x.relu(),
+            Activation::Sigmoid => x.sigmoid(),
+            Activation::Linear => x,
+        }
+    }
+
+    /// The tangent of `y = apply(z)` given the tangent `dz` of `z`, written
+    /// through `y` where the derivative allows.
+    fn tangent(self, z: &Value, y: &Value, dz: Value) -> Value {
+        match self {
+            Activation::Tanh => (1.0 - y * y) * dz,
+            Activation::ReLU if z.get_data() > 0.0 => dz,
+            Activation::ReLU => dz * 0.0,
+            Activation::Sigmoid => y * &(1.0 - y) * dz,
+            Activation::Linear => dz,
+        }
+    }
+}
+
+/// Weight initialization scheme. Biases start at zero except under
+/// `UniformUnit`, which keeps the original `U(-1, 1)` for both.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Init {
+    UniformUnit,
+    /// Glorot & Bengio (2010): `U(-a, a)` with `a = sqrt(6 / (fan_in + fan_out))`,
+    /// suited to tanh and sigmoid.
+    Xavier,
+    /// He et al. (2015): `U(-a, a)` with `a = sqrt(6 / fan_in)`, suited to ReLU.
+    He,
+    /// All zeros. Neurons in a layer then stay identical, so only use it for
+    /// output layers or tests.
+    Zeros,
+}
+
+impl Init {
+    fn sample_weight<R: Rng + ?Sized>(self, fan_in: u16, fan_out: u16, rng: &mut R) -> f64 {
+        let limit = match self {
+            Init::UniformUnit => 1.0,
+            Init::Xavier => (6.0 / (fan_in as f64 + fan_out as f64)).sqrt(),
+            Init::He => (6.0 / fan_in.max(1) as f64).sqrt(),
+            Init::Zeros => return 0.0,
+        };
+        rng.sample(Uniform::new(-limit, limit))
+    }
+}
+
+/// A differentiable building block: maps input `Value`s to output `Value`s
+/// and owns the parameters it uses, so architectures can be composed and
+/// trained generically.
+pub trait Module {
+    fn forward(&self, inputs: &[Value]) -> Vec<Value>;
+
+    fn parameters(&self) -> Vec<Value>;
+
+    /// The parameters split into named groups, such as layers, for tools
+    /// that look at each group separately. Defaults to one group, "all".
+    fn parameter_groups(&self) -> Vec<(String, Vec<Value>)> {
+        vec![("all".to_string(), self.parameters())]
+    }
+
+    /// Resets the grad of every parameter to zero.
+    fn zero_grad(&self) {
+        for p in self.parameters() {
+            p.update_grad(0.0);
+        }
+    }
+
+    /// Switches between training and evaluation behavior. Only stochastic
+    /// modules such as dropout act on it, and containers pass it on to their
+    /// children; everything else ignores it.
+    fn set_training(&mut self, _training: bool) {}
+
+    fn train(&mut self) {
+        self.set_training(true);
+    }
+
+    fn eval(&mut self) {
+        self.set_training(false);
+    }
+}
+
+/// Applies the activation elementwise, so it can sit between layers in a
+/// `Sequential`.
+impl Module for Activation {
+    fn forward(&self, inputs: &[Value]) -> Vec<Value> {
+        inputs.iter().map(|x| self.apply(x.clone())).collect()
+    }
+
+    fn parameters(&self) -> Vec<Value> {
+        vec![]
+    }
+}
+
+pub struct Neuron {
+    w: Vec<Value>,
+    b: Value,
+    activation: Activation,
+}
+
+impl Neuron {
+    pub fn new(nin: u16, activation: Activation) -> Self {
+        Self::new_with_rng(nin, activation, &mut rand::thread_rng())
+    }
+
+    /// Like `new`, drawing the initial weights from `rng` so initialization
+    /// can be made reproducible.
+    pub fn new_with_rng<R: Rng + ?Sized>(nin: u16, activation: Activation, rng: &mut R) -> Self {
+        Self::with_init(nin, 1, activation, Init::UniformUnit, rng)
+    }
+
+    /// Initializes the weights with `init`; `fan_out` is the width of the
+    /// layer the neuron belongs to, used by `Init::Xavier`.
+    pub fn with_init<R: Rng + ?Sized>(
+        nin: u16,
+        fan_out: u16,
+        activation: Activation,
+        init: Init,
+        rng: &mut R,
+    ) -> Self {
+        let w: Vec<Value> = (0..nin)
+            .map(|_| Value::new(init.sample_weight(nin, fan_out, rng)))
+            .collect();
+
+        let b = match init {
+            Init::UniformUnit => Value::new(rng.sample(Uniform::new(-1.0, 1.0))),
+            _ => Value::new(0.0),
+        };
+
+        Neuron { w, b, activation }
+    }
+
+    /// The neuron's single output; `forward` wraps it in a vector.
+    pub fn call(&self, inputs: &[Value]) -> Value {
+        assert_eq!(
+            self.w.len(),
+            inputs.len(),
+            "Input size must match number of weights."
+        );
+
+        self.activation.apply(self.call_linear(inputs))
+    }
+
+    /// The pre-activation `w . x + b`.
+    fn call_linear(&self, inputs: &[Value]) -> Value {
+        let wx: Vec<Value> = self
+            .w
+            .iter()
+            .zip(inputs.iter())
+            .map(|(weight, input)| weight * input)
+            .collect();
+        Value::sum(&wx) + &self.b
+    }
+
+    pub fn constrain(&self, constraint: Constraint) {
+        constraint.apply(&self.w);
+    }
+
+    /// The incoming weights, without the bias: what a `Constraint` acts on.
+    pub fn weight_vectors(&self) -> Vec<Vec<Value>> {
+        vec![self.w.clone()]
+    }
+}
+
+impl Module for Neuron {
+    fn forward(&self, inputs: &[Value]) -> Vec<Value> {
+        vec![self.call(inputs)]
+    }
+
+    fn parameters(&self) -> Vec<Value> {
+        let mut params: Vec<Value> = self.w.clone();
+        params.push(self.b.clone());
+        params
+    }
+}
+
+pub struct Layer {
+    neurons: Vec<Neuron>,
+}
+
+impl Layer {
+    pub fn new(nin: u16, nout: u16, activation: Activation) -> Self {
+        Self::new_with_rng(nin, nout, activation, &mut rand::thread_rng())
+    }
+
+    pub fn new_with_rng<R: Rng + ?Sized>(
+        nin: u16,
+        nout: u16,
+        activation: Activation,
+        rng: &mut R,
+    ) -> Self {
+        Self::with_init(nin, nout, activation, Init::UniformUnit, rng)
+    }
+
+    pub fn with_init<R: Rng + ?Sized>(
+        nin: u16,
+        nout: u16,
+        activation: Activation,
+        init: Init,
+        rng: &mut R,
+    ) -> Self {
+        let neurons: Vec<Neuron> = (0..nout)
+            .map(|_| Neuron::with_init(nin, nout, activation, init, rng))
+            .collect();
+        Layer { neurons }
+    }
+
+    pub fn constrain(&self, constraint: Constraint) {
+        for neuron in self.neurons.iter() {
+            neuron.constrain(constraint);
+        }
+    }
+
+    /// One incoming weight vector per neuron.
+    pub fn weight_vectors(&self) -> Vec<Vec<Value>> {
+        self.neurons
+            .iter()
+            .flat_map(|n| n.weight_vectors())
+            .collect()
+    }
+}
+
+impl Module for Layer {
+    fn forward(&self, inputs: &[Value]) -> Vec<Value> {
+        self.neurons
+            .iter()
+            .map(|neuron| neuron.call(inputs))
+            .collect()
+    }
+
+    fn parameters(&self) -> Vec<Value> {
+        self.neurons
+            .iter()
+            .flat_map(|neuron| neuron.parameters())
+            .collect()
+    }
+}
+
+/// Gradient reversal layer for domain-adversarial training.
+pub struct GradReverse {
+    lambda: f64,
+}
+
+impl GradReverse {
+    pub fn new(lambda: f64) -> Self {
+        GradReverse { lambda }
+    }
+}
+
+impl Module for GradReverse {
+    fn forward(&self, inputs: &[Value]) -> Vec<Value> {
+        inputs
+            .iter()
+            .map(|x| x.clone().grad_reverse(self.lambda))
+            .collect()
+    }
+
+    fn parameters(&self) -> Vec<Value> {
+        vec![]
+    }
+}
+
+/// Dropout for SELU networks: dropped units are set to SELU's negative
+/// saturation value and the result is rescaled so activations keep zero mean
+/// and unit variance.
+pub struct AlphaDropout {
+    p: f64,
+    training: bool,
+    rng: RefCell<StdRng>,
+}
+
+impl AlphaDropout {
+    pub fn new(p: f64) -> Self {
+        Self::with_rng(p, StdRng::from_entropy())
+    }
+
+    /// Draws the dropout masks reproducibly from `seed`.
+    pub fn new_seeded(p: f64, seed: u64) -> Self {
+        Self::with_rng(p, StdRng::seed_from_u64(seed))
+    }
+
+    fn with_rng(p: f64, rng: StdRng) -> Self {
+        assert!(
+            (0.0..1.0).contains(&p),
+            "Dropout probability must be in [0, 1)."
+        );
+        AlphaDropout {
+            p,
+            training: true,
+            rng: RefCell::new(rng),
+        }
+    }
+}
+
+impl Module for AlphaDropout {
+    fn forward(&self, inputs: &[Value]) -> Vec<Value> {
+        if !self.training || self.p == 0.0 {
+            return inputs.to_vec();
+        }
+        let mut rng = self.rng.borrow_mut();
+        let alpha_p = -SELU_SCALE * SELU_ALPHA;
+        let a = ((1.0 - self.p) * (1.0 + self.p * alpha_p.powi(2))).powf(-0.5);
+        let b = -a * alpha_p * self.p;
+
+        inputs
+            .iter()
+            .map(|x| {
+                if rng.gen::<f64>() < self.p {
+                    Value::constant(a * alpha_p + b)
+                } else {
+                    x * a + b
+                }
+            })
+            .collect()
+    }
+
+    fn parameters(&self) -> Vec<Value> {
+        vec![]
+    }
+
+    fn set_training(&mut self, training: bool) {
+        self.training = training;
+    }
+}
+
+/// Inverted dropout: in training mode each input is zeroed with
+/// probability `p` and the survivors are scaled by `1 / (1 - p)`, so eval
+/// mode can pass inputs through unchanged.
+pub struct Dropout {
+    p: f64,
+    training: bool,
+    rng: RefCell<StdRng>,
+}
+
+impl Dropout {
+    pub fn new(p: f64) -> Self {
+        Self::with_rng(p, StdRng::from_entropy())
+    }
+
+    /// Draws the dropout masks reproducibly from `seed`.
+    pub fn new_seeded(p: f64, seed: u64) -> Self {
+        Self::with_rng(p, StdRng::seed_from_u64(seed))
+    }
+
+    fn with_rng(p: f64, rng: StdRng) -> Self {
+        assert!(
+            (0.0..1.0).contains(&p),
+            "Dropout probability must be in [0, 1)."
+        );
+        Dropout {
+            p,
+            training: true,
+            rng: RefCell::new(rng),
+        }
+    }
+}
+
+impl Module for Dropout {
+    fn forward(&self, inputs: &[Value]) -> Vec<Value> {
+        if !self.training || self.p == 0.0 {
+            return inputs.to_vec();
+        }
+        let mut rng = self.rng.borrow_mut();
+        let scale = 1.0 / (1.0 - self.p);
+        inputs
+            .iter()
+            .map(|x| {
+                if rng.gen::<f64>() < self.p {
+                    Value::constant(0.0)
+                } else {
+                    x * scale
+                }
+            })
+            .collect()
+    }
+
+    fn parameters(&self) -> Vec<Value> {
+        vec![]
+    }
+
+    fn set_training(&mut self, training: bool) {
+        self.training = training;
+    }
+}
+
+/// Modules applied in order, each feeding its outputs to the next, e.g.
+/// `Sequential::new().push(Layer::new(2, 8, Activation::Linear))
+/// .push(Activation::ReLU).push(Layer::new(8, 1, Activation::Linear))`.
+#[derive(Default)]
+pub struct Sequential {
+    modules: Vec<Box<dyn Module>>,
+}
+
+impl Sequential {
+    pub fn new() -> Self {
+        Sequential { modules: vec![] }
+    }
+
+    pub fn push<M: Module + 'static>(mut self, module: M) -> Self {
+        self.modules.push(Box::new(module));
+        self
+    }
+}
+
+impl Module for Sequential {
+    fn forward(&self, inputs: &[Value]) -> Vec<Value> {
+        self.modules
+            .iter()
+            .fold(inputs.to_vec(), |acc, module| module.forward(&acc))
+    }
+
+    fn parameters(&self) -> Vec<Value> {
+        self.modules
+            .iter()
+            .flat_map(|module| module.parameters())
+            .collect()
+    }
+
+    fn set_training(&mut self, training: bool) {
+        for module in self.modules.iter_mut() {
+            module.set_training(training);
+        }
+    }
+}
+
+/// Skip connection around a block: `x + block(x)`. The block must map its
+/// inputs to as many outputs.
+pub struct Residual<M: Module> {
+    block: M,
+}
+
+impl<M: Module> Residual<M> {
+    pub fn new(block: M) -> Self {
+        Residual { block }
+    }
+}
+
+impl<M: Module> Module for Residual<M> {
+    fn forward(&self, inputs: &[Value]) -> Vec<Value> {
+        let outputs = self.block.forward(inputs);
+        assert_eq!(
+            outputs.len(),
+            inputs.len(),
+            "A residual block must keep the width of its inputs."
+        );
+        inputs
+            .iter()
+            .zip(outputs.iter())
+            .map(|(x, y)| x + y)
+            .collect()
+    }
+
+    fn parameters(&self) -> Vec<Value> {
+        self.block.parameters()
+    }
+
+    fn set_training(&mut self, training: bool) {
+        self.block.set_training(training);
+    }
+}
+
+/// Layer drop: in training mode the wrapped module runs with probability
+/// `survival` and is otherwise skipped, passing its inputs through
+/// unchanged; in eval mode it always runs. Wrapping a `Residual` gives
+/// stochastic depth (Huang et al., 2016), where a skipped block leaves just
+/// the skip connection, which lets deep stacks of blocks train like
+/// shallower ones. Skipped modules must keep the width of their inputs.
+pub struct StochasticDepth<M: Module> {
+    module: M,
+    survival: f64,
+    training: bool,
+}
+
+impl<M: Module> StochasticDepth<M> {
+    pub fn new(module: M, survival: f64) -> Self {
+        assert!(
+            survival > 0.0 && survival <= 1.0,
+            "Survival probability must be in (0, 1]."
+        );
+        StochasticDepth {
+            module,
+            survival,
+            training: true,
+        }
+    }
+}
+
+impl<M: Module> Module for StochasticDepth<M> {
+    fn forward(&self, inputs: &[Value]) -> Vec<Value> {
+        if self.training && rand::thread_rng().gen::<f64>() >= self.survival {
+            return inputs.to_vec();
+        }
+        self.module.forward(inputs)
+    }
+
+    fn parameters(&self) -> Vec<Value> {
+        self.module.parameters()
+    }
+
+    fn set_training(&mut self, training: bool) {
+        self.training = training;
+        self.module.set_training(training);
+    }
+}
+
+/// A recurrent cell that advances a state one time step at a time. As a
+/// `Module`, `forward` takes the step's `n_inputs` features followed by the
+/// previous state and returns the next state, whose first `hidden_size`
+/// values are the hidden output the rest of a network reads.
+pub trait RecurrentCell: Module {
+    fn n_inputs(&self) -> usize;
+
+    fn hidden_size(&self) -> usize;
+
+    fn state_size(&self) -> usize;
+
+    /// The all-zero state sequences start from.
+    fn initial_state(&self) -> Vec<Value> {
+        (0..self.state_size())
+            .map(|_| Value::constant(0.0))
+            .collect()
+    }
+
+    /// The state after input `x`.
+    fn step(&self, x: &[Value], state: &[Value]) -> Vec<Value> {
+        assert_eq!(x.len(), self.n_inputs(), "Input size must match the cell.");
+        assert_eq!(
+            state.len(),
+            self.state_size(),
+            "State size must match the cell."
+        );
+        self.forward(&[x, state].concat())
+    }
+
+    /// Runs the cell over a sequence of feature rows starting from `state`,
+    /// returning the hidden output of every step and the final state.
+    fn unroll(&self, xs: &[Vec<f64>], state: Vec<Value>) -> (Vec<Vec<Value>>, Vec<Value>) {
+        let mut state = state;
+        let mut hiddens = Vec::with_capacity(xs.len());
+        for x in xs {
+            let x: Vec<Value> = x.iter().map(|&xi| Value::constant(xi)).collect();
+            state = self.step(&x, &state);
+            hiddens.push(state[..self.hidden_size()].to_vec());
+        }
+        (hiddens, state)
+    }
+}
+
+/// Elman RNN cell: `h' = tanh(W [x, h] + b)`. The state is the hidden
+/// vector itself. Weights start Xavier-initialized and biases at zero.
+pub struct RNNCell {
+    layer: Layer,
+    n_inputs: usize,
+}
+
+impl RNNCell {
+    pub fn new(n_inputs: u16, hidden: u16) -> Self {
+        Self::new_with_rng(n_inputs, hidden, &mut rand::thread_rng())
+    }
+
+    pub fn new_seeded(n_inputs: u16, hidden: u16, seed: u64) -> Self {
+        Self::new_with_rng(n_inputs, hidden, &mut StdRng::seed_from_u64(seed))
+    }
+
+    pub fn new_with_rng<R: Rng + ?Sized>(n_inputs: u16, hidden: u16, rng: &mut R) -> Self {
+        RNNCell {
+            layer: Layer::with_init(
+                n_inputs + hidden,
+                hidden,
+                Activation::Tanh,
+                Init::Xavier,
+                rng,
+            ),
+            n_inputs: n_inputs as usize,
+        }
+    }
+}
+
+impl Module for RNNCell {
+    fn forward(&self, inputs: &[Value]) -> Vec<Value> {
+        self.layer.forward(inputs)
+    }
+
+    fn parameters(&self) -> Vec<Value> {
+        self.layer.parameters()
+    }
+}
+
+impl RecurrentCell for RNNCell {
+    fn n_inputs(&self) -> usize {
+        self.n_inputs
+    }
+
+    fn hidden_size(&self) -> usize {
+        self.layer.neurons.len()
+    }
+
+    fn state_size(&self) -> usize {
+        self.hidden_size()
+    }
+}
+
+/// LSTM cell (Hochreiter & Schmidhuber, 1997) with input, forget and output
+/// gates over `[x, h]`: `c' = f * c + i * tanh(W_g [x, h] + b_g)` and
+/// `h' = o * tanh(c')`. The state is `h` followed by `c`. Weights start
+/// Xavier-initialized and biases at zero.
+#[allow(clippy::upper_case_acronyms)]
+pub struct LSTMCell {
+    input_gate: Layer,
+    forget_gate: Layer,
+    candidate: Layer,
+    output_gate: Layer,
+    n_inputs: usize,
+}
+
+impl LSTMCell {
+    pub fn new(n_inputs: u16, hidden: u16) -> Self {
+        Self::new_with_rng(n_inputs, hidden, &mut rand::thread_rng())
+    }
+
+    pub fn new_seeded(n_inputs: u16, hidden: u16, seed: u64) -> Self {
+        Self::new_with_rng(n_inputs, hidden, &mut StdRng::seed_from_u64(seed))
+    }
+
+    /// Draws the gates' weights in the order input, forget, candidate,
+    /// output, which is also the order of `parameters`.
+    pub fn new_with_rng<R: Rng + ?Sized>(n_inputs: u16, hidden: u16, rng: &mut R) -> Self {
+        let mut gate =
+            |activation| Layer::with_init(n_inputs + hidden, hidden, activation, Init::Xavier, rng);
+        LSTMCell {
+            input_gate: gate(Activation::Sigmoid),
+            forget_gate: gate(Activation::Sigmoid),
+            candidate: gate(Activation::Tanh),
+            output_gate: gate(Activation::Sigmoid),
+            n_inputs: n_inputs as usize,
+        }
+    }
+
+    fn gates(&self) -> [&Layer; 4] {
+        [
+            &self.input_gate,
+            &self.forget_gate,
+            &self.candidate,
+            &self.output_gate,
+        ]
+    }
+}
+
+impl Module for LSTMCell {
+    fn forward(&self, inputs: &[Value]) -> Vec<Value> {
+        let hidden = self.hidden_size();
+        assert_eq!(
+            inputs.len(),
+            self.n_inputs + 2 * hidden,
+            "LSTM input must be the features, h and c."
+        );
+        let (xh, c) = inputs.split_at(self.n_inputs + hidden);
+        let [i, f, g, o] = self.gates().map(|gate| gate.forward(xh));
+        let c_next: Vec<Value> = (0..hidden).map(|j| &f[j] * &c[j] + &i[j] * &g[j]).collect();
+        let h_next: Vec<Value> = (0..hidden)
+            .map(|j| &o[j] * c_next[j].clone().tanh())
+            .collect();
+        [h_next, c_next].concat()
+    }
+
+    fn parameters(&self) -> Vec<Value> {
+        self.gates()
+            .iter()
+            .flat_map(|gate| gate.parameters())
+            .collect()
+    }
+
+    fn parameter_groups(&self) -> Vec<(String, Vec<Value>)> {
+        ["input gate", "forget gate", "candidate", "output gate"]
+            .iter()
+            .zip(self.gates())
+            .map(|(name, gate)| (name.to_string(), gate.parameters()))
+            .collect()
+    }
+}
+
+impl RecurrentCell for LSTMCell {
+    fn n_inputs(&self) -> usize {
+        self.n_inputs
+    }
+
+    fn hidden_size(&self) -> usize {
+        self.input_gate.neurons.len()
+    }
+
+    fn state_size(&self) -> usize {
+        2 * self.hidden_size()
+    }
+}
+
+#[allow(clippy::upper_case_acronyms)]
+pub struct MLP {
+    layers: Vec<Layer>,
+    /// Dropout applied to the outputs of the layer at each index, as added
+    /// by `MLPBuilder::dropout`.
+    dropout: Vec<(usize, Dropout)>,
+}
+
+impl MLP {
+    /// Starts an `MLPBuilder`, for per-layer activations, dropout and the
+    /// init scheme: `MLP::builder().input(3).hidden(16, Activation::ReLU)
+    /// .dropout(0.1).output(1, Activation::Linear).init(Init::He).seed(42)
+    /// .build()`.
+    pub fn builder() -> MLPBuilder {
+        MLPBuilder::new()
+    }
+
+    /// Hidden layers use `activation`; the output layer is linear so
+    /// regression targets aren't squashed.
+    pub fn new(nin: u16, nouts: Vec<u16>, activation: Activation) -> Self {
+        Self::new_with_rng(nin, nouts, activation, &mut rand::thread_rng())
+    }
+
+    /// Deterministic initialization from a `StdRng` seeded with `seed`.
+    pub fn new_seeded(nin: u16, nouts: Vec<u16>, activation: Activation, seed: u64) -> Self {
+        Self::new_with_rng(nin, nouts, activation, &mut StdRng::seed_from_u64(seed))
+    }
+
+    pub fn new_with_rng<R: Rng + ?Sized>(
+        nin: u16,
+        nouts: Vec<u16>,
+        activation: Activation,
+        rng: &mut R,
+    ) -> Self {
+        Self::with_init(nin, nouts, activation, None, Init::UniformUnit, rng)
+    }
+
+    /// Hidden layers use `activation` and the output layer `output`, linear
+    /// when `None`; e.g. `Some(Activation::Sigmoid)` for probabilities.
+    pub fn with_init<R: Rng + ?Sized>(
+        nin: u16,
+        nouts: Vec<u16>,
+        activation: Activation,
+        output: Option<Activation>,
+        init: Init,
+        rng: &mut R,
+    ) -> Self {
+        let sz = [vec![nin], nouts].concat();
+        let n_layers = sz.len() - 1;
+        let layers: Vec<Layer> = sz
+            .windows(2)
+            .enumerate()
+            .map(|(i, w)| {
+                let act = if i + 1 == n_layers {
+                    output.unwrap_or(Activation::Linear)
+                } else {
+                    activation
+                };
+                Layer::with_init(w[0], w[1], act, init, rng)
+            })
+            .collect();
+        MLP {
+            layers,
+            dropout: vec![],
+        }
+    }
+
+    /// Number of features `call` expects.
+    pub fn n_inputs(&self) -> usize {
+        self.layers
+            .first()
+            .and_then(|l| l.neurons.first())
+            .map_or(0, |n| n.w.len())
+    }
+
+    pub fn n_outputs(&self) -> usize {
+        self.layers.last().map_or(0, |l| l.neurons.len())
+    }
+
+    /// Forward pass on raw features, returning one `Value` per output
+    /// neuron.
+    pub fn call(&self, inputs: &[f64]) -> Vec<Value> {
+        let inputs: Vec<Value> = inputs.iter().map(|&x| Value::constant(x)).collect();
+        self.forward(&inputs)
+    }
+
+    /// The outputs at `inputs` together with their directional derivatives
+    /// along `direction` (a Jacobian-vector product), both as graphs that
+    /// stay differentiable in the weights. Tangents are carried forward
+    /// neuron by neuron alongside the values, so derivatives of the outputs
+    /// can go into a loss without higher-order backward. Dropout is not
+    /// applied.
+    pub fn call_with_tangent(&self, inputs: &[f64], direction: &[f64]) -> (Vec<Value>, Vec<Value>) {
+        assert_eq!(
+            inputs.len(),
+            direction.len(),
+            "Direction must have one entry per input."
+        );
+        let xs: Vec<Value> = inputs.iter().map(|&x| Value::constant(x)).collect();
+        let ts: Vec<Value> = direction.iter().map(|&t| Value::constant(t)).collect();
+        self.layers.iter().fold((xs, ts), |(xs, ts), layer| {
+            layer
+                .neurons
+                .iter()
+                .map(|n| {
+                    let z = n.call_linear(&xs);
+                    let dz: Vec<Value> = n.w.iter().zip(ts.iter()).map(|(w, t)| w * t).collect();
+                    let y = n.activation.apply(z.clone());
+                    let dy = n.activation.tangent(&z, &y, Value::sum(&dz));
+                    (y, dy)
+                })
+                .unzip()
+        })
+    }
+
+    /// Graph-free batch inference for scoring many rows: the weights are
+    /// copied out as plain `f64`s once and the rows are split across
+    /// `n_threads` threads, so no `Value` nodes are built. Returns outputs in
+    /// input order, bit for bit what `call` gives.
+    pub fn predict_batch_parallel(&self, inputs: &[Vec<f64>], n_threads: usize) -> Vec<Vec<f64>> {
+        assert!(n_threads > 0, "Need at least one thread.");
+        // (weights, bias, activation) per neuron, per layer.
+        type Weights = Vec<Vec<(Vec<f64>, f64, Activation)>>;
+        let weights: Weights = self
+            .layers
+            .iter()
+            .map(|layer| {
+                layer
+                    .neurons
+                    .iter()
+                    .map(|n| {
+                        let w = n.w.iter().map(|w| w.get_data()).collect();
+                        (w, n.b.get_data(), n.activation)
+                    })
+                    .collect()
+            })
+            .collect();
+        let predict = |row: &Vec<f64>| -> Vec<f64> {
+            weights.iter().fold(row.clone(), |x, layer| {
+                layer
+                    .iter()
+                    .map(|(w, b, activation)| {
+                        assert_eq!(w.len(), x.len(), "Input size must match number of weights.");
+                        let wx: Vec<f64> = w.iter().zip(x.iter()).map(|(w, x)| w * x).collect();
+                        let act = pairwise_sum(wx) + b;
+                        activation.eval(act)
+                    })
+                    .collect()
+            })
+        };
+
+        let chunk = inputs.len().div_ceil(n_threads).max(1);
+        thread::scope(|scope| {
+            let handles: Vec<_> = inputs
+                .chunks(chunk)
+                .map(|rows| scope.spawn(|| rows.iter().map(predict).collect::<Vec<_>>()))
+                .collect();
+            handles
+                .into_iter()
+                .flat_map(|h| h.join().unwrap_or_else(|e| panic::resume_unwind(e)))
+                .collect()
+        })
+    }
+
+    /// Net2Net-style widening: adds `extra` neurons to hidden layer `layer`
+    /// by replicating randomly chosen existing neurons, and divides the next
+    /// layer's outgoing weights among the copies so the network computes the
+    /// same function as before.
+    pub fn widen(&mut self, layer: usize, extra: u16) {
+        assert!(
+            layer + 1 < self.layers.len(),
+            "Only hidden layers can be widened."
+        );
+        let mut rng = rand::thread_rng();
+        let width = self.layers[layer].neurons.len();
+        let sources: Vec<usize> = (0..extra).map(|_| rng.gen_range(0..width)).collect();
+
+        let mut copies = vec![1; width];
+        for &j in sources.iter() {
+            copies[j] += 1;
+        }
+        for &j in sources.iter() {
+            let source = &self.layers[layer].neurons[j];
+            let neuron = Neuron {
+                w: source.w.iter().map(|w| Value::new(w.get_data())).collect(),
+                b: Value::new(source.b.get_data()),
+                activation: source.activation,
+            };
+            self.layers[layer].neurons.push(neuron);
+        }
+        for neuron in self.layers[layer + 1].neurons.iter_mut() {
+            for (w, &c) in neuron.w.iter().zip(copies.iter()) {
+                w.update_data(w.get_data() / c as f64);
+            }
+            for &j in sources.iter() {
+                let w = Value::new(neuron.w[j].get_data());
+                neuron.w.push(w);
+            }
+        }
+    }
+
+    /// Appends a freshly initialized linear output layer of `nout` neurons,
+    /// keeping all trained weights below it. The previous output layer
+    /// becomes hidden and switches to `activation`.
+    pub fn append_layer(&mut self, nout: u16, activation: Activation) {
+        let nin = match self.layers.last_mut() {
+            Some(last) => {
+                for neuron in last.neurons.iter_mut() {
+                    neuron.activation = activation;
+                }
+                last.neurons.len() as u16
+            }
+            None => 0,
+        };
+        self.layers.push(Layer::new(nin, nout, Activation::Linear));
+    }
+
+    /// Applies `constraint` to every neuron; call after each parameter
+    /// update, or wrap the optimizer in `optim::Constrained` to have it done
+    /// on every step.
+    pub fn constrain(&self, constraint: Constraint) {
+        for layer in self.layers.iter() {
+            layer.constrain(constraint);
+        }
+    }
+
+    /// One incoming weight vector per neuron, layer by layer, for
+    /// `optim::Constrained`.
+    pub fn weight_vectors(&self) -> Vec<Vec<Value>> {
+        self.layers
+            .iter()
+            .flat_map(|l| l.weight_vectors())
+            .collect()
+    }
+
+    /// Text format: a header line, then per layer a `layer <nin> <nout>
+    /// <activation>` line followed by one line per neuron holding its
+    /// weights and then its bias. Dropout isn't stored, since it only acts
+    /// in training.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        self.save_with_precision(path, Precision::F64)
+    }
+
+    /// Like `save`, storing the weights at `precision`. Half precisions
+    /// write each value as the hex bit pattern after a `precision <name>`
+    /// line; `load` reads either.
+    pub fn save_with_precision<P: AsRef<Path>>(
+        &self,
+        path: P,
+        precision: Precision,
+    ) -> io::Result<()> {
+        let mut out = format!("{}\n", MLP_HEADER);
+        if precision != Precision::F64 {
+            out.push_str(&format!("precision {}\n", precision.name()));
+        }
+        for layer in self.layers.iter() {
+            let nin = layer.neurons.first().map_or(0, |n| n.w.len());
+            let activation = layer
+                .neurons
+                .first()
+                .map_or(Activation::Linear, |n| n.activation);
+            out.push_str(&format!(
+                "layer {} {} {}\n",
+                nin,
+                layer.neurons.len(),
+                activation.name()
+            ));
+            for neuron in layer.neurons.iter() {
+                let values: Vec<String> = neuron
+                    .parameters()
+                    .iter()
+                    .map(|p| precision.encode(p.get_data()))
+                    .collect();
+                out.push_str(&values.join(" "));
+                out.push('\n');
+            }
+        }
+        fs::write(path, out)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let text = fs::read_to_string(path)?;
+        let mut lines = text.lines().peekable();
+        if lines.next() != Some(MLP_HEADER) {
+            return Err(invalid_data("not an MLP file".to_string()));
+        }
+        let mut precision = Precision::F64;
+        if let Some(name) = lines.peek().and_then(|l| l.strip_prefix("precision ")) {
+            precision = Precision::from_name(name.trim())
+                .ok_or_else(|| invalid_data(format!("unknown precision {:?}", name)))?;
+            lines.next();
+        }
+        let mut layers = vec![];
+        let mut expected_nin = None;
+        while let Some(line) = lines.next() {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let (nin, nout, activation) = match fields.as_slice() {
+                ["layer", nin, nout, activation] => (
+                    nin.parse::<usize>().ok(),
+                    nout.parse::<usize>().ok(),
+                    Activation::from_name(activation),
+                ),
+                _ => (None, None, None),
+            };
+            let (nin, nout, activation) = match (nin, nout, activation) {
+                (Some(nin), Some(nout), Some(activation)) => (nin, nout, activation),
+                _ => return Err(invalid_data(format!("invalid layer line {:?}", line))),
+            };
+            if expected_nin.is_some_and(|n| n != nin) {
+                return Err(invalid_data(format!(
+                    "layer {}: expected {} inputs, got {}",
+                    layers.len(),
+                    expected_nin.unwrap(),
+                    nin
+                )));
+            }
+            expected_nin = Some(nout);
+            let mut neurons = Vec::with_capacity(nout);
+            for _ in 0..nout {
+                let line = lines
+                    .next()
+                    .ok_or_else(|| invalid_data("truncated MLP file".to_string()))?;
+                let values = line
+                    .split_whitespace()
+                    .map(|v| precision.decode(v))
+                    .collect::<Option<Vec<f64>>>()
+                    .ok_or_else(|| invalid_data(format!("invalid weights {:?}", line)))?;
+                if values.len() != nin + 1 {
+                    return Err(invalid_data(format!(
+                        "expected {} weights and a bias, got {} values",
+                        nin,
+                        values.len()
+                    )));
+                }
+                neurons.push(Neuron {
+                    w: values[..nin].iter().map(|&w| Value::new(w)).collect(),
+                    b: Value::new(values[nin]),
+                    activation,
+                });
+            }
+            layers.push(Layer { neurons });
+        }
+        Ok(MLP {
+            layers,
+            dropout: vec![],
+        })
+    }
+
+    /// Builds an MLP from Dense-layer weights exported from Keras as JSON: an
+    /// array with one object per layer holding `kernel` (`nin` rows of `nout`
+    /// weights, Keras' layout), `bias` and optionally `activation` (`tanh`,
+    /// `relu`, `sigmoid` or `linear`, the default). For a Sequential model of
+    /// Dense layers:
+    ///
+    /// ```text
+    /// json.dump([{"kernel": l.get_weights()[0].tolist(),
+    ///             "bias": l.get_weights()[1].tolist(),
+    ///             "activation": l.get_config()["activation"]}
+    ///            for l in model.layers], f)
+    /// ```
+    pub fn load_keras_json<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let text = fs::read_to_string(path)?;
+        let doc = json::parse(&text).map_err(invalid_data)?;
+        let specs = doc
+            .as_array()
+            .ok_or_else(|| invalid_data("expected an array of layers".to_string()))?;
+        let mut layers = Vec::with_capacity(specs.len());
+        let mut expected_nin = None;
+        for (l, spec) in specs.iter().enumerate() {
+            let kernel: Vec<Vec<f64>> = spec
+                .get("kernel")
+                .and_then(Json::as_array)
+                .and_then(|rows| rows.iter().map(Json::as_f64_vec).collect())
+                .ok_or_else(|| invalid_data(format!("layer {}: invalid kernel", l)))?;
+            let bias = spec
+                .get("bias")
+                .and_then(Json::as_f64_vec)
+                .ok_or_else(|| invalid_data(format!("layer {}: invalid bias", l)))?;
+            let activation = match spec.get("activation") {
+                None => Activation::Linear,
+                Some(name) => name
+                    .as_str()
+                    .and_then(Activation::from_name)
+                    .ok_or_else(|| {
+                        invalid_data(format!("layer {}: unsupported activation {:?}", l, name))
+                    })?,
+            };
+            let (nin, nout) = (kernel.len(), bias.len());
+            if kernel.iter().any(|row| row.len() != nout) {
+                return Err(invalid_data(format!(
+                    "layer {}: kernel rows must have {} weights to match the bias",
+                    l, nout
+                )));
+            }
+            if expected_nin.is_some_and(|n| n != nin) {
+                return Err(invalid_data(format!(
+                    "layer {}: expected {} inputs, got {}",
+                    l,
+                    expected_nin.unwrap(),
+                    nin
+                )));
+            }
+            expected_nin = Some(nout);
+            let neurons = (0..nout)
+                .map(|j| Neuron {
+                    w: kernel.iter().map(|row| Value::new(row[j])).collect(),
+                    b: Value::new(bias[j]),
+                    activation,
+                })
+                .collect();
+            layers.push(Layer { neurons });
+        }
+        Ok(MLP {
+            layers,
+            dropout: vec![],
+        })
+    }
+
+    /// `regularize::l2_penalty` over every weight and bias.
+    pub fn l2_penalty(&self, lambda: f64) -> Value {
+        regularize::l2_penalty(&self.parameters(), lambda)
+    }
+
+    /// `regularize::l1_penalty` over every weight and bias.
+    pub fn l1_penalty(&self, lambda: f64) -> Value {
+        regularize::l1_penalty(&self.parameters(), lambda)
+    }
+
+    /// Exports the network as a PMML 4.4 `NeuralNetwork` regression model for
+    /// scoring tools that consume PMML. Inputs are the fields `x0, x1, ...`
+    /// and outputs the targets `y0, y1, ...`; neuron ids are `"layer,index"`,
+    /// with the inputs as layer 0.
+    pub fn to_pmml(&self) -> String {
+        let (nin, nout) = (self.n_inputs(), self.n_outputs());
+
+        let mut out = String::new();
+        out.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        out.push_str("<PMML xmlns=\"http://www.dmg.org/PMML-4_4\" version=\"4.4\">\n");
+        out.push_str("  <Header description=\"micrograd MLP\"/>\n");
+        out.push_str(&format!(
+            "  <DataDictionary numberOfFields=\"{}\">\n",
+            nin + nout
+        ));
+        let fields = (0..nin)
+            .map(|i| format!("x{}", i))
+            .chain((0..nout).map(|j| format!("y{}", j)));
+        for field in fields {
+            out.push_str(&format!(
+                "    <DataField name=\"{}\" optype=\"continuous\" dataType=\"double\"/>\n",
+                field
+            ));
+        }
+        out.push_str("  </DataDictionary>\n");
+        out.push_str(
+            "  <NeuralNetwork functionName=\"regression\" activationFunction=\"identity\">\n",
+        );
+        out.push_str("    <MiningSchema>\n");
+        for i in 0..nin {
+            out.push_str(&format!("      <MiningField name=\"x{}\"/>\n", i));
+        }
+        for j in 0..nout {
+            out.push_str(&format!(
+                "      <MiningField name=\"y{}\" usageType=\"target\"/>\n",
+                j
+            ));
+        }
+        out.push_str("    </MiningSchema>\n");
+        out.push_str(&format!("    <NeuralInputs numberOfInputs=\"{}\">\n", nin));
+        for i in 0..nin {
+            out.push_str(&format!("      <NeuralInput id=\"0,{}\">\n", i));
+            out.push_str("        <DerivedField optype=\"continuous\" dataType=\"double\">\n");
+            out.push_str(&format!("          <FieldRef field=\"x{}\"/>\n", i));
+            out.push_str("        </DerivedField>\n");
+            out.push_str("      </NeuralInput>\n");
+        }
+        out.push_str("    </NeuralInputs>\n");
+        for (l, layer) in self.layers.iter().enumerate() {
+            let activation = layer
+                .neurons
+                .first()
+                .map_or(Activation::Linear, |n| n.activation);
+            let function = match activation {
+                Activation::Tanh => "tanh",
+                Activation::ReLU => "rectifier",
+                Activation::Sigmoid => "logistic",
+                Activation::Linear => "identity",
+            };
+            out.push_str(&format!(
+                "    <NeuralLayer numberOfNeurons=\"{}\" activationFunction=\"{}\">\n",
+                layer.neurons.len(),
+                function
+            ));
+            for (j, neuron) in layer.neurons.iter().enumerate() {
+                out.push_str(&format!(
+                    "      <Neuron id=\"{},{}\" bias=\"{}\">\n",
+                    l + 1,
+                    j,
+                    neuron.b.get_data()
+                ));
+                for (k, w) in neuron.w.iter().enumerate() {
+                    out.push_str(&format!(
+                        "        <Con from=\"{},{}\" weight=\"{}\"/>\n",
+                        l,
+                        k,
+                        w.get_data()
+                    ));
+                }
+                out.push_str("      </Neuron>\n");
+            }
+            out.push_str("    </NeuralLayer>\n");
+        }
+        out.push_str(&format!(
+            "    <NeuralOutputs numberOfOutputs=\"{}\">\n",
+            nout
+        ));
+        for j in 0..nout {
+            out.push_str(&format!(
+                "      <NeuralOutput outputNeuron=\"{},{}\">\n",
+                self.layers.len(),
+                j
+            ));
+            out.push_str("        <DerivedField optype=\"continuous\" dataType=\"double\">\n");
+            out.push_str(&format!("          <FieldRef field=\"y{}\"/>\n", j));
+            out.push_str("        </DerivedField>\n");
+            out.push_str("      </NeuralOutput>\n");
+        }
+        out.push_str("    </NeuralOutputs>\n");
+        out.push_str("  </NeuralNetwork>\n");
+        out.push_str("</PMML>\n");
+        out
+    }
+}
+
+impl Module for MLP {
+    fn forward(&self, inputs: &[Value]) -> Vec<Value> {
+        self.layers
+            .iter()
+            .enumerate()
+            .fold(inputs.to_vec(), |acc, (i, layer)| {
+                let outputs = layer.forward(&acc);
+                match self.dropout.iter().find(|(after, _)| *after == i) {
+                    Some((_, dropout)) => dropout.forward(&outputs),
+                    None => outputs,
+                }
+            })
+    }
+
+    fn parameters(&self) -> Vec<Value> {
+        self.layers
+            .iter()
+            .flat_map(|layer| layer.parameters())
+            .collect()
+    }
+
+    fn parameter_groups(&self) -> Vec<(String, Vec<Value>)> {
+        self.layers
+            .iter()
+            .enumerate()
+            .map(|(i, layer)| (format!("layer {}", i), layer.parameters()))
+            .collect()
+    }
+
+    fn set_training(&mut self, training: bool) {
+        for (_, dropout) in self.dropout.iter_mut() {
+            dropout.set_training(training);
+        }
+    }
+}
+
+/// Fluent construction of an `MLP`; see `MLP::builder`. Layers are added in
+/// order and `dropout` applies to the outputs of the hidden layer added just
+/// before it. Weights are drawn with `Init::UniformUnit` from `thread_rng`
+/// unless `init` or `seed` say otherwise.
+pub struct MLPBuilder {
+    nin: Option<u16>,
+    layers: Vec<(u16, Activation)>,
+    dropout: Vec<(usize, Dropout)>,
+    has_output: bool,
+    init: Init,
+    seed: Option<u64>,
+}
+
+impl MLPBuilder {
+    fn new() -> Self {
+        MLPBuilder {
+            nin: None,
+            layers: vec![],
+            dropout: vec![],
+            has_output: false,
+            init: Init::UniformUnit,
+            seed: None,
+        }
+    }
+
+    pub fn input(mut self, nin: u16) -> Self {
+        self.nin = Some(nin);
+        self
+    }
+
+    pub fn hidden(mut self, nout: u16, activation: Activation) -> Self {
+        assert!(
+            !self.has_output,
+            "Hidden layers must come before the output."
+        );
+        self.layers.push((nout, activation));
+        self
+    }
+
+    /// Inverted dropout with probability `p` on the last hidden layer's
+    /// outputs; see `Dropout`.
+    pub fn dropout(mut self, p: f64) -> Self {
+        assert!(
+            !self.layers.is_empty() && !self.has_output,
+            "Dropout must follow a hidden layer."
+        );
+        self.dropout.push((self.layers.len() - 1, Dropout::new(p)));
+        self
+    }
+
+    pub fn output(mut self, nout: u16, activation: Activation) -> Self {
+        assert!(!self.has_output, "The output layer is already set.");
+        self.layers.push((nout, activation));
+        self.has_output = true;
+        self
+    }
+
+    pub fn init(mut self, init: Init) -> Self {
+        self.init = init;
+        self
+    }
+
+    /// Initializes from a `StdRng` seeded with `seed`, which then seeds the
+    /// dropout masks too, so training and `predict_mc` are reproducible.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    pub fn build(self) -> MLP {
+        let nin = self.nin.expect("Set the input size with `input`.");
+        assert!(self.has_output, "Set the output layer with `output`.");
+        let mut rng: Box<dyn RngCore> = match self.seed {
+            Some(seed) => Box::new(StdRng::seed_from_u64(seed)),
+            None => Box::new(rand::thread_rng()),
+        };
+        let mut fan_in = nin;
+        let mut layers = Vec::with_capacity(self.layers.len());
+        for &(nout, activation) in self.layers.iter() {
+            layers.push(Layer::with_init(
+                fan_in, nout, activation, self.init, &mut rng,
+            ));
+            fan_in = nout;
+        }
+        let mut dropout = self.dropout;
+        if self.seed.is_some() {
+            for (_, d) in dropout.iter_mut() {
+                d.rng = RefCell::new(StdRng::seed_from_u64(rng.next_u64()));
+            }
+        }
+        MLP { layers, dropout }
+    }
+}
+
+/// Encoder and decoder MLPs meeting at a `bottleneck`-wide code: `encode`
+/// maps `nin` features down through `hidden` to the code and `decode`
+/// mirrors the widths back up, with linear code and output layers. As a
+/// `Module` it outputs the reconstruction, so it trains like any regressor
+/// on targets equal to its inputs:
+///
+/// ```text
+/// let ae = Autoencoder::new(8, vec![6], 2, Activation::Tanh);
+/// let data = Dataset::reconstruction(inputs);
+/// let adam = Adam::new(ae.parameters(), 0.01);
+/// Trainer::new(&ae, MSELoss::new(Reduction::Mean), adam, &data).epochs(200).fit();
+/// ```
+pub struct Autoencoder {
+    encoder: MLP,
+    decoder: MLP,
+}
+
+impl Autoencoder {
+    pub fn new(nin: u16, hidden: Vec<u16>, bottleneck: u16, activation: Activation) -> Self {
+        Self::new_with_rng(nin, hidden, bottleneck, activation, &mut rand::thread_rng())
+    }
+
+    pub fn new_seeded(
+        nin: u16,
+        hidden: Vec<u16>,
+        bottleneck: u16,
+        activation: Activation,
+        seed: u64,
+    ) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+        Self::new_with_rng(nin, hidden, bottleneck, activation, &mut rng)
+    }
+
+    pub fn new_with_rng<R: Rng + ?Sized>(
+        nin: u16,
+        hidden: Vec<u16>,
+        bottleneck: u16,
+        activation: Activation,
+        rng: &mut R,
+    ) -> Self {
+        let down = [hidden.clone(), vec![bottleneck]].concat();
+        let up = [hidden.into_iter().rev().collect(), vec![nin]].concat();
+        Autoencoder {
+            encoder: MLP::new_with_rng(nin, down, activation, rng),
+            decoder: MLP::new_with_rng(bottleneck, up, activation, rng),
+        }
+    }
+
+    pub fn encoder(&self) -> &MLP {
+        &self.encoder
+    }
+
+    pub fn decoder(&self) -> &MLP {
+        &self.decoder
+    }
+
+    pub fn encode(&self, inputs: &[Value]) -> Vec<Value> {
+        self.encoder.forward(inputs)
+    }
+
+    pub fn decode(&self, code: &[Value]) -> Vec<Value> {
+        self.decoder.forward(code)
+    }
+
+    /// Mean squared reconstruction error of one sample, e.g. as an anomaly
+    /// score.
+    pub fn reconstruction_error(&self, x: &[f64]) -> f64 {
+        let inputs: Vec<Value> = x.iter().map(|&xi| Value::constant(xi)).collect();
+        loss::mse(&self.forward(&inputs), x).get_data()
+    }
+}
+
+impl Module for Autoencoder {
+    fn forward(&self, inputs: &[Value]) -> Vec<Value> {
+        self.decode(&self.encode(inputs))
+    }
+
+    fn parameters(&self) -> Vec<Value> {
+        let mut params = self.encoder.parameters();
+        params.extend(self.decoder.parameters());
+        params
+    }
+}
+
+/// Variational autoencoder (Kingma & Welling, 2014). The encoder outputs a
+/// diagonal Gaussian over a `latent`-wide code, `mu` then `log_var`; a code
+/// is drawn with `Value::gaussian` and decoded back to `nin` features. As a
+/// `Module` it decodes `mu`, the deterministic reconstruction. Train it with
+/// `loss`.
+pub struct VAE {
+    encoder: MLP,
+    decoder: MLP,
+    latent: usize,
+}
+
+impl VAE {
+    pub fn new(nin: u16, hidden: Vec<u16>, latent: u16, activation: Activation) -> Self {
+        Self::new_with_rng(nin, hidden, latent, activation, &mut rand::thread_rng())
+    }
+
+    pub fn new_seeded(
+        nin: u16,
+        hidden: Vec<u16>,
+        latent: u16,
+        activation: Activation,
+        seed: u64,
+    ) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+        Self::new_with_rng(nin, hidden, latent, activation, &mut rng)
+    }
+
+    pub fn new_with_rng<R: Rng + ?Sized>(
+        nin: u16,
+        hidden: Vec<u16>,
+        latent: u16,
+        activation: Activation,
+        rng: &mut R,
+    ) -> Self {
+        let down = [hidden.clone(), vec![2 * latent]].concat();
+        let up = [hidden.into_iter().rev().collect(), vec![nin]].concat();
+        VAE {
+            encoder: MLP::new_with_rng(nin, down, activation, rng),
+            decoder: MLP::new_with_rng(latent, up, activation, rng),
+            latent: latent as usize,
+        }
+    }
+
+    /// The mean and log-variance of the code distribution.
+    pub fn encode(&self, inputs: &[Value]) -> (Vec<Value>, Vec<Value>) {
+        let mut mu = self.encoder.forward(inputs);
+        let log_var = mu.split_off(self.latent);
+        (mu, log_var)
+    }
+
+    pub fn decode(&self, code: &[Value]) -> Vec<Value> {
+        self.decoder.forward(code)
+    }
+
+    /// Decodes a code drawn from the N(0, I) prior.
+    pub fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> Vec<f64> {
+        let code: Vec<Value> = (0..self.latent)
+            .map(|_| Value::constant(engine::standard_normal(rng)))
+            .collect();
+        self.decode(&code).iter().map(|v| v.get_data()).collect()
+    }
+
+    /// Negative ELBO averaged over `batch`: the squared reconstruction error
+    /// of a reparameterized sample, summed over features, plus `beta` times
+    /// `KL(q(z | x) || N(0, I)) = 0.5 * sum(mu^2 + exp(log_var) - 1 - log_var)`.
+    /// `beta = 1` is the standard VAE; larger values trade reconstruction for
+    /// a smoother latent space.
+    pub fn loss<R: Rng + ?Sized>(&self, batch: &[Vec<f64>], beta: f64, rng: &mut R) -> Value {
+        let losses: Vec<Value> = batch
+            .iter()
+            .map(|x| {
+                let inputs: Vec<Value> = x.iter().map(|&xi| Value::constant(xi)).collect();
+                let (mu, log_var) = self.encode(&inputs);
+                let code: Vec<Value> = mu
+                    .iter()
+                    .zip(log_var.iter())
+                    .map(|(m, lv)| Value::gaussian(m.clone(), lv.clone(), rng))
+                    .collect();
+                let squares: Vec<Value> = self
+                    .decode(&code)
+                    .iter()
+                    .zip(x.iter())
+                    .map(|(y, &t)| (y - t).powf(2.0))
+                    .collect();
+                let kl_terms: Vec<Value> = mu
+                    .iter()
+                    .zip(log_var.iter())
+                    .map(|(m, lv)| m * m + lv.clone().exp() - 1.0 - lv)
+                    .collect();
+                Value::sum(&squares) + Value::sum(&kl_terms) * (0.5 * beta)
+            })
+            .collect();
+        Value::mean(&losses)
+    }
+}
+
+impl Module for VAE {
+    fn forward(&self, inputs: &[Value]) -> Vec<Value> {
+        self.decode(&self.encode(inputs).0)
+    }
+
+    fn parameters(&self) -> Vec<Value> {
+        let mut params = self.encoder.parameters();
+        params.extend(self.decoder.parameters());
+        params
+    }
+}
+
+/// Gradient descent on the input of a fixed model: starting from `init`,
+/// takes `steps` steps of size `lr` that lower `objective(outputs)` and
+/// returns the final input. Negate the objective to ascend instead, e.g. to
+/// maximize a neuron for feature visualization. Weights are never updated
+/// and their grads are zeroed before returning.
+pub fn optimize_input<F>(model: &MLP, init: &[f64], objective: F, steps: usize, lr: f64) -> Vec<f64>
+where
+    F: Fn(&[Value]) -> Value,
+{
+    let mut x = init.to_vec();
+    for _ in 0..steps {
+        let inputs: Vec<Value> = x.iter().map(|&xi| Value::new(xi)).collect();
+        let loss = objective(&model.forward(&inputs));
+        model.zero_grad();
+        loss.backward();
+        for (xi, input) in x.iter_mut().zip(inputs.iter()) {
+            *xi -= lr * input.get_grad();
+        }
+    }
+    model.zero_grad();
+    x
+}
+
+/// Monte Carlo dropout (Gal & Ghahramani, 2016): runs `n_samples` stochastic
+/// forward passes and returns the per-output mean and variance as a cheap
+/// predictive uncertainty estimate. Dropout layers in `model` must be in
+/// training mode, otherwise every pass is identical and the variance is zero.
+pub fn predict_mc<M: Module + ?Sized>(
+    model: &M,
+    input: &[f64],
+    n_samples: usize,
+) -> (Vec<f64>, Vec<f64>) {
+    assert!(n_samples > 0, "MC dropout needs at least one sample.");
+    let inputs: Vec<Value> = input.iter().map(|&x| Value::constant(x)).collect();
+    let samples: Vec<Vec<f64>> = (0..n_samples)
+        .map(|_| {
+            model
+                .forward(&inputs)
+                .iter()
+                .map(|y| y.get_data())
+                .collect()
+        })
+        .collect();
+    mean_and_variance(&samples)
+}
+
+/// Per-column mean and (population) variance of a set of output vectors.
+fn mean_and_variance(samples: &[Vec<f64>]) -> (Vec<f64>, Vec<f64>) {
+    let n = samples.len() as f64;
+    let width = samples[0].len();
+    let mean: Vec<f64> = (0..width)
+        .map(|j| samples.iter().map(|s| s[j]).sum::<f64>() / n)
+        .collect();
+    let variance = (0..width)
+        .map(|j| {
+            samples
+                .iter()
+                .map(|s| (s[j] - mean[j]).powi(2))
+                .sum::<f64>()
+                / n
+        })
+        .collect();
+    (mean, variance)
+}
+
+/// Sums in the same pairwise order as `Value::sum`, so plain-`f64` forward
+/// passes round exactly like the graph.
+fn pairwise_sum(mut level: Vec<f64>) -> f64 {
+    if level.is_empty() {
+        return 0.0;
+    }
+    while level.len() > 1 {
+        level = level
+            .chunks(2)
+            .map(|pair| match pair {
+                [a, b] => a + b,
+                _ => pair[0],
+            })
+            .collect();
+    }
+    level[0]
+}
+
+/// Deep ensemble (Lakshminarayanan et al., 2017): independently trained
+/// members whose spread gives a predictive uncertainty estimate.
+#[derive(Default)]
+pub struct Ensemble {
+    members: Vec<Box<dyn Module>>,
+}
+
+impl Ensemble {
+    pub fn new() -> Self {
+        Ensemble { members: vec![] }
+    }
+
+    pub fn push<M: Module + 'static>(mut self, member: M) -> Self {
+        self.members.push(Box::new(member));
+        self
+    }
+
+    fn member_outputs(&self, input: &[f64]) -> Vec<Vec<f64>> {
+        assert!(!self.members.is_empty(), "Ensemble has no members.");
+        let inputs: Vec<Value> = input.iter().map(|&x| Value::constant(x)).collect();
+        self.members
+            .iter()
+            .map(|m| m.forward(&inputs).iter().map(|y| y.get_data()).collect())
+            .collect()
+    }
+
+    /// Per-output mean and variance across members.
+    pub fn predict(&self, input: &[f64]) -> (Vec<f64>, Vec<f64>) {
+        mean_and_variance(&self.member_outputs(input))
+    }
+
+    /// Fraction of members whose argmax output differs from the ensemble's
+    /// majority vote; 0 when all members agree on the class.
+    pub fn disagreement(&self, input: &[f64]) -> f64 {
+        let votes: Vec<usize> = self
+            .member_outputs(input)
+            .iter()
+            .map(|o| {
+                (0..o.len())
+                    .max_by(|&a, &b| o[a].total_cmp(&o[b]))
+                    .unwrap_or(0)
+            })
+            .collect();
+        let mut counts = vec![0; votes.iter().max().map_or(0, |&v| v + 1)];
+        for &v in votes.iter() {
+            counts[v] += 1;
+        }
+        let majority = counts.iter().max().copied().unwrap_or(0);
+        1.0 - majority as f64 / votes.len() as f64
+    }
+}
+
+/// The forward pass averages the members' outputs.
+impl Module for Ensemble {
+    fn forward(&self, inputs: &[Value]) -> Vec<Value> {
+        assert!(!self.members.is_empty(), "Ensemble has no members.");
+        let outputs: Vec<Vec<Value>> = self.members.iter().map(|m| m.forward(inputs)).collect();
+        (0..outputs[0].len())
+            .map(|j| {
+                let column: Vec<Value> = outputs.iter().map(|o| o[j].clone()).collect();
+                Value::mean(&column)
+            })
+            .collect()
+    }
+
+    fn parameters(&self) -> Vec<Value> {
+        self.members
+            .iter()
+            .flat_map(|member| member.parameters())
+            .collect()
+    }
+
+    fn set_training(&mut self, training: bool) {
+        for member in self.members.iter_mut() {
+            member.set_training(training);
+        }
+    }
+}
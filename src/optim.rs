@@ -0,0 +1,244 @@
+use crate::engine::Value;
+use crate::nn::Constraint;
+
+pub trait Optimizer {
+    /// Updates every parameter from its current gradient.
+    fn step(&mut self);
+
+    fn zero_grad(&self);
+
+    fn lr(&self) -> f64;
+
+    fn set_lr(&mut self, lr: f64);
+
+    /// Called by `Trainer` at the end of every epoch. Does nothing by
+    /// default; `LrScheduler` advances its schedule.
+    fn end_epoch(&mut self) {}
+}
+
+fn zero_grads(params: &[Value]) {
+    for p in params {
+        p.update_grad(0.0);
+    }
+}
+
+/// Clamps every stored gradient to `[-max, max]`. Call between `backward`
+/// and the optimizer step.
+pub fn clip_grad_value(params: &[Value], max: f64) {
+    assert!(max >= 0.0, "Clip value must be non-negative.");
+    for p in params {
+        p.update_grad(p.get_grad().clamp(-max, max));
+    }
+}
+
+/// Joint L2 norm of the stored gradients.
+pub fn grad_norm(params: &[Value]) -> f64 {
+    params
+        .iter()
+        .map(|p| p.get_grad().powi(2))
+        .sum::<f64>()
+        .sqrt()
+}
+
+/// Rescales the gradients so their joint L2 norm is at most `max_norm`,
+/// keeping their direction. Returns the norm before clipping, which is worth
+/// logging to spot exploding gradients.
+pub fn clip_grad_norm(params: &[Value], max_norm: f64) -> f64 {
+    assert!(max_norm >= 0.0, "Max norm must be non-negative.");
+    let norm = grad_norm(params);
+    if norm > max_norm {
+        let scale = max_norm / norm;
+        for p in params {
+            p.update_grad(p.get_grad() * scale);
+        }
+    }
+    norm
+}
+
+/// Stochastic gradient descent with optional (heavy-ball) momentum and L2
+/// weight decay.
+pub struct SGD {
+    params: Vec<Value>,
+    lr: f64,
+    momentum: f64,
+    weight_decay: f64,
+    velocity: Vec<f64>,
+}
+
+impl SGD {
+    pub fn new(params: Vec<Value>, lr: f64) -> Self {
+        let velocity = vec![0.0; params.len()];
+        SGD {
+            params,
+            lr,
+            momentum: 0.0,
+            weight_decay: 0.0,
+            velocity,
+        }
+    }
+
+    pub fn momentum(mut self, momentum: f64) -> Self {
+        self.momentum = momentum;
+        self
+    }
+
+    pub fn weight_decay(mut self, weight_decay: f64) -> Self {
+        self.weight_decay = weight_decay;
+        self
+    }
+}
+
+impl Optimizer for SGD {
+    fn step(&mut self) {
+        for (p, v) in self.params.iter().zip(self.velocity.iter_mut()) {
+            let g = p.get_grad() + self.weight_decay * p.get_data();
+            *v = self.momentum * *v + g;
+            p.update_data(p.get_data() - self.lr * *v);
+        }
+    }
+
+    fn zero_grad(&self) {
+        zero_grads(&self.params);
+    }
+
+    fn lr(&self) -> f64 {
+        self.lr
+    }
+
+    fn set_lr(&mut self, lr: f64) {
+        self.lr = lr;
+    }
+}
+
+/// Adam (Kingma & Ba, 2015) with bias-corrected moment estimates and
+/// optional L2 weight decay folded into the gradient.
+pub struct Adam {
+    params: Vec<Value>,
+    lr: f64,
+    beta1: f64,
+    beta2: f64,
+    eps: f64,
+    weight_decay: f64,
+    m: Vec<f64>,
+    v: Vec<f64>,
+    t: i32,
+}
+
+impl Adam {
+    pub fn new(params: Vec<Value>, lr: f64) -> Self {
+        let n = params.len();
+        Adam {
+            params,
+            lr,
+            beta1: 0.9,
+            beta2: 0.999,
+            eps: 1e-8,
+            weight_decay: 0.0,
+            m: vec![0.0; n],
+            v: vec![0.0; n],
+            t: 0,
+        }
+    }
+
+    pub fn betas(mut self, beta1: f64, beta2: f64) -> Self {
+        self.beta1 = beta1;
+        self.beta2 = beta2;
+        self
+    }
+
+    pub fn eps(mut self, eps: f64) -> Self {
+        self.eps = eps;
+        self
+    }
+
+    pub fn weight_decay(mut self, weight_decay: f64) -> Self {
+        self.weight_decay = weight_decay;
+        self
+    }
+}
+
+impl Optimizer for Adam {
+    fn step(&mut self) {
+        self.t += 1;
+        let bias1 = 1.0 - self.beta1.powi(self.t);
+        let bias2 = 1.0 - self.beta2.powi(self.t);
+        for ((p, m), v) in self
+            .params
+            .iter()
+            .zip(self.m.iter_mut())
+            .zip(self.v.iter_mut())
+        {
+            let g = p.get_grad() + self.weight_decay * p.get_data();
+            *m = self.beta1 * *m + (1.0 - self.beta1) * g;
+            *v = self.beta2 * *v + (1.0 - self.beta2) * g * g;
+            let m_hat = *m / bias1;
+            let v_hat = *v / bias2;
+            p.update_data(p.get_data() - self.lr * m_hat / (v_hat.sqrt() + self.eps));
+        }
+    }
+
+    fn zero_grad(&self) {
+        zero_grads(&self.params);
+    }
+
+    fn lr(&self) -> f64 {
+        self.lr
+    }
+
+    fn set_lr(&mut self, lr: f64) {
+        self.lr = lr;
+    }
+}
+
+/// Wraps an optimizer and applies a `Constraint` after every step, so the
+/// constraint holds throughout training, including under `Trainer`.
+/// `weights` holds one incoming weight vector per neuron, as returned by
+/// `MLP::weight_vectors`.
+pub struct Constrained<O: Optimizer> {
+    optimizer: O,
+    weights: Vec<Vec<Value>>,
+    constraint: Constraint,
+}
+
+impl<O: Optimizer> Constrained<O> {
+    pub fn new(optimizer: O, weights: Vec<Vec<Value>>, constraint: Constraint) -> Self {
+        Constrained {
+            optimizer,
+            weights,
+            constraint,
+        }
+    }
+
+    pub fn optimizer(&self) -> &O {
+        &self.optimizer
+    }
+
+    pub fn into_inner(self) -> O {
+        self.optimizer
+    }
+}
+
+impl<O: Optimizer> Optimizer for Constrained<O> {
+    fn step(&mut self) {
+        self.optimizer.step();
+        for weights in self.weights.iter() {
+            self.constraint.apply(weights);
+        }
+    }
+
+    fn zero_grad(&self) {
+        self.optimizer.zero_grad();
+    }
+
+    fn lr(&self) -> f64 {
+        self.optimizer.lr()
+    }
+
+    fn set_lr(&mut self, lr: f64) {
+        self.optimizer.set_lr(lr);
+    }
+
+    fn end_epoch(&mut self) {
+        self.optimizer.end_epoch();
+    }
+}
@@ -0,0 +1,43 @@
+use crate::engine::Value;
+use crate::nn::MLP;
+
+fn output(model: &MLP, x: f64) -> Value {
+    let y = model.call(&[x]);
+    assert_eq!(y.len(), 1, "PINN helpers expect a single-output model.");
+    y.into_iter().next().unwrap()
+}
+
+/// `y(x)` and `dy/dx` of a single-input, single-output model. The
+/// derivative is exact, carried through the network by
+/// `MLP::call_with_tangent`, and both results stay differentiable in the
+/// weights.
+pub fn value_and_derivative(model: &MLP, x: f64) -> (Value, Value) {
+    let (mut y, mut dy) = model.call_with_tangent(&[x], &[1.0]);
+    assert_eq!(y.len(), 1, "PINN helpers expect a single-output model.");
+    (y.remove(0), dy.remove(0))
+}
+
+/// Mean squared residual of the ODE `residual(x, y, dy/dx) = 0` over the
+/// collocation points `xs`. For `dy/dx = -y` pass `|_, y, dy| dy + y`.
+pub fn ode_residual_loss<F>(model: &MLP, xs: &[f64], residual: F) -> Value
+where
+    F: Fn(f64, &Value, &Value) -> Value,
+{
+    assert!(!xs.is_empty(), "Need at least one collocation point.");
+    let squares: Vec<Value> = xs
+        .iter()
+        .map(|&x| {
+            let (y, dy) = value_and_derivative(model, x);
+            let r = residual(x, &y, &dy);
+            &r * &r
+        })
+        .collect();
+    Value::mean(&squares)
+}
+
+/// Squared error of the condition `y(x0) = y0`; add it to the residual loss
+/// to pin down the solution.
+pub fn initial_condition_loss(model: &MLP, x0: f64, y0: f64) -> Value {
+    let diff = output(model, x0) - y0;
+    &diff * &diff
+}
@@ -0,0 +1,461 @@
+use crate::data::{Impute, Imputer};
+use crate::engine::standard_normal;
+use rand::seq::SliceRandom;
+use rand::Rng;
+use std::collections::HashMap;
+use std::f64;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+const ENCODER_HEADER: &str = "micrograd-categorical-encoder v1";
+const PIPELINE_HEADER: &str = "micrograd-preprocess v1";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Encoding {
+    /// One feature per column holding the category's index.
+    Ordinal,
+    /// One 0/1 feature per category.
+    OneHot,
+}
+
+/// What to do with a category that wasn't seen during `fit`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Unknown {
+    Error,
+    /// Encoded as all zeros (one-hot) or -1 (ordinal).
+    Ignore,
+}
+
+fn invalid_data(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Fit/transform encoder for string categorical columns. Categories are
+/// stored sorted so encodings don't depend on row order, and the fitted
+/// encoder can be saved next to a model to encode inference inputs the same
+/// way.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CategoricalEncoder {
+    encoding: Encoding,
+    unknown: Unknown,
+    categories: Vec<Vec<String>>,
+}
+
+impl CategoricalEncoder {
+    /// `rows` holds the categorical columns of each sample.
+    pub fn fit(rows: &[Vec<String>], encoding: Encoding, unknown: Unknown) -> Self {
+        let width = rows.first().map_or(0, |row| row.len());
+        let categories = (0..width)
+            .map(|j| {
+                let mut column: Vec<String> = rows.iter().map(|row| row[j].clone()).collect();
+                column.sort();
+                column.dedup();
+                column
+            })
+            .collect();
+        CategoricalEncoder {
+            encoding,
+            unknown,
+            categories,
+        }
+    }
+
+    pub fn categories(&self) -> &[Vec<String>] {
+        &self.categories
+    }
+
+    /// Number of features a transformed row has.
+    pub fn n_outputs(&self) -> usize {
+        match self.encoding {
+            Encoding::Ordinal => self.categories.len(),
+            Encoding::OneHot => self.categories.iter().map(|c| c.len()).sum(),
+        }
+    }
+
+    pub fn transform_row(&self, row: &[String]) -> io::Result<Vec<f64>> {
+        assert_eq!(
+            row.len(),
+            self.categories.len(),
+            "Row width must match the fitted columns."
+        );
+        let mut out = Vec::with_capacity(self.n_outputs());
+        for (j, (value, categories)) in row.iter().zip(self.categories.iter()).enumerate() {
+            let index = categories.binary_search(value).ok();
+            if index.is_none() && self.unknown == Unknown::Error {
+                return Err(invalid_data(format!(
+                    "unknown category {:?} in column {}",
+                    value, j
+                )));
+            }
+            match self.encoding {
+                Encoding::Ordinal => out.push(index.map_or(-1.0, |i| i as f64)),
+                Encoding::OneHot => {
+                    let mut one_hot = vec![0.0; categories.len()];
+                    if let Some(i) = index {
+                        one_hot[i] = 1.0;
+                    }
+                    out.extend(one_hot);
+                }
+            }
+        }
+        Ok(out)
+    }
+
+    pub fn transform(&self, rows: &[Vec<String>]) -> io::Result<Vec<Vec<f64>>> {
+        rows.iter().map(|row| self.transform_row(row)).collect()
+    }
+
+    /// Text format: a header line, the encoding and unknown policy, then one
+    /// tab-separated line of categories per column.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let encoding = match self.encoding {
+            Encoding::Ordinal => "ordinal",
+            Encoding::OneHot => "onehot",
+        };
+        let unknown = match self.unknown {
+            Unknown::Error => "error",
+            Unknown::Ignore => "ignore",
+        };
+        let mut out = format!("{}\n{}\t{}\n", ENCODER_HEADER, encoding, unknown);
+        for categories in self.categories.iter() {
+            if categories
+                .iter()
+                .any(|c| c.contains('\t') || c.contains('\n'))
+            {
+                return Err(invalid_data(
+                    "categories containing tabs or newlines can't be saved".to_string(),
+                ));
+            }
+            out.push_str(&categories.join("\t"));
+            out.push('\n');
+        }
+        fs::write(path, out)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let text = fs::read_to_string(path)?;
+        let mut lines = text.lines();
+        if lines.next() != Some(ENCODER_HEADER) {
+            return Err(invalid_data("not a categorical encoder file".to_string()));
+        }
+        let settings = lines.next().unwrap_or("");
+        let (encoding, unknown) = settings
+            .split_once('\t')
+            .ok_or_else(|| invalid_data(format!("invalid settings line {:?}", settings)))?;
+        let encoding = match encoding {
+            "ordinal" => Encoding::Ordinal,
+            "onehot" => Encoding::OneHot,
+            other => return Err(invalid_data(format!("unknown encoding {:?}", other))),
+        };
+        let unknown = match unknown {
+            "error" => Unknown::Error,
+            "ignore" => Unknown::Ignore,
+            other => return Err(invalid_data(format!("unknown policy {:?}", other))),
+        };
+        let categories = lines
+            .map(|line| line.split('\t').map(|c| c.to_string()).collect())
+            .collect();
+        Ok(CategoricalEncoder {
+            encoding,
+            unknown,
+            categories,
+        })
+    }
+}
+
+/// Target (mean) encoding for high-cardinality categorical columns: each
+/// category becomes one feature holding the smoothed mean target of the
+/// training samples in it,
+/// `(sum + smoothing * global_mean) / (count + smoothing)`.
+/// Unseen categories encode as the global mean.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TargetEncoder {
+    smoothing: f64,
+    global_mean: f64,
+    means: Vec<HashMap<String, f64>>,
+}
+
+impl TargetEncoder {
+    /// Fits on every row. Use this encoder for validation and inference
+    /// data; encode the training rows with `fit_transform_kfold` instead,
+    /// since encoding a row with its own target leaks the label.
+    pub fn fit(rows: &[Vec<String>], targets: &[f64], smoothing: f64) -> Self {
+        assert_eq!(
+            rows.len(),
+            targets.len(),
+            "Number of rows must match number of targets."
+        );
+        let global_mean = if targets.is_empty() {
+            0.0
+        } else {
+            targets.iter().sum::<f64>() / targets.len() as f64
+        };
+        let width = rows.first().map_or(0, |row| row.len());
+        let means = (0..width)
+            .map(|j| {
+                let mut stats: HashMap<String, (f64, f64)> = HashMap::new();
+                for (row, &y) in rows.iter().zip(targets.iter()) {
+                    let entry = stats.entry(row[j].clone()).or_insert((0.0, 0.0));
+                    entry.0 += y;
+                    entry.1 += 1.0;
+                }
+                stats
+                    .into_iter()
+                    .map(|(category, (sum, count))| {
+                        let mean = (sum + smoothing * global_mean) / (count + smoothing);
+                        (category, mean)
+                    })
+                    .collect()
+            })
+            .collect();
+        TargetEncoder {
+            smoothing,
+            global_mean,
+            means,
+        }
+    }
+
+    /// Returns the encoder fitted on all rows together with out-of-fold
+    /// encodings of those rows: rows are split into `k` random folds and
+    /// each fold is encoded by an encoder fitted on the other `k - 1`.
+    pub fn fit_transform_kfold<R: Rng + ?Sized>(
+        rows: &[Vec<String>],
+        targets: &[f64],
+        smoothing: f64,
+        k: usize,
+        rng: &mut R,
+    ) -> (Self, Vec<Vec<f64>>) {
+        assert!(k >= 2, "K-fold target encoding needs at least two folds.");
+        let mut order: Vec<usize> = (0..rows.len()).collect();
+        order.shuffle(rng);
+        let mut fold_of = vec![0; rows.len()];
+        for (position, &i) in order.iter().enumerate() {
+            fold_of[i] = position % k;
+        }
+
+        let mut encoded = vec![vec![]; rows.len()];
+        for fold in 0..k {
+            let (train_rows, train_targets): (Vec<Vec<String>>, Vec<f64>) = (0..rows.len())
+                .filter(|&i| fold_of[i] != fold)
+                .map(|i| (rows[i].clone(), targets[i]))
+                .unzip();
+            let encoder = TargetEncoder::fit(&train_rows, &train_targets, smoothing);
+            for i in (0..rows.len()).filter(|&i| fold_of[i] == fold) {
+                encoded[i] = encoder.transform_row(&rows[i]);
+            }
+        }
+        (TargetEncoder::fit(rows, targets, smoothing), encoded)
+    }
+
+    pub fn transform_row(&self, row: &[String]) -> Vec<f64> {
+        assert_eq!(
+            row.len(),
+            self.means.len(),
+            "Row width must match the fitted columns."
+        );
+        row.iter()
+            .zip(self.means.iter())
+            .map(|(value, means)| means.get(value).copied().unwrap_or(self.global_mean))
+            .collect()
+    }
+
+    pub fn transform(&self, rows: &[Vec<String>]) -> Vec<Vec<f64>> {
+        rows.iter().map(|row| self.transform_row(row)).collect()
+    }
+}
+
+/// Random Fourier feature map (Tancik et al., 2020): `x` becomes
+/// `[sin(2 pi B x), cos(2 pi B x)]` with the rows of `B` drawn from
+/// `N(0, scale^2)`. Larger scales let small MLPs fit higher-frequency targets.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FourierFeatures {
+    frequencies: Vec<Vec<f64>>,
+}
+
+impl FourierFeatures {
+    pub fn new<R: Rng + ?Sized>(
+        n_inputs: usize,
+        n_frequencies: usize,
+        scale: f64,
+        rng: &mut R,
+    ) -> Self {
+        let frequencies = (0..n_frequencies)
+            .map(|_| {
+                (0..n_inputs)
+                    .map(|_| scale * standard_normal(rng))
+                    .collect()
+            })
+            .collect();
+        FourierFeatures { frequencies }
+    }
+
+    pub fn n_outputs(&self) -> usize {
+        2 * self.frequencies.len()
+    }
+
+    pub fn transform_row(&self, row: &[f64]) -> Vec<f64> {
+        let projections: Vec<f64> = self
+            .frequencies
+            .iter()
+            .map(|b| {
+                assert_eq!(b.len(), row.len(), "Row width must match n_inputs.");
+                2.0 * f64::consts::PI
+                    * b.iter()
+                        .zip(row.iter())
+                        .map(|(bj, xj)| bj * xj)
+                        .sum::<f64>()
+            })
+            .collect();
+        projections
+            .iter()
+            .map(|p| p.sin())
+            .chain(projections.iter().map(|p| p.cos()))
+            .collect()
+    }
+
+    pub fn transform(&self, rows: &[Vec<f64>]) -> Vec<Vec<f64>> {
+        rows.iter().map(|row| self.transform_row(row)).collect()
+    }
+}
+
+/// Powers `x_j, x_j^2, ..., x_j^degree` of every feature, grouped by feature
+/// (no cross terms).
+pub fn polynomial_features(row: &[f64], degree: u32) -> Vec<f64> {
+    row.iter()
+        .flat_map(|&x| (1..=degree).map(move |d| x.powi(d as i32)))
+        .collect()
+}
+
+/// Numeric preprocessing fitted on training inputs and saved next to a model,
+/// so `micrograd predict --pipeline` prepares inference rows the same way:
+/// missing values are filled in as by an `Imputer`, then every feature is
+/// standardized to zero mean and unit variance.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pipeline {
+    fill: Option<Vec<f64>>,
+    mean: Vec<f64>,
+    std: Vec<f64>,
+}
+
+impl Pipeline {
+    /// Without `impute`, missing values pass through as NaN. The statistics
+    /// skip missing entries; constant columns keep a scale of 1.
+    pub fn fit(inputs: &[Vec<f64>], impute: Option<Impute>) -> Self {
+        assert!(
+            impute != Some(Impute::DropRow),
+            "A pipeline can't drop rows; impute with Mean, Median or Constant."
+        );
+        let fill = impute.map(|strategy| Imputer::fit(inputs, strategy).fill_values().to_vec());
+        let width = inputs.first().map_or(0, |row| row.len());
+        let (mut mean, mut std) = (vec![0.0; width], vec![1.0; width]);
+        for j in 0..width {
+            let observed: Vec<f64> = inputs
+                .iter()
+                .map(|row| match &fill {
+                    Some(fill) if row[j].is_nan() => fill[j],
+                    _ => row[j],
+                })
+                .filter(|x| !x.is_nan())
+                .collect();
+            if observed.is_empty() {
+                continue;
+            }
+            let n = observed.len() as f64;
+            mean[j] = observed.iter().sum::<f64>() / n;
+            let var = observed.iter().map(|x| (x - mean[j]).powi(2)).sum::<f64>() / n;
+            if var > 0.0 {
+                std[j] = var.sqrt();
+            }
+        }
+        Pipeline { fill, mean, std }
+    }
+
+    pub fn n_features(&self) -> usize {
+        self.mean.len()
+    }
+
+    pub fn transform_row(&self, row: &[f64]) -> Vec<f64> {
+        assert_eq!(
+            row.len(),
+            self.mean.len(),
+            "Row width must match the fitted data."
+        );
+        row.iter()
+            .enumerate()
+            .map(|(j, &x)| {
+                let x = match &self.fill {
+                    Some(fill) if x.is_nan() => fill[j],
+                    _ => x,
+                };
+                (x - self.mean[j]) / self.std[j]
+            })
+            .collect()
+    }
+
+    pub fn transform(&self, rows: &[Vec<f64>]) -> Vec<Vec<f64>> {
+        rows.iter().map(|row| self.transform_row(row)).collect()
+    }
+
+    /// Text format: a header line, then `fill` (or `fill none`), `mean` and
+    /// `std` lines of space-separated values. Values round-trip exactly.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let join = |row: &[f64]| {
+            row.iter()
+                .map(|x| x.to_string())
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        let fill = self.fill.as_deref().map_or("none".to_string(), join);
+        let out = format!(
+            "{}\nfill {}\nmean {}\nstd {}\n",
+            PIPELINE_HEADER,
+            fill,
+            join(&self.mean),
+            join(&self.std)
+        );
+        fs::write(path, out)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let text = fs::read_to_string(path)?;
+        let mut lines = text.lines();
+        if lines.next() != Some(PIPELINE_HEADER) {
+            return Err(invalid_data(
+                "not a preprocessing pipeline file".to_string(),
+            ));
+        }
+        let mut field = |name: &str| -> io::Result<&str> {
+            let line = lines.next().unwrap_or("");
+            match line.split_once(' ') {
+                Some((key, values)) if key == name => Ok(values),
+                _ if line == name => Ok(""),
+                _ => Err(invalid_data(format!(
+                    "expected a {} line, got {:?}",
+                    name, line
+                ))),
+            }
+        };
+        let parse = |values: &str| {
+            values
+                .split_whitespace()
+                .map(|v| {
+                    v.parse()
+                        .map_err(|_| invalid_data(format!("invalid number {:?}", v)))
+                })
+                .collect::<io::Result<Vec<f64>>>()
+        };
+        let fill = match field("fill")? {
+            "none" => None,
+            values => Some(parse(values)?),
+        };
+        let mean = parse(field("mean")?)?;
+        let std = parse(field("std")?)?;
+        if std.len() != mean.len() || fill.as_ref().is_some_and(|f| f.len() != mean.len()) {
+            return Err(invalid_data(
+                "fill, mean and std must have the same length".to_string(),
+            ));
+        }
+        Ok(Pipeline { fill, mean, std })
+    }
+}
@@ -0,0 +1,183 @@
+use crate::diagnostics::per_sample_grads;
+use crate::engine::{standard_normal, Value};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+/// Magnitude pruning with rewinding for lottery-ticket experiments.
+///
+/// Create it at initialization, then alternate training, `prune` and
+/// `rewind`. During training call `apply_mask` after every update so pruned
+/// weights stay at zero.
+pub struct LotteryTicket {
+    initial: Vec<f64>,
+    mask: Vec<bool>,
+}
+
+impl LotteryTicket {
+    pub fn new(params: &[Value]) -> Self {
+        LotteryTicket {
+            initial: params.iter().map(|p| p.get_data()).collect(),
+            mask: vec![true; params.len()],
+        }
+    }
+
+    /// Prunes `fraction` of the surviving parameters with the smallest
+    /// magnitude.
+    pub fn prune(&mut self, params: &[Value], fraction: f64) {
+        assert_eq!(
+            self.mask.len(),
+            params.len(),
+            "Parameter count must match the snapshot."
+        );
+        let mut surviving: Vec<usize> = (0..params.len()).filter(|&i| self.mask[i]).collect();
+        surviving.sort_by(|&i, &j| {
+            params[i]
+                .get_data()
+                .abs()
+                .total_cmp(&params[j].get_data().abs())
+        });
+        let n_prune = (surviving.len() as f64 * fraction).round() as usize;
+        for &i in surviving.iter().take(n_prune) {
+            self.mask[i] = false;
+        }
+        self.apply_mask(params);
+    }
+
+    /// Resets surviving parameters to their initial values.
+    pub fn rewind(&self, params: &[Value]) {
+        for ((p, &init), &keep) in params.iter().zip(self.initial.iter()).zip(self.mask.iter()) {
+            p.update_data(if keep { init } else { 0.0 });
+        }
+    }
+
+    pub fn apply_mask(&self, params: &[Value]) {
+        for (p, &keep) in params.iter().zip(self.mask.iter()) {
+            if !keep {
+                p.update_data(0.0);
+            }
+        }
+    }
+
+    /// Fraction of parameters pruned so far.
+    pub fn sparsity(&self) -> f64 {
+        self.mask.iter().filter(|&&keep| !keep).count() as f64 / self.mask.len() as f64
+    }
+}
+
+/// `lambda * sum(p^2)` over `params`, to add to the loss. Its gradient pulls
+/// each parameter toward zero by `2 * lambda * p` (weight decay).
+pub fn l2_penalty(params: &[Value], lambda: f64) -> Value {
+    let squares: Vec<Value> = params.iter().map(|p| p.clone().powf(2.0)).collect();
+    Value::sum(&squares) * lambda
+}
+
+/// `lambda * sum(|p|)` over `params`, to add to the loss. Pushes parameters
+/// to exactly zero, so it favors sparse weights.
+pub fn l1_penalty(params: &[Value], lambda: f64) -> Value {
+    let magnitudes: Vec<Value> = params.iter().map(|p| p.clone().abs()).collect();
+    Value::sum(&magnitudes) * lambda
+}
+
+/// Gaussian weight noise regularizer.
+///
+/// Call `perturb` before the forward pass and `restore` after backward but
+/// before the parameter update: gradients are taken at the noisy weights
+/// while the update is applied to the clean ones, and fresh noise is drawn
+/// every step. `Trainer::weight_noise` does this for each batch.
+pub struct WeightNoise {
+    std: f64,
+    clean: Vec<f64>,
+    rng: StdRng,
+}
+
+impl WeightNoise {
+    pub fn new(std: f64) -> Self {
+        Self::with_rng(std, StdRng::from_entropy())
+    }
+
+    /// Draws the noise reproducibly from `seed`.
+    pub fn new_seeded(std: f64, seed: u64) -> Self {
+        Self::with_rng(std, StdRng::seed_from_u64(seed))
+    }
+
+    fn with_rng(std: f64, rng: StdRng) -> Self {
+        assert!(std >= 0.0, "Noise standard deviation must be non-negative.");
+        WeightNoise {
+            std,
+            clean: vec![],
+            rng,
+        }
+    }
+
+    pub fn perturb(&mut self, params: &[Value]) {
+        self.clean = params.iter().map(|p| p.get_data()).collect();
+        for p in params {
+            p.update_data(p.get_data() + self.std * standard_normal(&mut self.rng));
+        }
+    }
+
+    pub fn restore(&mut self, params: &[Value]) {
+        assert_eq!(
+            self.clean.len(),
+            params.len(),
+            "restore() must follow perturb() on the same parameters."
+        );
+        for (p, &clean) in params.iter().zip(self.clean.iter()) {
+            p.update_data(clean);
+        }
+        self.clean.clear();
+    }
+}
+
+/// Elastic weight consolidation: a quadratic penalty that keeps parameters
+/// important to a previous task close to the values learned for it.
+#[allow(clippy::upper_case_acronyms)]
+pub struct EWC {
+    anchors: Vec<f64>,
+    importances: Vec<f64>,
+    lambda: f64,
+}
+
+impl EWC {
+    /// Snapshots `params` after training on the previous task and estimates
+    /// their importances as mean squared gradients of the per-sample `losses`
+    /// (the diagonal of the empirical Fisher information).
+    pub fn new(params: &[Value], losses: Vec<Value>, lambda: f64) -> Self {
+        let grads = per_sample_grads(params, losses);
+        let mut importances = vec![0.0; params.len()];
+        for g in grads.iter() {
+            for (importance, gj) in importances.iter_mut().zip(g.iter()) {
+                *importance += gj.powi(2);
+            }
+        }
+        if !grads.is_empty() {
+            for importance in importances.iter_mut() {
+                *importance /= grads.len() as f64;
+            }
+        }
+
+        let anchors = params.iter().map(|p| p.get_data()).collect();
+        EWC {
+            anchors,
+            importances,
+            lambda,
+        }
+    }
+
+    /// `lambda / 2 * sum_i F_i * (p_i - p*_i)^2`, to be added to the new
+    /// task's loss.
+    pub fn penalty(&self, params: &[Value]) -> Value {
+        assert_eq!(
+            self.anchors.len(),
+            params.len(),
+            "Parameter count must match the consolidated task."
+        );
+        let terms: Vec<Value> = params
+            .iter()
+            .zip(self.anchors.iter())
+            .zip(self.importances.iter())
+            .map(|((p, &anchor), &importance)| (p - anchor).powf(2.0) * importance)
+            .collect();
+        Value::sum(&terms) * (0.5 * self.lambda)
+    }
+}
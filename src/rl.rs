@@ -0,0 +1,543 @@
+use crate::engine::Value;
+use crate::loss::softmax_cross_entropy;
+use crate::nn::{Module, MLP};
+use crate::optim::Optimizer;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+/// What an environment returns for one action.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Step {
+    pub state: Vec<f64>,
+    pub reward: f64,
+    /// The episode has ended; call `reset` before stepping again.
+    pub done: bool,
+}
+
+/// An episodic environment with actions `0..n_actions()`.
+pub trait Environment {
+    fn n_actions(&self) -> usize;
+
+    /// Starts a new episode and returns its first state.
+    fn reset(&mut self) -> Vec<f64>;
+
+    fn step(&mut self, action: usize) -> Step;
+}
+
+// Constants of the classic formulation (Barto, Sutton & Anderson, 1983).
+const GRAVITY: f64 = 9.8;
+const CART_MASS: f64 = 1.0;
+const POLE_MASS: f64 = 0.1;
+const POLE_HALF_LENGTH: f64 = 0.5;
+const FORCE: f64 = 10.0;
+const TAU: f64 = 0.02;
+const THETA_LIMIT: f64 = 12.0 * std::f64::consts::PI / 180.0;
+const X_LIMIT: f64 = 2.4;
+
+/// Balancing a pole on a cart. The state is `(x, x_dot, theta, theta_dot)`,
+/// action 0 pushes the cart left and 1 right, and every step earns a reward
+/// of 1 until the pole tips past 12 degrees, the cart leaves `[-2.4, 2.4]`
+/// or `max_steps` steps pass.
+pub struct CartPole {
+    state: [f64; 4],
+    steps: usize,
+    max_steps: usize,
+    rng: StdRng,
+}
+
+impl CartPole {
+    /// Episodes start from a state drawn from `U(-0.05, 0.05)`, seeded by
+    /// `seed`, and last at most 200 steps.
+    pub fn new(seed: u64) -> Self {
+        CartPole {
+            state: [0.0; 4],
+            steps: 0,
+            max_steps: 200,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    pub fn max_steps(mut self, max_steps: usize) -> Self {
+        self.max_steps = max_steps;
+        self
+    }
+}
+
+impl Environment for CartPole {
+    fn n_actions(&self) -> usize {
+        2
+    }
+
+    fn reset(&mut self) -> Vec<f64> {
+        for s in self.state.iter_mut() {
+            *s = self.rng.gen_range(-0.05..0.05);
+        }
+        self.steps = 0;
+        self.state.to_vec()
+    }
+
+    fn step(&mut self, action: usize) -> Step {
+        assert!(action < 2, "CartPole actions are 0 (left) and 1 (right).");
+        let [x, x_dot, theta, theta_dot] = self.state;
+        let force = if action == 1 { FORCE } else { -FORCE };
+        let (sin, cos) = theta.sin_cos();
+        let total_mass = CART_MASS + POLE_MASS;
+        let pole_moment = POLE_MASS * POLE_HALF_LENGTH;
+
+        let temp = (force + pole_moment * theta_dot * theta_dot * sin) / total_mass;
+        let theta_acc = (GRAVITY * sin - cos * temp)
+            / (POLE_HALF_LENGTH * (4.0 / 3.0 - POLE_MASS * cos * cos / total_mass));
+        let x_acc = temp - pole_moment * theta_acc * cos / total_mass;
+        self.state = [
+            x + TAU * x_dot,
+            x_dot + TAU * x_acc,
+            theta + TAU * theta_dot,
+            theta_dot + TAU * theta_acc,
+        ];
+        self.steps += 1;
+
+        let [x, _, theta, _] = self.state;
+        Step {
+            state: self.state.to_vec(),
+            reward: 1.0,
+            done: x.abs() > X_LIMIT || theta.abs() > THETA_LIMIT || self.steps >= self.max_steps,
+        }
+    }
+}
+
+/// A stochastic policy: the MLP's outputs are logits, one per action, and
+/// actions are drawn from their softmax.
+pub struct Policy {
+    net: MLP,
+}
+
+impl Policy {
+    pub fn new(net: MLP) -> Self {
+        Policy { net }
+    }
+
+    pub fn net(&self) -> &MLP {
+        &self.net
+    }
+
+    pub fn probabilities(&self, state: &[f64]) -> Vec<f64> {
+        Value::softmax(&self.net.call(state))
+            .iter()
+            .map(|p| p.get_data())
+            .collect()
+    }
+
+    /// `ln pi(action | state)`, differentiable in the weights.
+    pub fn log_prob(&self, state: &[f64], action: usize) -> Value {
+        -softmax_cross_entropy(&self.net.call(state), action)
+    }
+
+    /// Draws an action and returns it with its log-probability.
+    pub fn sample<R: Rng + ?Sized>(&self, state: &[f64], rng: &mut R) -> (usize, Value) {
+        let logits = self.net.call(state);
+        let probs = Value::softmax(&logits);
+        let u: f64 = rng.gen();
+        let mut cumulative = 0.0;
+        // Falls back to the last action if rounding leaves the sum below u.
+        let mut action = probs.len() - 1;
+        for (a, p) in probs.iter().enumerate() {
+            cumulative += p.get_data();
+            if u < cumulative {
+                action = a;
+                break;
+            }
+        }
+        (action, -softmax_cross_entropy(&logits, action))
+    }
+}
+
+/// One rollout of a `Policy`: the log-probability of each action taken and
+/// the reward that followed it.
+pub struct Episode {
+    pub log_probs: Vec<Value>,
+    pub rewards: Vec<f64>,
+}
+
+impl Episode {
+    pub fn len(&self) -> usize {
+        self.rewards.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rewards.is_empty()
+    }
+
+    pub fn total_reward(&self) -> f64 {
+        self.rewards.iter().sum()
+    }
+}
+
+/// Runs `policy` in `env` from a fresh `reset` until the episode ends.
+pub fn run_episode<E: Environment + ?Sized, R: Rng + ?Sized>(
+    env: &mut E,
+    policy: &Policy,
+    rng: &mut R,
+) -> Episode {
+    let mut state = env.reset();
+    let mut episode = Episode {
+        log_probs: vec![],
+        rewards: vec![],
+    };
+    loop {
+        let (action, log_prob) = policy.sample(&state, rng);
+        let step = env.step(action);
+        episode.log_probs.push(log_prob);
+        episode.rewards.push(step.reward);
+        if step.done {
+            return episode;
+        }
+        state = step.state;
+    }
+}
+
+/// `G_t = r_t + gamma * G_{t+1}` for every step of an episode.
+pub fn discounted_returns(rewards: &[f64], gamma: f64) -> Vec<f64> {
+    let mut returns = vec![0.0; rewards.len()];
+    let mut running = 0.0;
+    for (g, &r) in returns.iter_mut().zip(rewards.iter()).rev() {
+        running = r + gamma * running;
+        *g = running;
+    }
+    returns
+}
+
+/// One REINFORCE update (Williams, 1992) from a finished episode: descends
+/// `-mean(ln pi(a_t | s_t) * G_t)` with the optimizer, which must be built
+/// over the policy's parameters. For episodes longer than one step the
+/// returns are standardized, a baseline that cuts the gradient's variance.
+/// Returns the surrogate loss.
+pub fn reinforce_step<O: Optimizer>(episode: Episode, gamma: f64, optimizer: &mut O) -> f64 {
+    assert!(!episode.is_empty(), "Cannot learn from an empty episode.");
+    let mut returns = discounted_returns(&episode.rewards, gamma);
+    if returns.len() > 1 {
+        let n = returns.len() as f64;
+        let mean = returns.iter().sum::<f64>() / n;
+        let std = (returns.iter().map(|g| (g - mean).powi(2)).sum::<f64>() / n).sqrt();
+        for g in returns.iter_mut() {
+            *g = (*g - mean) / (std + 1e-8);
+        }
+    }
+    let terms: Vec<Value> = episode
+        .log_probs
+        .iter()
+        .zip(returns.iter())
+        .map(|(log_prob, &g)| log_prob * -g)
+        .collect();
+    let loss = Value::mean(&terms);
+    let loss_value = loss.get_data();
+    optimizer.zero_grad();
+    loss.backward();
+    optimizer.step();
+    loss_value
+}
+
+/// A `size` x `size` grid walk from the top-left cell to the goal in the
+/// bottom-right one. Actions 0 to 3 move up, right, down and left, and a move
+/// into the edge leaves the agent in place. Reaching the goal pays 1 and ends
+/// the episode; every other step costs 0.01, and episodes are cut off after
+/// `max_steps`. States are one-hot over the cells, in row-major order.
+pub struct GridWorld {
+    size: usize,
+    pos: (usize, usize),
+    steps: usize,
+    max_steps: usize,
+}
+
+impl GridWorld {
+    /// Episodes last at most `4 * size^2` steps.
+    pub fn new(size: usize) -> Self {
+        assert!(size >= 2, "The grid needs at least two cells a side.");
+        GridWorld {
+            size,
+            pos: (0, 0),
+            steps: 0,
+            max_steps: 4 * size * size,
+        }
+    }
+
+    pub fn max_steps(mut self, max_steps: usize) -> Self {
+        self.max_steps = max_steps;
+        self
+    }
+
+    fn state(&self) -> Vec<f64> {
+        let mut state = vec![0.0; self.size * self.size];
+        state[self.pos.0 * self.size + self.pos.1] = 1.0;
+        state
+    }
+}
+
+impl Environment for GridWorld {
+    fn n_actions(&self) -> usize {
+        4
+    }
+
+    fn reset(&mut self) -> Vec<f64> {
+        self.pos = (0, 0);
+        self.steps = 0;
+        self.state()
+    }
+
+    fn step(&mut self, action: usize) -> Step {
+        let (row, col) = self.pos;
+        let last = self.size - 1;
+        self.pos = match action {
+            0 => (row.saturating_sub(1), col),
+            1 => (row, (col + 1).min(last)),
+            2 => ((row + 1).min(last), col),
+            3 => (row, col.saturating_sub(1)),
+            _ => panic!("GridWorld actions are 0 to 3."),
+        };
+        self.steps += 1;
+        let at_goal = self.pos == (last, last);
+        Step {
+            state: self.state(),
+            reward: if at_goal { 1.0 } else { -0.01 },
+            done: at_goal || self.steps >= self.max_steps,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Transition {
+    pub state: Vec<f64>,
+    pub action: usize,
+    pub reward: f64,
+    pub next_state: Vec<f64>,
+    pub done: bool,
+}
+
+/// Fixed-capacity experience replay: once full, each new transition
+/// overwrites the oldest one.
+pub struct ReplayBuffer {
+    transitions: Vec<Transition>,
+    capacity: usize,
+    next: usize,
+}
+
+impl ReplayBuffer {
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "Replay buffer capacity must be positive.");
+        ReplayBuffer {
+            transitions: Vec::with_capacity(capacity),
+            capacity,
+            next: 0,
+        }
+    }
+
+    pub fn push(&mut self, transition: Transition) {
+        if self.transitions.len() < self.capacity {
+            self.transitions.push(transition);
+        } else {
+            self.transitions[self.next] = transition;
+        }
+        self.next = (self.next + 1) % self.capacity;
+    }
+
+    pub fn len(&self) -> usize {
+        self.transitions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.transitions.is_empty()
+    }
+
+    /// `batch_size` transitions drawn uniformly with replacement.
+    pub fn sample<R: Rng + ?Sized>(&self, batch_size: usize, rng: &mut R) -> Vec<&Transition> {
+        assert!(!self.is_empty(), "Cannot sample an empty replay buffer.");
+        (0..batch_size)
+            .map(|_| &self.transitions[rng.gen_range(0..self.transitions.len())])
+            .collect()
+    }
+}
+
+/// Deep Q-learning (Mnih et al., 2015): `q` outputs one action value per
+/// action and is trained towards `r + gamma * max_a' target(s')[a']` on
+/// minibatches replayed from past transitions, where `target` is a copy of
+/// `q` refreshed every `target_update` training steps. Acts epsilon-greedily.
+#[allow(clippy::upper_case_acronyms)]
+pub struct DQN<O: Optimizer> {
+    q: MLP,
+    target: MLP,
+    optimizer: O,
+    buffer: ReplayBuffer,
+    gamma: f64,
+    batch_size: usize,
+    target_update: usize,
+    epsilon: f64,
+    updates: usize,
+}
+
+impl<O: Optimizer> DQN<O> {
+    /// `target` must have the same architecture as `q` and starts as a copy
+    /// of it. Build `optimizer` over `q.parameters()`. Defaults: gamma 0.99,
+    /// batches of 32 from a 10000-transition buffer, a target refresh every
+    /// 100 updates and epsilon 0.1.
+    pub fn new(q: MLP, target: MLP, optimizer: O) -> Self {
+        assert_eq!(
+            q.parameters().len(),
+            target.parameters().len(),
+            "The target network must match the Q-network."
+        );
+        let dqn = DQN {
+            q,
+            target,
+            optimizer,
+            buffer: ReplayBuffer::new(10_000),
+            gamma: 0.99,
+            batch_size: 32,
+            target_update: 100,
+            epsilon: 0.1,
+            updates: 0,
+        };
+        dqn.sync_target();
+        dqn
+    }
+
+    pub fn gamma(mut self, gamma: f64) -> Self {
+        self.gamma = gamma;
+        self
+    }
+
+    pub fn batch_size(mut self, batch_size: usize) -> Self {
+        assert!(batch_size > 0, "Batch size must be positive.");
+        self.batch_size = batch_size;
+        self
+    }
+
+    pub fn buffer_capacity(mut self, capacity: usize) -> Self {
+        self.buffer = ReplayBuffer::new(capacity);
+        self
+    }
+
+    pub fn target_update(mut self, every: usize) -> Self {
+        assert!(every > 0, "Target update interval must be positive.");
+        self.target_update = every;
+        self
+    }
+
+    pub fn epsilon(mut self, epsilon: f64) -> Self {
+        assert!((0.0..=1.0).contains(&epsilon), "Epsilon must be in [0, 1].");
+        self.epsilon = epsilon;
+        self
+    }
+
+    /// Sets the exploration rate between episodes, e.g. to decay it.
+    pub fn set_epsilon(&mut self, epsilon: f64) {
+        assert!((0.0..=1.0).contains(&epsilon), "Epsilon must be in [0, 1].");
+        self.epsilon = epsilon;
+    }
+
+    pub fn q(&self) -> &MLP {
+        &self.q
+    }
+
+    pub fn target(&self) -> &MLP {
+        &self.target
+    }
+
+    pub fn buffer(&self) -> &ReplayBuffer {
+        &self.buffer
+    }
+
+    /// Copies the Q-network's weights into the target network.
+    pub fn sync_target(&self) {
+        for (t, p) in self.target.parameters().iter().zip(self.q.parameters()) {
+            t.update_data(p.get_data());
+        }
+    }
+
+    /// The action with the highest Q-value, the first on ties.
+    pub fn greedy_action(&self, state: &[f64]) -> usize {
+        argmax(&values(&self.q, state))
+    }
+
+    /// A uniformly random action with probability epsilon, else the greedy
+    /// one.
+    pub fn act<R: Rng + ?Sized>(&self, state: &[f64], rng: &mut R) -> usize {
+        if rng.gen::<f64>() < self.epsilon {
+            rng.gen_range(0..self.q.n_outputs())
+        } else {
+            self.greedy_action(state)
+        }
+    }
+
+    /// One gradient step on a replayed minibatch, returning its mean squared
+    /// TD error, or `None` until the buffer holds a full batch.
+    pub fn train_batch<R: Rng + ?Sized>(&mut self, rng: &mut R) -> Option<f64> {
+        if self.buffer.len() < self.batch_size {
+            return None;
+        }
+        let errors: Vec<Value> = self
+            .buffer
+            .sample(self.batch_size, rng)
+            .into_iter()
+            .map(|t| {
+                let mut y = t.reward;
+                if !t.done {
+                    let next = values(&self.target, &t.next_state);
+                    y += self.gamma * next.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+                }
+                (&self.q.call(&t.state)[t.action] - y).powf(2.0)
+            })
+            .collect();
+        let loss = Value::mean(&errors);
+        let loss_value = loss.get_data();
+        self.optimizer.zero_grad();
+        loss.backward();
+        self.optimizer.step();
+
+        self.updates += 1;
+        if self.updates.is_multiple_of(self.target_update) {
+            self.sync_target();
+        }
+        Some(loss_value)
+    }
+
+    /// Plays one episode, storing every transition and training on a batch
+    /// after each step. Returns the episode's total reward.
+    pub fn run_episode<E: Environment + ?Sized, R: Rng + ?Sized>(
+        &mut self,
+        env: &mut E,
+        rng: &mut R,
+    ) -> f64 {
+        let mut state = env.reset();
+        let mut total = 0.0;
+        loop {
+            let action = self.act(&state, rng);
+            let step = env.step(action);
+            total += step.reward;
+            self.buffer.push(Transition {
+                state,
+                action,
+                reward: step.reward,
+                next_state: step.state.clone(),
+                done: step.done,
+            });
+            self.train_batch(rng);
+            if step.done {
+                return total;
+            }
+            state = step.state;
+        }
+    }
+}
+
+fn values(net: &MLP, state: &[f64]) -> Vec<f64> {
+    net.call(state).iter().map(|v| v.get_data()).collect()
+}
+
+fn argmax(xs: &[f64]) -> usize {
+    let mut best = 0;
+    for (i, &x) in xs.iter().enumerate() {
+        if x > xs[best] {
+            best = i;
+        }
+    }
+    best
+}
@@ -0,0 +1,224 @@
+use crate::data::{class_label, Dataset};
+use rand::distributions::{Distribution, WeightedIndex};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+
+/// Decides which samples an epoch visits, and in what order. `Trainer` asks
+/// once per epoch and cuts the result into batches with a `BatchSampler`.
+pub trait Sampler {
+    fn indices(&mut self, dataset: &Dataset) -> Vec<usize>;
+}
+
+impl<S: Sampler + ?Sized> Sampler for &mut S {
+    fn indices(&mut self, dataset: &Dataset) -> Vec<usize> {
+        (**self).indices(dataset)
+    }
+}
+
+impl<S: Sampler + ?Sized> Sampler for Box<S> {
+    fn indices(&mut self, dataset: &Dataset) -> Vec<usize> {
+        (**self).indices(dataset)
+    }
+}
+
+/// Every sample once, in dataset order.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SequentialSampler;
+
+impl Sampler for SequentialSampler {
+    fn indices(&mut self, dataset: &Dataset) -> Vec<usize> {
+        (0..dataset.len()).collect()
+    }
+}
+
+/// Every sample once, in a fresh random order each epoch, reproducibly from
+/// the seed.
+pub struct RandomSampler {
+    rng: StdRng,
+    order: Vec<usize>,
+}
+
+impl RandomSampler {
+    pub fn new(seed: u64) -> Self {
+        RandomSampler {
+            rng: StdRng::seed_from_u64(seed),
+            order: vec![],
+        }
+    }
+}
+
+impl Sampler for RandomSampler {
+    fn indices(&mut self, dataset: &Dataset) -> Vec<usize> {
+        // Each epoch reshuffles the previous one's order.
+        if self.order.len() != dataset.len() {
+            self.order = (0..dataset.len()).collect();
+        }
+        self.order.shuffle(&mut self.rng);
+        self.order.clone()
+    }
+}
+
+/// Curriculum learning: samples sorted by a difficulty score, easiest
+/// first, with only the easiest `start_fraction` of them in the first
+/// epoch. The pool grows linearly until it holds every sample after
+/// `warmup_epochs` epochs. `difficulty` gets each sample's input and target
+/// and is evaluated once, on the first epoch.
+pub struct CurriculumSampler<F: Fn(&[f64], &[f64]) -> f64> {
+    difficulty: F,
+    warmup_epochs: usize,
+    start_fraction: f64,
+    shuffle: Option<StdRng>,
+    sorted: Vec<usize>,
+    epoch: usize,
+}
+
+impl<F: Fn(&[f64], &[f64]) -> f64> CurriculumSampler<F> {
+    /// Defaults to a start fraction of 0.25, with each epoch's pool in
+    /// easiest-first order.
+    pub fn new(difficulty: F, warmup_epochs: usize) -> Self {
+        CurriculumSampler {
+            difficulty,
+            warmup_epochs,
+            start_fraction: 0.25,
+            shuffle: None,
+            sorted: vec![],
+            epoch: 0,
+        }
+    }
+
+    pub fn start_fraction(mut self, start_fraction: f64) -> Self {
+        assert!(
+            start_fraction > 0.0 && start_fraction <= 1.0,
+            "Start fraction must be in (0, 1]."
+        );
+        self.start_fraction = start_fraction;
+        self
+    }
+
+    /// Shuffles each epoch's pool, reproducibly from `seed`, instead of
+    /// presenting it easiest first.
+    pub fn shuffle(mut self, seed: u64) -> Self {
+        self.shuffle = Some(StdRng::seed_from_u64(seed));
+        self
+    }
+
+    /// Fraction of the samples in the pool at `epoch`.
+    pub fn fraction(&self, epoch: usize) -> f64 {
+        if epoch >= self.warmup_epochs {
+            return 1.0;
+        }
+        let progress = epoch as f64 / self.warmup_epochs as f64;
+        self.start_fraction + (1.0 - self.start_fraction) * progress
+    }
+}
+
+impl<F: Fn(&[f64], &[f64]) -> f64> Sampler for CurriculumSampler<F> {
+    fn indices(&mut self, dataset: &Dataset) -> Vec<usize> {
+        if self.sorted.len() != dataset.len() {
+            let scores: Vec<f64> = dataset
+                .inputs
+                .iter()
+                .zip(dataset.targets.iter())
+                .map(|(x, y)| (self.difficulty)(x, y))
+                .collect();
+            self.sorted = (0..dataset.len()).collect();
+            self.sorted
+                .sort_by(|&a, &b| scores[a].total_cmp(&scores[b]));
+        }
+        let n = (self.fraction(self.epoch) * dataset.len() as f64).ceil() as usize;
+        self.epoch += 1;
+        let mut pool = self.sorted[..n.min(dataset.len())].to_vec();
+        if let Some(rng) = self.shuffle.as_mut() {
+            pool.shuffle(rng);
+        }
+        pool
+    }
+}
+
+/// Groups another sampler's indices into batches of `batch_size`; the last
+/// batch is smaller unless `drop_last` discards it.
+pub struct BatchSampler<S: Sampler> {
+    sampler: S,
+    batch_size: usize,
+    drop_last: bool,
+}
+
+impl<S: Sampler> BatchSampler<S> {
+    pub fn new(sampler: S, batch_size: usize) -> Self {
+        assert!(batch_size > 0, "Batch size must be positive.");
+        BatchSampler {
+            sampler,
+            batch_size,
+            drop_last: false,
+        }
+    }
+
+    pub fn drop_last(mut self, drop_last: bool) -> Self {
+        self.drop_last = drop_last;
+        self
+    }
+
+    /// One epoch's batches.
+    pub fn batches(&mut self, dataset: &Dataset) -> Vec<Vec<usize>> {
+        self.sampler
+            .indices(dataset)
+            .chunks(self.batch_size)
+            .filter(|batch| !self.drop_last || batch.len() == self.batch_size)
+            .map(|batch| batch.to_vec())
+            .collect()
+    }
+}
+
+/// Draws `num_samples` samples per epoch with replacement, sample `i` with
+/// probability proportional to `weights[i]`. With `inverse_class_frequency`
+/// weights every class is drawn equally often on average, which rebalances
+/// an imbalanced classification dataset.
+pub struct WeightedRandomSampler {
+    weights: Vec<f64>,
+    distribution: WeightedIndex<f64>,
+    num_samples: usize,
+    rng: StdRng,
+}
+
+impl WeightedRandomSampler {
+    pub fn new(weights: Vec<f64>, num_samples: usize, seed: u64) -> Self {
+        assert!(
+            weights.iter().all(|w| w.is_finite() && *w >= 0.0),
+            "Weights must be finite and non-negative."
+        );
+        let distribution = WeightedIndex::new(&weights).expect("Weights must not all be zero.");
+        WeightedRandomSampler {
+            weights,
+            distribution,
+            num_samples,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+}
+
+impl Sampler for WeightedRandomSampler {
+    fn indices(&mut self, dataset: &Dataset) -> Vec<usize> {
+        assert_eq!(
+            self.weights.len(),
+            dataset.len(),
+            "Need one weight per sample."
+        );
+        (0..self.num_samples)
+            .map(|_| self.distribution.sample(&mut self.rng))
+            .collect()
+    }
+}
+
+/// One weight per sample, the reciprocal of the size of its class; classes
+/// are read from the targets as in `Dataset::stratified_split`.
+pub fn inverse_class_frequency(dataset: &Dataset) -> Vec<f64> {
+    let labels: Vec<f64> = dataset.targets.iter().map(|t| class_label(t)).collect();
+    labels
+        .iter()
+        .map(|label| {
+            let count = labels.iter().filter(|l| l.total_cmp(label).is_eq()).count();
+            1.0 / count as f64
+        })
+        .collect()
+}
@@ -0,0 +1,338 @@
+use crate::engine::{domain_input, Op, EXP_MAX_ARG, SELU_ALPHA, SELU_SCALE};
+use std::cell::RefCell;
+use std::ops::{Add, Div, Mul, Neg, Sub};
+
+#[derive(Debug, Clone)]
+struct Node {
+    data: f64,
+    grad: f64,
+    op: Option<Op>,
+    /// Parent index and the local derivative `d(self)/d(parent)`, recorded
+    /// on the forward pass. Only the first `n_parents` entries are used.
+    parents: [(usize, f64); 2],
+    n_parents: usize,
+}
+
+/// Arena-backed alternative to the `Value` graph. Nodes live in one `Vec`
+/// and are addressed by index, and since they are appended in creation
+/// order the tape is already topologically sorted: `backward` is a single
+/// reverse sweep with no per-node allocation, hashing or `Rc` traffic.
+///
+/// Build a fresh tape per training step, creating the parameters from their
+/// stored values with `var`.
+#[derive(Debug, Default)]
+pub struct Tape {
+    nodes: RefCell<Vec<Node>>,
+}
+
+impl Tape {
+    pub fn new() -> Self {
+        Tape {
+            nodes: RefCell::new(vec![]),
+        }
+    }
+
+    /// Records a leaf.
+    pub fn var(&self, data: f64) -> Var<'_> {
+        self.push(data, None, &[])
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.borrow().is_empty()
+    }
+
+    pub fn zero_grad(&self) {
+        for node in self.nodes.borrow_mut().iter_mut() {
+            node.grad = 0.0;
+        }
+    }
+
+    fn push(&self, data: f64, op: Option<Op>, parents: &[(usize, f64)]) -> Var<'_> {
+        debug_assert_eq!(
+            op.as_ref().map_or(0, Op::arity),
+            parents.len(),
+            "Operand count must match the op's arity."
+        );
+        let mut nodes = self.nodes.borrow_mut();
+        let mut slots = [(0, 0.0); 2];
+        slots[..parents.len()].copy_from_slice(parents);
+        nodes.push(Node {
+            data,
+            grad: 0.0,
+            op,
+            parents: slots,
+            n_parents: parents.len(),
+        });
+        Var {
+            tape: self,
+            index: nodes.len() - 1,
+        }
+    }
+}
+
+/// Handle to a node on a `Tape`, with the same op API as `Value`.
+#[derive(Debug, Clone, Copy)]
+pub struct Var<'t> {
+    tape: &'t Tape,
+    index: usize,
+}
+
+impl<'t> Var<'t> {
+    pub fn get_data(&self) -> f64 {
+        self.tape.nodes.borrow()[self.index].data
+    }
+
+    pub fn get_grad(&self) -> f64 {
+        self.tape.nodes.borrow()[self.index].grad
+    }
+
+    pub fn get_op(&self) -> Option<Op> {
+        self.tape.nodes.borrow()[self.index].op.clone()
+    }
+
+    pub fn update_data(&self, data: f64) {
+        self.tape.nodes.borrow_mut()[self.index].data = data;
+    }
+
+    fn unary(self, data: f64, op: Op, local: f64) -> Var<'t> {
+        self.tape.push(data, Some(op), &[(self.index, local)])
+    }
+
+    pub fn tanh(self) -> Var<'t> {
+        let t = self.get_data().tanh();
+        self.unary(t, Op::Tanh, 1.0 - t * t)
+    }
+
+    /// Clamped to `±EXP_MAX_ARG` like `Value::exp`.
+    pub fn exp(self) -> Var<'t> {
+        let e = self.get_data().clamp(-EXP_MAX_ARG, EXP_MAX_ARG).exp();
+        self.unary(e, Op::Exp, e)
+    }
+
+    /// Non-positive inputs follow the current `DomainPolicy`.
+    pub fn ln(self) -> Var<'t> {
+        let x = domain_input(Op::Log, self.get_data(), 0.0);
+        self.unary(x.ln(), Op::Log, 1.0 / x)
+    }
+
+    pub fn sigmoid(self) -> Var<'t> {
+        let x = self.get_data();
+        let s = if x >= 0.0 {
+            1.0 / (1.0 + (-x).exp())
+        } else {
+            let e = x.exp();
+            e / (1.0 + e)
+        };
+        self.unary(s, Op::Sigmoid, s * (1.0 - s))
+    }
+
+    /// Negative inputs follow the current `DomainPolicy`.
+    pub fn sqrt(self) -> Var<'t> {
+        let r = domain_input(Op::Sqrt, self.get_data(), 0.0).sqrt();
+        self.unary(r, Op::Sqrt, 0.5 / r)
+    }
+
+    pub fn abs(self) -> Var<'t> {
+        let x = self.get_data();
+        let local = if x > 0.0 {
+            1.0
+        } else if x < 0.0 {
+            -1.0
+        } else {
+            0.0
+        };
+        self.unary(x.abs(), Op::Abs, local)
+    }
+
+    pub fn sin(self) -> Var<'t> {
+        let x = self.get_data();
+        self.unary(x.sin(), Op::Sin, x.cos())
+    }
+
+    pub fn cos(self) -> Var<'t> {
+        let x = self.get_data();
+        self.unary(x.cos(), Op::Cos, -x.sin())
+    }
+
+    pub fn relu(self) -> Var<'t> {
+        let x = self.get_data();
+        if x > 0.0 {
+            self.unary(x, Op::Relu, 1.0)
+        } else {
+            self.unary(0.0, Op::Relu, 0.0)
+        }
+    }
+
+    pub fn selu(self) -> Var<'t> {
+        let x = self.get_data();
+        if x > 0.0 {
+            self.unary(SELU_SCALE * x, Op::Selu, SELU_SCALE)
+        } else {
+            let e = SELU_SCALE * SELU_ALPHA * x.exp();
+            self.unary(e - SELU_SCALE * SELU_ALPHA, Op::Selu, e)
+        }
+    }
+
+    /// Piecewise-linear sigmoid, `clamp(x / 6 + 0.5, 0, 1)`.
+    pub fn hard_sigmoid(self) -> Var<'t> {
+        let x = self.get_data();
+        let local = if x > -3.0 && x < 3.0 { 1.0 / 6.0 } else { 0.0 };
+        self.unary((x / 6.0 + 0.5).clamp(0.0, 1.0), Op::HardSigmoid, local)
+    }
+
+    /// Piecewise-linear tanh, `clamp(x, -1, 1)`.
+    pub fn hard_tanh(self) -> Var<'t> {
+        let x = self.get_data();
+        let local = if x > -1.0 && x < 1.0 { 1.0 } else { 0.0 };
+        self.unary(x.clamp(-1.0, 1.0), Op::HardTanh, local)
+    }
+
+    /// Identity in the forward pass; scales the gradient by `-lambda` on the
+    /// way back.
+    pub fn grad_reverse(self, lambda: f64) -> Var<'t> {
+        self.unary(self.get_data(), Op::GradReverse(lambda), -lambda)
+    }
+
+    /// Both the base and the exponent get gradients, and bases outside the
+    /// domain follow the current `DomainPolicy`, as in `Value::pow`.
+    pub fn pow(self, other: Var<'t>) -> Var<'t> {
+        let exponent = other.get_data();
+        let base = domain_input(Op::Pow, self.get_data(), exponent);
+        let out = base.powf(exponent);
+        // d(a^b)/db = a^b ln(a), taken as 0 at a = 0 like `Value::pow`.
+        let d_exponent = if base == 0.0 { 0.0 } else { out * base.ln() };
+        self.tape.push(
+            out,
+            Some(Op::Pow),
+            &[
+                (self.index, exponent * base.powf(exponent - 1.0)),
+                (other.index, d_exponent),
+            ],
+        )
+    }
+
+    /// `self^exponent` for a constant exponent, recorded as a leaf.
+    pub fn powf(self, exponent: f64) -> Var<'t> {
+        self.pow(self.tape.var(exponent))
+    }
+
+    pub fn max(self, other: Var<'t>) -> Var<'t> {
+        let (a, b) = (self.get_data(), other.get_data());
+        let (ga, gb) = if a >= b { (1.0, 0.0) } else { (0.0, 1.0) };
+        self.tape.push(
+            a.max(b),
+            Some(Op::Max),
+            &[(self.index, ga), (other.index, gb)],
+        )
+    }
+
+    pub fn min(self, other: Var<'t>) -> Var<'t> {
+        let (a, b) = (self.get_data(), other.get_data());
+        let (ga, gb) = if a <= b { (1.0, 0.0) } else { (0.0, 1.0) };
+        self.tape.push(
+            a.min(b),
+            Some(Op::Min),
+            &[(self.index, ga), (other.index, gb)],
+        )
+    }
+
+    /// Backpropagates from this node, accumulating into the grads of the
+    /// leaves recorded before it. As with `Value::backward`, intermediate
+    /// grads are cleared afterwards, so backpropagating again, from this or
+    /// another node, only adds that pass's contribution to the leaves.
+    pub fn backward(self) {
+        let mut nodes = self.tape.nodes.borrow_mut();
+        nodes[self.index].grad = 1.0;
+        for i in (0..=self.index).rev() {
+            let Node {
+                grad,
+                parents,
+                n_parents,
+                ..
+            } = nodes[i];
+            for &(parent, local) in parents[..n_parents].iter() {
+                nodes[parent].grad += local * grad;
+            }
+        }
+        for node in nodes[..=self.index].iter_mut() {
+            if node.op.is_some() {
+                node.grad = 0.0;
+            }
+        }
+    }
+}
+
+impl<'t> Add for Var<'t> {
+    type Output = Var<'t>;
+
+    fn add(self, other: Var<'t>) -> Var<'t> {
+        self.tape.push(
+            self.get_data() + other.get_data(),
+            Some(Op::Add),
+            &[(self.index, 1.0), (other.index, 1.0)],
+        )
+    }
+}
+
+impl<'t> Mul for Var<'t> {
+    type Output = Var<'t>;
+
+    fn mul(self, other: Var<'t>) -> Var<'t> {
+        let (a, b) = (self.get_data(), other.get_data());
+        self.tape
+            .push(a * b, Some(Op::Mul), &[(self.index, b), (other.index, a)])
+    }
+}
+
+impl<'t> Sub for Var<'t> {
+    type Output = Var<'t>;
+
+    fn sub(self, other: Var<'t>) -> Var<'t> {
+        self + (-other)
+    }
+}
+
+impl<'t> Div for Var<'t> {
+    type Output = Var<'t>;
+
+    fn div(self, other: Var<'t>) -> Var<'t> {
+        self * other.powf(-1.0)
+    }
+}
+
+impl<'t> Neg for Var<'t> {
+    type Output = Var<'t>;
+
+    fn neg(self) -> Var<'t> {
+        self * -1.0
+    }
+}
+
+macro_rules! scalar_binop {
+    ($imp:ident, $method:ident) => {
+        impl<'t> $imp<f64> for Var<'t> {
+            type Output = Var<'t>;
+
+            fn $method(self, other: f64) -> Var<'t> {
+                $imp::$method(self, self.tape.var(other))
+            }
+        }
+
+        impl<'t> $imp<Var<'t>> for f64 {
+            type Output = Var<'t>;
+
+            fn $method(self, other: Var<'t>) -> Var<'t> {
+                $imp::$method(other.tape.var(self), other)
+            }
+        }
+    };
+}
+
+scalar_binop!(Add, add);
+scalar_binop!(Mul, mul);
+scalar_binop!(Sub, sub);
+scalar_binop!(Div, div);
@@ -0,0 +1,630 @@
+use crate::augment::{Mixup, Transform};
+use crate::data::Dataset;
+use crate::diagnostics;
+use crate::engine::Value;
+use crate::json;
+use crate::loss::Loss;
+use crate::nn::{Ensemble, Module, RecurrentCell, MLP};
+use crate::optim::{self, Optimizer};
+use crate::regularize::WeightNoise;
+use crate::sampler::{BatchSampler, CurriculumSampler, RandomSampler, Sampler, SequentialSampler};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Returned by callbacks to continue or end training early.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Control {
+    Continue,
+    Stop,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BatchEnd {
+    pub epoch: usize,
+    pub batch: usize,
+    pub loss: f64,
+    /// Learning rate of this batch's step.
+    pub lr: f64,
+    /// L2 norm of the model's gradients before the step.
+    pub grad_norm: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EpochEnd {
+    pub epoch: usize,
+    /// Mean of the epoch's batch losses.
+    pub loss: f64,
+}
+
+/// Hooks `Trainer::fit` runs after every optimizer step and every epoch.
+/// Both default to doing nothing.
+pub trait Callback {
+    fn on_batch_end(&mut self, _stats: &BatchEnd) -> Control {
+        Control::Continue
+    }
+
+    fn on_epoch_end(&mut self, _stats: &EpochEnd) -> Control {
+        Control::Continue
+    }
+}
+
+/// Stops once the epoch loss hasn't improved on the best so far by more
+/// than `min_delta` for `patience` epochs in a row.
+pub struct EarlyStopping {
+    patience: usize,
+    min_delta: f64,
+    best: f64,
+    stale: usize,
+}
+
+impl EarlyStopping {
+    pub fn new(patience: usize, min_delta: f64) -> Self {
+        EarlyStopping {
+            patience,
+            min_delta,
+            best: f64::INFINITY,
+            stale: 0,
+        }
+    }
+}
+
+impl Callback for EarlyStopping {
+    fn on_epoch_end(&mut self, stats: &EpochEnd) -> Control {
+        if stats.loss < self.best - self.min_delta {
+            self.best = stats.loss;
+            self.stale = 0;
+        } else {
+            self.stale += 1;
+        }
+        if self.stale >= self.patience {
+            Control::Stop
+        } else {
+            Control::Continue
+        }
+    }
+}
+
+/// Prints the loss every `every` epochs.
+pub struct PrintLoss {
+    every: usize,
+}
+
+impl PrintLoss {
+    pub fn new(every: usize) -> Self {
+        assert!(every > 0, "Print interval must be positive.");
+        PrintLoss { every }
+    }
+}
+
+impl Callback for PrintLoss {
+    fn on_epoch_end(&mut self, stats: &EpochEnd) -> Control {
+        if stats.epoch.is_multiple_of(self.every) {
+            println!("epoch {}: loss {}", stats.epoch, stats.loss);
+        }
+        Control::Continue
+    }
+}
+
+/// Mini-batch training loop: per batch, forward the inputs, reduce the loss,
+/// zero the grads, backpropagate and step the optimizer. Build the optimizer
+/// over `model.parameters()`.
+pub struct Trainer<'a, M: Module + ?Sized, L: Loss, O: Optimizer> {
+    model: &'a M,
+    loss: L,
+    optimizer: O,
+    dataset: &'a Dataset,
+    batch_size: usize,
+    epochs: usize,
+    sampler: Box<dyn Sampler + 'a>,
+    transforms: Vec<Box<dyn Transform + 'a>>,
+    suggest_lr: bool,
+    seed: Option<u64>,
+    initial_lr: f64,
+    task_history: Vec<Vec<(String, f64)>>,
+    sequence: Option<SequenceMode>,
+    weight_noise: Option<WeightNoise>,
+    callbacks: Vec<Box<dyn Callback + 'a>>,
+}
+
+/// Settings of `Trainer::sequence`.
+struct SequenceMode {
+    step_width: usize,
+    teacher_forcing: f64,
+    rng: StdRng,
+}
+
+impl<'a, M: Module + ?Sized, L: Loss, O: Optimizer> Trainer<'a, M, L, O> {
+    /// Defaults to full-batch training for one epoch, in dataset order.
+    pub fn new(model: &'a M, loss: L, optimizer: O, dataset: &'a Dataset) -> Self {
+        Trainer {
+            initial_lr: optimizer.lr(),
+            model,
+            loss,
+            optimizer,
+            dataset,
+            batch_size: dataset.len().max(1),
+            epochs: 1,
+            sampler: Box::new(SequentialSampler),
+            transforms: vec![],
+            suggest_lr: false,
+            seed: None,
+            task_history: vec![],
+            sequence: None,
+            weight_noise: None,
+            callbacks: vec![],
+        }
+    }
+
+    pub fn batch_size(mut self, batch_size: usize) -> Self {
+        assert!(batch_size > 0, "Batch size must be positive.");
+        self.batch_size = batch_size;
+        self
+    }
+
+    pub fn epochs(mut self, epochs: usize) -> Self {
+        self.epochs = epochs;
+        self
+    }
+
+    /// Reshuffles the samples every epoch, reproducibly from `seed`; short
+    /// for `sampler(RandomSampler::new(seed))`.
+    pub fn shuffle(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self.sampler(RandomSampler::new(seed))
+    }
+
+    /// Sets the order in which each epoch visits the samples, replacing any
+    /// earlier `shuffle` or `sampler`.
+    pub fn sampler<S: Sampler + 'a>(mut self, sampler: S) -> Self {
+        self.sampler = Box::new(sampler);
+        self
+    }
+
+    /// Trains easiest samples first, growing from a quarter of the dataset
+    /// to all of it over `warmup_epochs`; short for
+    /// `sampler(CurriculumSampler::new(difficulty, warmup_epochs))`, which
+    /// has the other options.
+    pub fn curriculum<F: Fn(&[f64], &[f64]) -> f64 + 'a>(
+        self,
+        difficulty: F,
+        warmup_epochs: usize,
+    ) -> Self {
+        self.sampler(CurriculumSampler::new(difficulty, warmup_epochs))
+    }
+
+    /// Augments every training batch; transforms run in the order added.
+    pub fn transform<T: Transform + 'a>(mut self, transform: T) -> Self {
+        self.transforms.push(Box::new(transform));
+        self
+    }
+
+    /// Short for `transform(Mixup::new(alpha, seed))`.
+    pub fn mixup(self, alpha: f64, seed: u64) -> Self {
+        self.transform(Mixup::new(alpha, seed))
+    }
+
+    /// Adds Gaussian noise of standard deviation `std` to every parameter
+    /// for each batch's forward and backward pass, drawn reproducibly from
+    /// `seed`; the optimizer then steps from the clean weights. See
+    /// `regularize::WeightNoise`.
+    pub fn weight_noise(mut self, std: f64, seed: u64) -> Self {
+        self.weight_noise = Some(WeightNoise::new_seeded(std, seed));
+        self
+    }
+
+    /// Prints `diagnostics::suggest_lr` for each of the model's parameter
+    /// groups at the start of `fit`, measured on the first batch in dataset
+    /// order before any training.
+    pub fn suggest_lr(mut self) -> Self {
+        self.suggest_lr = true;
+        self
+    }
+
+    /// Trains an autoregressive model: each target row is a sequence of
+    /// `step_width`-wide steps, and the model runs once per step on the
+    /// sample's inputs followed by the previous step (zeros before the
+    /// first), its outputs over all steps making up the prediction. While
+    /// training the previous step is the ground truth (teacher forcing); see
+    /// `teacher_forcing` to mix in the model's own outputs, and
+    /// `predict_sequence` for generation, which only uses them.
+    pub fn sequence(mut self, step_width: usize) -> Self {
+        assert!(step_width > 0, "Step width must be positive.");
+        self.sequence = Some(SequenceMode {
+            step_width,
+            teacher_forcing: 1.0,
+            rng: StdRng::from_entropy(),
+        });
+        self
+    }
+
+    /// In `sequence` mode, feeds the ground-truth previous step with
+    /// probability `ratio` and the model's own previous output otherwise,
+    /// drawn per step reproducibly from `seed`. 1, the default, always
+    /// feeds the ground truth and 0 trains on the model's own generations.
+    pub fn teacher_forcing(mut self, ratio: f64, seed: u64) -> Self {
+        assert!(
+            (0.0..=1.0).contains(&ratio),
+            "Teacher forcing ratio must be in [0, 1]."
+        );
+        let mode = self
+            .sequence
+            .as_mut()
+            .expect("Teacher forcing needs `sequence` mode.");
+        mode.teacher_forcing = ratio;
+        mode.rng = StdRng::seed_from_u64(seed);
+        self
+    }
+
+    pub fn callback<C: Callback + 'a>(mut self, callback: C) -> Self {
+        self.callbacks.push(Box::new(callback));
+        self
+    }
+
+    pub fn optimizer(&self) -> &O {
+        &self.optimizer
+    }
+
+    /// Per epoch run, the mean of each batch's `Loss::task_losses`, such as
+    /// the unweighted task losses of a `MultiTaskLoss`. Empty entries for
+    /// losses without components.
+    pub fn task_history(&self) -> &[Vec<(String, f64)>] {
+        &self.task_history
+    }
+
+    /// Describes a finished run for `RunManifest::save`; pass the history
+    /// `fit` returned.
+    pub fn manifest(&self, history: &[f64]) -> RunManifest {
+        RunManifest {
+            batch_size: self.batch_size,
+            epochs: self.epochs,
+            initial_lr: self.initial_lr,
+            seed: self.seed,
+            n_samples: self.dataset.len(),
+            dataset_hash: self.dataset.fingerprint(),
+            epochs_run: history.len(),
+            final_loss: history.last().copied().unwrap_or(f64::NAN),
+            best_loss: history.iter().copied().fold(f64::NAN, f64::min),
+            final_lr: self.optimizer.lr(),
+            history: history.to_vec(),
+            checkpoints: vec![],
+        }
+    }
+
+    fn forward_batch(&mut self, inputs: &[Vec<f64>], targets: &[Vec<f64>]) -> Vec<Vec<Value>> {
+        let model = self.model;
+        match self.sequence.as_mut() {
+            Some(mode) => inputs
+                .iter()
+                .zip(targets.iter())
+                .map(|(input, target)| {
+                    let ratio = mode.teacher_forcing;
+                    let rng = &mut mode.rng;
+                    rollout(model, input, mode.step_width, target, || {
+                        rng.gen::<f64>() < ratio
+                    })
+                })
+                .collect(),
+            None => inputs
+                .iter()
+                .map(|row| {
+                    let row: Vec<Value> = row.iter().map(|&x| Value::constant(x)).collect();
+                    model.forward(&row)
+                })
+                .collect(),
+        }
+    }
+
+    fn print_lr_suggestions(&mut self) {
+        let n = self.batch_size.min(self.dataset.len());
+        let first: Vec<usize> = (0..n).collect();
+        let batch = self.dataset.subset(&first);
+        let preds = self.forward_batch(&batch.inputs, &batch.targets);
+        let loss = self.loss.call(&preds, &batch.targets);
+        println!("suggested starting learning rates (SGD):");
+        for s in diagnostics::suggest_lr(&self.model.parameter_groups(), loss) {
+            match s.lr {
+                Some(lr) => println!(
+                    "  {}: {:.3e} (weight rms {:.3e}, grad rms {:.3e})",
+                    s.group, lr, s.param_rms, s.grad_rms
+                ),
+                None => println!("  {}: none, its gradients are zero", s.group),
+            }
+        }
+    }
+
+    /// Trains for the configured number of epochs, or until a callback
+    /// returns `Control::Stop`, and returns the loss of every epoch run.
+    pub fn fit(&mut self) -> Vec<f64> {
+        self.run(|_, _| Ok(()))
+            .expect("A no-op epoch hook cannot fail.")
+    }
+
+    /// The training loop behind `fit`, calling `after_epoch` with the model
+    /// and epoch index once each epoch's updates are done.
+    fn run<F>(&mut self, mut after_epoch: F) -> io::Result<Vec<f64>>
+    where
+        F: FnMut(&M, usize) -> io::Result<()>,
+    {
+        assert!(
+            !self.dataset.is_empty(),
+            "Cannot train on an empty dataset."
+        );
+        if self.suggest_lr {
+            self.print_lr_suggestions();
+        }
+        let params = self.model.parameters();
+        let mut history = vec![];
+        for epoch in 0..self.epochs {
+            let batches =
+                BatchSampler::new(&mut self.sampler, self.batch_size).batches(self.dataset);
+            let mut total = 0.0;
+            let mut task_totals: Vec<(String, f64)> = vec![];
+            let mut n_batches = 0;
+            let mut stop = false;
+            for (batch, indices) in batches.iter().enumerate() {
+                let batch_data = self.dataset.subset(indices);
+                let (mut inputs, mut targets) = (batch_data.inputs, batch_data.targets);
+                for transform in self.transforms.iter_mut() {
+                    transform.apply(&mut inputs, &mut targets);
+                }
+                if let Some(noise) = self.weight_noise.as_mut() {
+                    noise.perturb(&params);
+                }
+                let preds = self.forward_batch(&inputs, &targets);
+                let loss = self.loss.call(&preds, &targets);
+                let loss_value = loss.get_data();
+                let tasks = self.loss.task_losses(&preds, &targets);
+                if task_totals.is_empty() {
+                    task_totals = tasks.iter().map(|(name, _)| (name.clone(), 0.0)).collect();
+                }
+                for ((_, total), (_, value)) in task_totals.iter_mut().zip(tasks) {
+                    *total += value;
+                }
+
+                self.optimizer.zero_grad();
+                loss.backward();
+                if let Some(noise) = self.weight_noise.as_mut() {
+                    noise.restore(&params);
+                }
+                let grad_norm = optim::grad_norm(&params);
+                let lr = self.optimizer.lr();
+                self.optimizer.step();
+
+                total += loss_value;
+                n_batches += 1;
+                let stats = BatchEnd {
+                    epoch,
+                    batch,
+                    loss: loss_value,
+                    lr,
+                    grad_norm,
+                };
+                for callback in self.callbacks.iter_mut() {
+                    stop |= callback.on_batch_end(&stats) == Control::Stop;
+                }
+                if stop {
+                    break;
+                }
+            }
+            self.optimizer.end_epoch();
+            let stats = EpochEnd {
+                epoch,
+                loss: total / n_batches as f64,
+            };
+            history.push(stats.loss);
+            for (_, total) in task_totals.iter_mut() {
+                *total /= n_batches as f64;
+            }
+            self.task_history.push(task_totals);
+            after_epoch(self.model, epoch)?;
+            for callback in self.callbacks.iter_mut() {
+                stop |= callback.on_epoch_end(&stats) == Control::Stop;
+            }
+            if stop {
+                break;
+            }
+        }
+        Ok(history)
+    }
+}
+
+impl<L: Loss, O: Optimizer> Trainer<'_, MLP, L, O> {
+    /// Snapshot ensembling (Huang et al., 2017): trains as `fit` does, saving
+    /// the model to `dir/snapshot_<k>.txt` at the end of every `cycle_len`
+    /// epochs, and returns the history with an `Ensemble` of the saved
+    /// snapshots. Pair it with `Schedule::CosineWarmRestarts { t_0:
+    /// cycle_len, .. }` so each snapshot is taken at a learning rate
+    /// minimum, where the model has settled into a different basin.
+    pub fn fit_snapshots<P: AsRef<Path>>(
+        &mut self,
+        cycle_len: usize,
+        dir: P,
+    ) -> io::Result<(Vec<f64>, Ensemble)> {
+        assert!(cycle_len > 0, "Cycle length must be positive.");
+        let dir = dir.as_ref();
+        fs::create_dir_all(dir)?;
+        let mut paths = vec![];
+        let history = self.run(|model, epoch| {
+            if (epoch + 1).is_multiple_of(cycle_len) {
+                let path = dir.join(format!("snapshot_{}.txt", paths.len()));
+                model.save(&path)?;
+                paths.push(path);
+            }
+            Ok(())
+        })?;
+        let mut ensemble = Ensemble::new();
+        for path in paths {
+            ensemble = ensemble.push(MLP::load(path)?);
+        }
+        Ok((history, ensemble))
+    }
+}
+
+/// Runs `model` over the steps of `truth`, a flattened sequence of
+/// `step_width`-wide steps, feeding it `input` and the previous step. After
+/// each step `force` decides between the ground truth and the model's own
+/// output, which goes back in as a constant.
+fn rollout<M, F>(
+    model: &M,
+    input: &[f64],
+    step_width: usize,
+    truth: &[f64],
+    mut force: F,
+) -> Vec<Value>
+where
+    M: Module + ?Sized,
+    F: FnMut() -> bool,
+{
+    assert!(
+        truth.len().is_multiple_of(step_width),
+        "Sequence targets must be whole steps of {} values.",
+        step_width
+    );
+    let context: Vec<Value> = input.iter().map(|&x| Value::constant(x)).collect();
+    let mut previous: Vec<Value> = (0..step_width).map(|_| Value::constant(0.0)).collect();
+    let mut outputs = Vec::with_capacity(truth.len());
+    for step in truth.chunks(step_width) {
+        let predicted = model.forward(&[context.as_slice(), &previous].concat());
+        assert_eq!(
+            predicted.len(),
+            step_width,
+            "The model must output one step of the sequence."
+        );
+        previous = if force() {
+            step.iter().map(|&y| Value::constant(y)).collect()
+        } else {
+            predicted.iter().map(Value::detach).collect()
+        };
+        outputs.extend(predicted);
+    }
+    outputs
+}
+
+/// Generates `n_steps` steps from a model trained in `Trainer::sequence`
+/// mode, feeding each step the model's own previous output. Returns the
+/// flattened sequence.
+pub fn predict_sequence<M: Module + ?Sized>(
+    model: &M,
+    input: &[f64],
+    step_width: usize,
+    n_steps: usize,
+) -> Vec<Value> {
+    assert!(step_width > 0, "Step width must be positive.");
+    rollout(
+        model,
+        input,
+        step_width,
+        &vec![0.0; n_steps * step_width],
+        || false,
+    )
+}
+
+/// Truncated backpropagation through time over one long sequence whose rows
+/// are its time steps. The cell runs from `cell.initial_state()` with `head`
+/// mapping each hidden output to a prediction, and every `k` steps the
+/// chunk's loss is backpropagated, the optimizer steps and the state is
+/// detached, so no gradient flows into earlier chunks and the graph never
+/// spans more than `k` steps however long the sequence is. Build the
+/// optimizer over the cell's and the head's parameters. Returns the loss of
+/// every chunk.
+pub fn truncated_bptt<C, H, L, O>(
+    cell: &C,
+    head: &H,
+    loss: &L,
+    optimizer: &mut O,
+    sequence: &Dataset,
+    k: usize,
+) -> Vec<f64>
+where
+    C: RecurrentCell + ?Sized,
+    H: Module + ?Sized,
+    L: Loss,
+    O: Optimizer,
+{
+    assert!(k > 0, "Truncation length must be positive.");
+    let mut state = cell.initial_state();
+    let mut losses = vec![];
+    for (xs, ys) in sequence.inputs.chunks(k).zip(sequence.targets.chunks(k)) {
+        let (hiddens, next) = cell.unroll(xs, state);
+        let preds: Vec<Vec<Value>> = hiddens.iter().map(|h| head.forward(h)).collect();
+        let chunk_loss = loss.call(&preds, ys);
+        losses.push(chunk_loss.get_data());
+        optimizer.zero_grad();
+        chunk_loss.backward();
+        optimizer.step();
+        state = next.iter().map(Value::detach).collect();
+    }
+    losses
+}
+
+/// Machine-readable record of a training run: the trainer's configuration,
+/// a fingerprint of the data, the resulting metrics and any checkpoints
+/// written, so runs can be indexed and compared by other tools.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RunManifest {
+    pub batch_size: usize,
+    /// Epochs configured; `epochs_run` is lower when a callback stopped early.
+    pub epochs: usize,
+    pub initial_lr: f64,
+    /// The `Trainer::shuffle` seed, if the run shuffled.
+    pub seed: Option<u64>,
+    pub n_samples: usize,
+    /// `Dataset::fingerprint` of the training set.
+    pub dataset_hash: u64,
+    pub epochs_run: usize,
+    pub final_loss: f64,
+    pub best_loss: f64,
+    pub final_lr: f64,
+    pub history: Vec<f64>,
+    pub checkpoints: Vec<String>,
+}
+
+impl RunManifest {
+    /// Records a checkpoint file the run produced.
+    pub fn checkpoint<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.checkpoints
+            .push(path.as_ref().to_string_lossy().into_owned());
+        self
+    }
+
+    /// The manifest as a JSON object with `config`, `dataset`, `metrics` and
+    /// `checkpoints` sections. Non-finite numbers are written as `null` and
+    /// the hash as a hex string, since JSON numbers can't hold 64 bits.
+    pub fn to_json(&self) -> String {
+        let history: Vec<String> = self.history.iter().map(|&x| json::number(x)).collect();
+        let checkpoints: Vec<String> = self.checkpoints.iter().map(|p| json::quote(p)).collect();
+        let seed = self.seed.map_or("null".to_string(), |s| s.to_string());
+        let mut out = String::from("{\n");
+        out.push_str(&format!(
+            "  \"config\": {{\"batch_size\": {}, \"epochs\": {}, \"initial_lr\": {}, \"seed\": {}}},\n",
+            self.batch_size,
+            self.epochs,
+            json::number(self.initial_lr),
+            seed
+        ));
+        out.push_str(&format!(
+            "  \"dataset\": {{\"n_samples\": {}, \"hash\": \"{:016x}\"}},\n",
+            self.n_samples, self.dataset_hash
+        ));
+        out.push_str(&format!(
+            "  \"metrics\": {{\"epochs_run\": {}, \"final_loss\": {}, \"best_loss\": {}, \"final_lr\": {}, \"history\": [{}]}},\n",
+            self.epochs_run,
+            json::number(self.final_loss),
+            json::number(self.best_loss),
+            json::number(self.final_lr),
+            history.join(", ")
+        ));
+        out.push_str(&format!(
+            "  \"checkpoints\": [{}]\n}}\n",
+            checkpoints.join(", ")
+        ));
+        out
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path, self.to_json())
+    }
+}
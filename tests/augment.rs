@@ -0,0 +1,74 @@
+use micrograd::augment::{FeatureDropout, GaussianNoise, Mixup, Transform};
+
+#[test]
+fn gaussian_noise_has_the_requested_spread() {
+    let mut inputs = vec![vec![1.0; 100]; 50];
+    let mut targets = vec![vec![7.0]; 50];
+    GaussianNoise::new(0.5, 3).apply(&mut inputs, &mut targets);
+    let xs: Vec<f64> = inputs.into_iter().flatten().collect();
+    let mean = xs.iter().sum::<f64>() / xs.len() as f64;
+    let var = xs.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / xs.len() as f64;
+    assert!((mean - 1.0).abs() < 0.05, "mean {}", mean);
+    assert!((var.sqrt() - 0.5).abs() < 0.05, "std {}", var.sqrt());
+    assert!(targets.iter().all(|t| t[0] == 7.0));
+}
+
+#[test]
+fn feature_dropout_zeroes_without_rescaling() {
+    let mut inputs = vec![vec![2.0; 100]; 40];
+    FeatureDropout::new(0.3, 1).apply(&mut inputs, &mut vec![vec![]; 40]);
+    let xs: Vec<f64> = inputs.into_iter().flatten().collect();
+    assert!(xs.iter().all(|&x| x == 0.0 || x == 2.0));
+    let dropped = xs.iter().filter(|&&x| x == 0.0).count() as f64 / xs.len() as f64;
+    assert!((dropped - 0.3).abs() < 0.03, "dropped {}", dropped);
+}
+
+#[test]
+fn mixup_blends_inputs_and_targets_with_one_weight() {
+    for seed in 0..10 {
+        let mut inputs: Vec<Vec<f64>> = (0..6).map(|i| vec![i as f64, 10.0 * i as f64]).collect();
+        let mut targets: Vec<Vec<f64>> = (0..6)
+            .map(|i| {
+                if i < 3 {
+                    vec![1.0, 0.0]
+                } else {
+                    vec![0.0, 1.0]
+                }
+            })
+            .collect();
+        Mixup::new(0.4, seed).apply(&mut inputs, &mut targets);
+        for (x, y) in inputs.iter().zip(targets.iter()) {
+            // Both features come from the same pair of samples.
+            assert!((x[1] - 10.0 * x[0]).abs() < 1e-9);
+            assert!((y[0] + y[1] - 1.0).abs() < 1e-12);
+            assert!(y.iter().all(|&p| (0.0..=1.0).contains(&p)));
+        }
+        // Blending preserves the batch mean.
+        let mean = inputs.iter().map(|x| x[0]).sum::<f64>() / 6.0;
+        assert!((mean - 2.5).abs() < 1e-9, "mean {}", mean);
+    }
+}
+
+#[test]
+fn mixup_weights_follow_the_beta_mean() {
+    // Sample 0 becomes λ when paired with sample 1 and stays 1 when paired
+    // with itself.
+    let mut mixup = Mixup::new(2.0, 5);
+    let mut total = 0.0;
+    let mut n = 0;
+    for _ in 0..2000 {
+        let mut inputs = vec![vec![1.0], vec![0.0]];
+        mixup.apply(&mut inputs, &mut [vec![], vec![]]);
+        if inputs[0][0] != 1.0 {
+            total += inputs[0][0];
+            n += 1;
+        }
+    }
+    // Beta(2, 2) has mean 0.5.
+    assert!(n > 500);
+    assert!(
+        (total / n as f64 - 0.5).abs() < 0.03,
+        "{}",
+        total / n as f64
+    );
+}
@@ -0,0 +1,277 @@
+// Runs the same seeded models through the `Value` graph and the `Tape` and
+// checks that losses and gradients agree, so the backends can't drift apart.
+
+use micrograd::engine::{set_domain_policy, DomainPolicy};
+use micrograd::tape::{Tape, Var};
+use micrograd::Value;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::ops::{Add, Mul, Sub};
+
+const TOL: f64 = 1e-10;
+
+/// The op API both backends share, so a model can be written once.
+trait Scalar:
+    Clone
+    + Add<Output = Self>
+    + Add<f64, Output = Self>
+    + Sub<Output = Self>
+    + Mul<Output = Self>
+    + Mul<f64, Output = Self>
+{
+    fn tanh(self) -> Self;
+    fn sigmoid(self) -> Self;
+    fn relu(self) -> Self;
+    fn exp(self) -> Self;
+    fn ln(self) -> Self;
+    fn sqrt(self) -> Self;
+    fn abs(self) -> Self;
+    fn sin(self) -> Self;
+    fn cos(self) -> Self;
+    fn selu(self) -> Self;
+    fn hard_sigmoid(self) -> Self;
+    fn hard_tanh(self) -> Self;
+    fn grad_reverse(self, lambda: f64) -> Self;
+    fn pow(self, exponent: Self) -> Self;
+    fn powf(self, exponent: f64) -> Self;
+    fn max(self, other: Self) -> Self;
+    fn min(self, other: Self) -> Self;
+}
+
+macro_rules! impl_scalar {
+    ($t:ty) => {
+        impl Scalar for $t {
+            fn tanh(self) -> Self {
+                <$t>::tanh(self)
+            }
+            fn sigmoid(self) -> Self {
+                <$t>::sigmoid(self)
+            }
+            fn relu(self) -> Self {
+                <$t>::relu(self)
+            }
+            fn exp(self) -> Self {
+                <$t>::exp(self)
+            }
+            fn ln(self) -> Self {
+                <$t>::ln(self)
+            }
+            fn sqrt(self) -> Self {
+                <$t>::sqrt(self)
+            }
+            fn abs(self) -> Self {
+                <$t>::abs(self)
+            }
+            fn sin(self) -> Self {
+                <$t>::sin(self)
+            }
+            fn cos(self) -> Self {
+                <$t>::cos(self)
+            }
+            fn selu(self) -> Self {
+                <$t>::selu(self)
+            }
+            fn hard_sigmoid(self) -> Self {
+                <$t>::hard_sigmoid(self)
+            }
+            fn hard_tanh(self) -> Self {
+                <$t>::hard_tanh(self)
+            }
+            fn grad_reverse(self, lambda: f64) -> Self {
+                <$t>::grad_reverse(self, lambda)
+            }
+            fn pow(self, exponent: Self) -> Self {
+                <$t>::pow(self, exponent)
+            }
+            fn powf(self, exponent: f64) -> Self {
+                <$t>::powf(self, exponent)
+            }
+            fn max(self, other: Self) -> Self {
+                <$t>::max(self, other)
+            }
+            fn min(self, other: Self) -> Self {
+                <$t>::min(self, other)
+            }
+        }
+    };
+}
+
+impl_scalar!(Value);
+impl_scalar!(Var<'_>);
+
+/// Mean squared error of a one-hidden-layer tanh network over `data`.
+/// `params` holds, per hidden unit, its input weights and bias, then the
+/// output weights and bias.
+fn mlp_loss<S: Scalar>(params: &[S], data: &[(Vec<f64>, f64)], nin: usize, hidden: usize) -> S {
+    let out = &params[hidden * (nin + 1)..];
+    let mut total: Option<S> = None;
+    for (x, y) in data {
+        let mut pred = out[hidden].clone();
+        for (j, unit) in params[..hidden * (nin + 1)].chunks(nin + 1).enumerate() {
+            let mut act = unit[nin].clone();
+            for (w, &xi) in unit[..nin].iter().zip(x.iter()) {
+                act = act + w.clone() * xi;
+            }
+            pred = pred + out[j].clone() * act.tanh();
+        }
+        let sq = (pred + -y).powf(2.0);
+        total = Some(match total {
+            None => sq,
+            Some(t) => t + sq,
+        });
+    }
+    total.unwrap() * (1.0 / data.len() as f64)
+}
+
+/// Every other shared op, chained so each one's gradient matters.
+fn op_zoo<S: Scalar>(params: &[S]) -> S {
+    let (a, b, c) = (params[0].clone(), params[1].clone(), params[2].clone());
+    let positive = (a.clone() * a.clone() + 0.5).sqrt().ln();
+    let waves = b.clone().sin() * c.clone().cos() + (a.clone() - c.clone()).abs();
+    let gates = (b.clone() * 0.3).exp().sigmoid() + (c.clone() - b.clone()).relu();
+    let picks = a.clone().max(b.clone()) * c.clone().min(a.clone());
+    positive * waves + gates - picks.powf(3.0)
+}
+
+/// The piecewise activations, gradient reversal and `pow` with a learned
+/// exponent.
+fn activation_zoo<S: Scalar>(params: &[S]) -> S {
+    let (a, b, c) = (params[0].clone(), params[1].clone(), params[2].clone());
+    let gates = (a.clone() * 2.0).hard_sigmoid() * (b.clone() * 1.5).hard_tanh();
+    let selus = a.clone().selu() + (b.clone() - c.clone()).selu();
+    let reversed = (c.clone() * a.clone()).grad_reverse(0.7);
+    let base = (a.clone() * a.clone() + 0.5).sqrt();
+    gates * selus + reversed + base.pow(c.clone() * b.clone())
+}
+
+fn seeded_case(seed: u64, nin: usize, hidden: usize) -> (Vec<f64>, Vec<(Vec<f64>, f64)>) {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let params = (0..hidden * (nin + 1) + hidden + 1)
+        .map(|_| rng.gen_range(-1.0..1.0))
+        .collect();
+    let data = (0..16)
+        .map(|_| {
+            let x: Vec<f64> = (0..nin).map(|_| rng.gen_range(-2.0..2.0)).collect();
+            let y = x.iter().map(|xi| xi.sin()).sum();
+            (x, y)
+        })
+        .collect();
+    (params, data)
+}
+
+/// Loss and gradients from each backend for the same parameters.
+fn run_both<F, G>(params: &[f64], value_fn: F, tape_fn: G) -> [(f64, Vec<f64>); 2]
+where
+    F: Fn(&[Value]) -> Value,
+    G: for<'t> Fn(&[Var<'t>]) -> Var<'t>,
+{
+    let values: Vec<Value> = params.iter().map(|&p| Value::new(p)).collect();
+    let loss = value_fn(&values);
+    let value_loss = loss.get_data();
+    loss.backward();
+    let value_grads = values.iter().map(|v| v.get_grad()).collect();
+
+    let tape = Tape::new();
+    let vars: Vec<Var> = params.iter().map(|&p| tape.var(p)).collect();
+    let loss = tape_fn(&vars);
+    let tape_loss = loss.get_data();
+    loss.backward();
+    let tape_grads = vars.iter().map(|v| v.get_grad()).collect();
+
+    [(value_loss, value_grads), (tape_loss, tape_grads)]
+}
+
+fn assert_agree([(value_loss, value_grads), (tape_loss, tape_grads)]: [(f64, Vec<f64>); 2]) {
+    let close = |a: f64, b: f64| (a - b).abs() <= TOL * a.abs().max(1.0);
+    assert!(
+        close(value_loss, tape_loss),
+        "losses differ: value {}, tape {}",
+        value_loss,
+        tape_loss
+    );
+    for (i, (&gv, &gt)) in value_grads.iter().zip(tape_grads.iter()).enumerate() {
+        assert!(
+            close(gv, gt),
+            "grad {} differs: value {}, tape {}",
+            i,
+            gv,
+            gt
+        );
+    }
+}
+
+#[test]
+fn mlp_loss_matches_across_backends() {
+    for seed in 0..5 {
+        let (nin, hidden) = (3, 5);
+        let (params, data) = seeded_case(seed, nin, hidden);
+        assert_agree(run_both(
+            &params,
+            |p| mlp_loss(p, &data, nin, hidden),
+            |p| mlp_loss(p, &data, nin, hidden),
+        ));
+    }
+}
+
+#[test]
+fn op_zoo_matches_across_backends() {
+    for seed in 0..20 {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let params: Vec<f64> = (0..3).map(|_| rng.gen_range(-2.0..2.0)).collect();
+        // A closure, since the generic fn item is only valid for one tape lifetime.
+        assert_agree(run_both(&params, op_zoo, |p| op_zoo(p)));
+    }
+}
+
+#[test]
+fn activation_zoo_matches_across_backends() {
+    for seed in 0..20 {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let params: Vec<f64> = (0..3).map(|_| rng.gen_range(-2.0..2.0)).collect();
+        assert_agree(run_both(&params, activation_zoo, |p| activation_zoo(p)));
+    }
+}
+
+#[test]
+fn domain_policy_applies_in_both_backends() {
+    fn out_of_domain<S: Scalar>(p: &[S]) -> S {
+        p[0].clone().ln() + p[1].clone().sqrt() + p[2].clone().powf(-1.0)
+    }
+    let previous = set_domain_policy(DomainPolicy::Stabilize(1e-3));
+    let results = run_both(&[0.0, -1.0, 0.0], out_of_domain, |p| out_of_domain(p));
+    set_domain_policy(previous);
+
+    for (loss, grads) in results.iter() {
+        assert!(loss.is_finite(), "loss {} is not finite", loss);
+        assert!(grads.iter().all(|g| g.is_finite()), "grads {:?}", grads);
+    }
+    assert_agree(results);
+}
+
+#[test]
+fn repeated_backward_matches_across_backends() {
+    // Two losses sharing `h`, with a second pass from the first loss too:
+    // each pass must add only its own contribution to the leaf grads.
+    fn losses<S: Scalar>(p: &[S]) -> (S, S) {
+        let h = (p[0].clone() * p[1].clone()).tanh();
+        (h.clone() * h.clone() + p[0].clone(), h * 3.0 + p[1].clone())
+    }
+    let params = [0.7, -1.3];
+
+    let values: Vec<Value> = params.iter().map(|&p| Value::new(p)).collect();
+    let (first, second) = losses(&values);
+    first.clone().backward();
+    second.backward();
+    first.backward();
+    let value_grads = values.iter().map(|v| v.get_grad()).collect();
+
+    let tape = Tape::new();
+    let vars: Vec<Var> = params.iter().map(|&p| tape.var(p)).collect();
+    let (first, second) = losses(&vars);
+    first.backward();
+    second.backward();
+    first.backward();
+    let tape_grads = vars.iter().map(|v| v.get_grad()).collect();
+
+    assert_agree([(0.0, value_grads), (0.0, tape_grads)]);
+}
@@ -0,0 +1,32 @@
+use micrograd::calibration::{expected_calibration_error, reliability_bins};
+
+#[test]
+fn expected_calibration_error_on_a_hand_made_example() {
+    let probs = vec![
+        vec![0.9, 0.05, 0.05],
+        vec![0.8, 0.2, 0.0],
+        vec![0.6, 0.4, 0.0],
+        vec![0.4, 0.35, 0.25],
+    ];
+    let labels = [0, 1, 0, 2];
+    // Upper bin: confidences 0.9, 0.8, 0.6 with two of three right, a gap
+    // of 0.1. Lower bin: confidence 0.4, wrong.
+    let bins = reliability_bins(&probs, &labels, 2);
+    assert_eq!(bins.len(), 2);
+    assert_eq!((bins[0].lower, bins[0].upper, bins[0].count), (0.0, 0.5, 1));
+    assert!((bins[0].confidence - 0.4).abs() < 1e-12 && bins[0].accuracy == 0.0);
+    assert_eq!((bins[1].lower, bins[1].upper, bins[1].count), (0.5, 1.0, 3));
+    assert!((bins[1].confidence - 2.3 / 3.0).abs() < 1e-12);
+    assert!((bins[1].accuracy - 2.0 / 3.0).abs() < 1e-12);
+
+    let ece = expected_calibration_error(&probs, &labels, 2);
+    assert!((ece - (3.0 * 0.1 + 0.4) / 4.0).abs() < 1e-12, "ece {}", ece);
+
+    // With ten bins every sample has a bin to itself.
+    let ece = expected_calibration_error(&probs, &labels, 10);
+    assert!(
+        (ece - (0.1 + 0.8 + 0.4 + 0.4) / 4.0).abs() < 1e-12,
+        "ece {}",
+        ece
+    );
+}
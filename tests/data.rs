@@ -0,0 +1,398 @@
+use micrograd::data::{
+    load_cached, load_csv, parse_json_inputs, parse_libsvm, CsvStream, Dataset, Impute, Imputer,
+    ShuffleBuffer,
+};
+use micrograd::preprocess::{
+    polynomial_features, CategoricalEncoder, Encoding, FourierFeatures, Pipeline, TargetEncoder,
+    Unknown,
+};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use std::fs;
+use std::io::Cursor;
+
+#[test]
+fn constant_imputation_ignores_unobserved_columns() {
+    let inputs = vec![vec![f64::NAN, 1.0], vec![f64::NAN, f64::NAN]];
+    let imputer = Imputer::fit(&inputs, Impute::Constant(7.0));
+    assert_eq!(
+        imputer.transform_row(&[f64::NAN, f64::NAN]),
+        Some(vec![7.0, 7.0])
+    );
+
+    let imputer = Imputer::fit(&inputs, Impute::Mean);
+    assert_eq!(
+        imputer.transform_row(&[f64::NAN, f64::NAN]),
+        Some(vec![0.0, 1.0])
+    );
+}
+
+#[test]
+fn libsvm_densifies_sparse_rows() {
+    let text = "1 1:0.5 3:2 # a comment\n\n-1 qid:4 2:1.5\n";
+    let data = parse_libsvm(text, None).unwrap();
+    assert_eq!(
+        data,
+        Dataset::new(
+            vec![vec![0.5, 0.0, 2.0], vec![0.0, 1.5, 0.0]],
+            vec![vec![1.0], vec![-1.0]],
+        )
+    );
+    assert_eq!(parse_libsvm(text, Some(5)).unwrap().n_features(), 5);
+}
+
+#[test]
+fn libsvm_rejects_bad_indices() {
+    let message = |text, n_features| parse_libsvm(text, n_features).unwrap_err().to_string();
+    assert_eq!(message("1 0:1.0", None), "line 1: invalid index \"0\"");
+    assert_eq!(message("1 1:1\n0 x:2", None), "line 2: invalid index \"x\"");
+    assert_eq!(
+        message("1 4:1.0", Some(3)),
+        "line 1: index 4 exceeds 3 features"
+    );
+    assert_eq!(
+        message("1 2", None),
+        "line 1: expected index:value, got \"2\""
+    );
+    assert_eq!(message("yes 1:1", None), "line 1: invalid label \"yes\"");
+}
+
+#[test]
+fn csv_stream_splits_features_and_targets() {
+    let text = "a;y;b\n1;10;2\n\n3;;4\n";
+    let stream = CsvStream::from_reader(Cursor::new(text), true, vec![1])
+        .unwrap()
+        .delimiter(';');
+    assert_eq!(
+        stream.header(),
+        Some(vec!["a".to_string(), "y".to_string(), "b".to_string()])
+    );
+    let rows: Vec<(Vec<f64>, Vec<f64>)> = stream.map(Result::unwrap).collect();
+    assert_eq!(rows.len(), 2);
+    assert_eq!(rows[0], (vec![1.0, 2.0], vec![10.0]));
+    assert_eq!(rows[1].0, vec![3.0, 4.0]);
+    assert!(rows[1].1[0].is_nan());
+}
+
+#[test]
+fn csv_stream_reports_bad_rows() {
+    let errors = |text: &'static str| -> Vec<String> {
+        CsvStream::from_reader(Cursor::new(text), true, vec![2])
+            .unwrap()
+            .filter_map(|row| row.err().map(|e| e.to_string()))
+            .collect()
+    };
+    assert_eq!(
+        errors("a,b,y\n1,2,3\n4,5\n"),
+        ["line 3: target column 2 missing from row"]
+    );
+    assert_eq!(
+        errors("a,b,y\n1,two,3\n"),
+        ["line 2: invalid number \"two\" in column 1"]
+    );
+}
+
+#[test]
+fn shuffle_buffer_yields_every_item_once() {
+    let shuffled: Vec<u32> = ShuffleBuffer::new(0..100, 10, StdRng::seed_from_u64(1)).collect();
+    assert_ne!(shuffled, (0..100).collect::<Vec<_>>());
+    let mut sorted = shuffled.clone();
+    sorted.sort();
+    assert_eq!(sorted, (0..100).collect::<Vec<_>>());
+    // An item can't be drawn before the buffer has reached it.
+    for (position, &item) in shuffled.iter().enumerate() {
+        assert!(item < position as u32 + 10, "{} at {}", item, position);
+    }
+
+    let again: Vec<u32> = ShuffleBuffer::new(0..100, 10, StdRng::seed_from_u64(1)).collect();
+    assert_eq!(again, shuffled);
+    let unbuffered: Vec<u32> = ShuffleBuffer::new(0..5, 1, StdRng::seed_from_u64(1)).collect();
+    assert_eq!(unbuffered, [0, 1, 2, 3, 4]);
+}
+
+#[test]
+fn json_inputs_parse_and_validate() {
+    let rows = parse_json_inputs(r#"{"inputs": [[1, 2.5], [-3e2, 0]]}"#).unwrap();
+    assert_eq!(rows, [vec![1.0, 2.5], vec![-300.0, 0.0]]);
+    assert!(parse_json_inputs(r#"{"inputs": []}"#).unwrap().is_empty());
+
+    let err = |text| parse_json_inputs(text).unwrap_err().to_string();
+    assert_eq!(
+        err(r#"[[1]]"#),
+        "expected an object with an \"inputs\" array"
+    );
+    assert_eq!(
+        err(r#"{"inputs": [[1], "x"]}"#),
+        "input 1: expected an array of numbers"
+    );
+    assert_eq!(
+        err(r#"{"inputs": [[1, 2], [3]]}"#),
+        "input 1: expected 2 features, got 1"
+    );
+}
+
+#[test]
+fn stratified_split_keeps_every_class_on_both_sides() {
+    // Eight samples of class 0 and two of class 1, as one-hot targets.
+    let inputs: Vec<Vec<f64>> = (0..10).map(|i| vec![i as f64]).collect();
+    let targets: Vec<Vec<f64>> = (0..10)
+        .map(|i| {
+            if i < 8 {
+                vec![1.0, 0.0]
+            } else {
+                vec![0.0, 1.0]
+            }
+        })
+        .collect();
+    let data = Dataset::new(inputs, targets);
+    for seed in 0..20 {
+        let (train, test) = data.stratified_split(0.2, &mut StdRng::seed_from_u64(seed));
+        let count =
+            |d: &Dataset, class: usize| d.targets.iter().filter(|t| t[class] == 1.0).count();
+        assert_eq!((count(&train, 0), count(&train, 1)), (6, 1));
+        assert_eq!((count(&test, 0), count(&test, 1)), (2, 1));
+
+        let mut seen: Vec<f64> = train
+            .inputs
+            .iter()
+            .chain(test.inputs.iter())
+            .map(|x| x[0])
+            .collect();
+        seen.sort_by(f64::total_cmp);
+        assert_eq!(seen, (0..10).map(|i| i as f64).collect::<Vec<_>>());
+    }
+}
+
+#[test]
+fn random_split_sizes_and_reproducibility() {
+    let data = Dataset::new(
+        (0..10).map(|i| vec![i as f64]).collect(),
+        (0..10).map(|i| vec![i as f64]).collect(),
+    );
+    let (train, test) = data.split(0.25, &mut StdRng::seed_from_u64(3));
+    // 2.5 rounds away from zero.
+    assert_eq!((train.len(), test.len()), (7, 3));
+    assert!(test.inputs.windows(2).all(|w| w[0][0] < w[1][0]));
+    assert_eq!(data.split(0.25, &mut StdRng::seed_from_u64(3)).1, test);
+    assert_eq!(data.subset(&[4, 1]).inputs, [vec![4.0], vec![1.0]]);
+}
+
+#[test]
+fn fingerprint_changes_with_the_data() {
+    let data = Dataset::new(vec![vec![1.0, 2.0]], vec![vec![0.0]]);
+    assert_eq!(data.fingerprint(), data.clone().fingerprint());
+    let changed = Dataset::new(vec![vec![1.0, 2.5]], vec![vec![0.0]]);
+    assert_ne!(changed.fingerprint(), data.fingerprint());
+    // Moving a value across the input/target boundary changes the hash.
+    let moved = Dataset::new(vec![vec![1.0]], vec![vec![2.0, 0.0]]);
+    assert_ne!(moved.fingerprint(), data.fingerprint());
+}
+
+fn temp_dir(name: &str) -> std::path::PathBuf {
+    std::env::temp_dir().join(format!("micrograd-{}-{}", std::process::id(), name))
+}
+
+#[test]
+fn dataset_save_load_round_trip() {
+    let dir = temp_dir("dataset-round-trip");
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("data.txt");
+    let data = Dataset::new(
+        vec![vec![0.1, -2.5e-300, f64::NAN], vec![]],
+        vec![vec![1.0 / 3.0], vec![f64::INFINITY, 0.0]],
+    );
+    data.save(&path).unwrap();
+    let loaded = Dataset::load(&path).unwrap();
+    assert_eq!(loaded.inputs[0][..2], data.inputs[0][..2]);
+    assert!(loaded.inputs[0][2].is_nan());
+    assert!(loaded.inputs[1].is_empty());
+    assert_eq!(loaded.targets, data.targets);
+
+    fs::write(&path, "micrograd-dataset v1\n1 2\t3\n1 x\t3\n").unwrap();
+    let err = Dataset::load(&path).unwrap_err();
+    assert_eq!(err.to_string(), "line 3: invalid number \"x\"");
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn cached_datasets_are_built_once_per_source_and_recipe() {
+    let dir = temp_dir("dataset-cache");
+    fs::create_dir_all(&dir).unwrap();
+    let source = dir.join("raw.csv");
+    fs::write(&source, "1,2\n3,4\n").unwrap();
+    let cache = dir.join("cache");
+    let builds = std::cell::Cell::new(0);
+    let build = |path: &std::path::Path| {
+        builds.set(builds.get() + 1);
+        // Doubles the first column as the "preprocessing".
+        let mut data = load_csv(path, false, vec![1])?;
+        for x in data.inputs.iter_mut() {
+            x[0] *= 2.0;
+        }
+        Ok(data)
+    };
+
+    let first = load_cached(&source, "double", &cache, build).unwrap();
+    assert_eq!(first.inputs, [vec![2.0], vec![6.0]]);
+    let second = load_cached(&source, "double", &cache, build).unwrap();
+    assert_eq!((second, builds.get()), (first.clone(), 1));
+
+    load_cached(&source, "double v2", &cache, build).unwrap();
+    fs::write(&source, "1,2\n5,6\n").unwrap();
+    let changed = load_cached(&source, "double", &cache, build).unwrap();
+    assert_eq!(builds.get(), 3);
+    assert_eq!(changed.inputs[1], [10.0]);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+fn strings(rows: &[&[&str]]) -> Vec<Vec<String>> {
+    rows.iter()
+        .map(|row| row.iter().map(|s| s.to_string()).collect())
+        .collect()
+}
+
+#[test]
+fn categorical_encoder_save_load_round_trip() {
+    let dir = temp_dir("encoder-round-trip");
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("encoder.txt");
+    let rows = strings(&[&["red", "s"], &["blue", "m"], &["red", "l"]]);
+
+    let encoder = CategoricalEncoder::fit(&rows, Encoding::OneHot, Unknown::Ignore);
+    encoder.save(&path).unwrap();
+    let loaded = CategoricalEncoder::load(&path).unwrap();
+    assert_eq!(loaded, encoder);
+    assert_eq!(
+        loaded.transform(&rows).unwrap(),
+        encoder.transform(&rows).unwrap()
+    );
+    // An unseen category encodes as all zeros under `Ignore`.
+    let unseen = strings(&[&["green", "m"]]);
+    assert_eq!(
+        loaded.transform(&unseen).unwrap(),
+        [[0.0, 0.0, 0.0, 1.0, 0.0]]
+    );
+
+    let encoder = CategoricalEncoder::fit(&rows, Encoding::Ordinal, Unknown::Error);
+    encoder.save(&path).unwrap();
+    let loaded = CategoricalEncoder::load(&path).unwrap();
+    assert_eq!(loaded, encoder);
+    assert_eq!(loaded.transform_row(&rows[1]).unwrap(), [0.0, 1.0]);
+    let err = loaded.transform(&unseen).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+
+    let tabbed = CategoricalEncoder::fit(&strings(&[&["a\tb"]]), Encoding::Ordinal, Unknown::Error);
+    assert!(tabbed.save(&path).is_err());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn kfold_target_encoding_never_sees_a_rows_own_target() {
+    let rows = strings(&[
+        &["a"],
+        &["b"],
+        &["a"],
+        &["a"],
+        &["b"],
+        &["c"],
+        &["a"],
+        &["b"],
+        &["c"],
+        &["a"],
+    ]);
+    let targets: Vec<f64> = (0..10).map(|i| (i % 3) as f64).collect();
+    let encode = |targets: &[f64]| {
+        TargetEncoder::fit_transform_kfold(&rows, targets, 1.0, 3, &mut StdRng::seed_from_u64(6))
+    };
+    let (encoder, encoded) = encode(&targets);
+    assert_eq!(encoder, TargetEncoder::fit(&rows, &targets, 1.0));
+    assert_eq!(encoded.len(), rows.len());
+
+    // Changing one row's target moves other rows' encodings, never its own.
+    let mut others_moved = false;
+    for i in 0..rows.len() {
+        let mut changed = targets.clone();
+        changed[i] += 100.0;
+        let (_, reencoded) = encode(&changed);
+        assert_eq!(reencoded[i], encoded[i], "row {} saw its own target", i);
+        others_moved |= reencoded != encoded;
+    }
+    assert!(others_moved);
+}
+
+#[test]
+fn fourier_features_are_sin_cos_pairs_of_scaled_projections() {
+    let features = FourierFeatures::new(2, 3, 1.0, &mut StdRng::seed_from_u64(8));
+    assert_eq!(features.n_outputs(), 6);
+    let x = [0.3, -0.8];
+    let y = features.transform_row(&x);
+    assert_eq!(y.len(), 6);
+    for j in 0..3 {
+        assert!((y[j].powi(2) + y[3 + j].powi(2) - 1.0).abs() < 1e-12);
+    }
+    assert_eq!(
+        features.transform_row(&[0.0, 0.0]),
+        [0.0, 0.0, 0.0, 1.0, 1.0, 1.0]
+    );
+
+    // The same draws at twice the scale are the original map at 2x.
+    let doubled = FourierFeatures::new(2, 3, 2.0, &mut StdRng::seed_from_u64(8));
+    assert_eq!(
+        doubled.transform_row(&x),
+        features.transform_row(&[0.6, -1.6])
+    );
+    // So by the double-angle formulas, sin(2p) = 2 sin(p) cos(p).
+    let twice = doubled.transform_row(&x);
+    for j in 0..3 {
+        assert!((twice[j] - 2.0 * y[j] * y[3 + j]).abs() < 1e-12);
+    }
+    assert_eq!(features.transform(&[x.to_vec()]), [y]);
+}
+
+#[test]
+fn polynomial_features_group_powers_by_feature() {
+    assert_eq!(
+        polynomial_features(&[2.0, -3.0], 3),
+        [2.0, 4.0, 8.0, -3.0, 9.0, -27.0]
+    );
+    assert_eq!(polynomial_features(&[0.5], 1), [0.5]);
+    assert!(polynomial_features(&[1.0, 2.0], 0).is_empty());
+}
+
+#[test]
+fn pipeline_fills_then_standardizes_and_round_trips() {
+    let inputs = vec![
+        vec![1.0, 5.0, f64::NAN],
+        vec![3.0, 5.0, 2.0],
+        vec![f64::NAN, 5.0, 4.0],
+    ];
+    let pipeline = Pipeline::fit(&inputs, Some(Impute::Mean));
+    assert_eq!(pipeline.n_features(), 3);
+    // Filled columns are [1, 3, 2], [5, 5, 5] and [3, 2, 4].
+    let std = (2.0f64 / 3.0).sqrt();
+    let row = pipeline.transform_row(&[f64::NAN, 7.0, 4.0]);
+    assert_eq!(row, vec![0.0, 2.0, 1.0 / std]);
+
+    let path = temp_dir("pipeline.txt");
+    pipeline.save(&path).unwrap();
+    let loaded = Pipeline::load(&path).unwrap();
+    assert_eq!(loaded, pipeline);
+
+    let raw = Pipeline::fit(&inputs, None);
+    raw.save(&path).unwrap();
+    let loaded = Pipeline::load(&path).unwrap();
+    assert!(loaded.transform_row(&[f64::NAN, 5.0, 3.0])[0].is_nan());
+    assert_eq!(loaded, raw);
+
+    fs::write(
+        &path,
+        "micrograd-preprocess v1\nfill none\nmean 1 2\nstd 1\n",
+    )
+    .unwrap();
+    let err = Pipeline::load(&path).unwrap_err();
+    fs::remove_file(&path).unwrap();
+    assert_eq!(
+        err.to_string(),
+        "fill, mean and std must have the same length"
+    );
+}
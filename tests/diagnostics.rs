@@ -0,0 +1,14 @@
+use micrograd::diagnostics::gradient_noise_scale;
+use micrograd::Value;
+
+#[test]
+fn gradient_noise_scale_matches_a_hand_computed_batch() {
+    let params = vec![Value::new(0.5), Value::new(-1.5)];
+    let (a, b) = (&params[0], &params[1]);
+    // Per-sample gradients (2, 1), (4, 3) and (0, 2): mean G = (2, 2) and
+    // squared deviations 1 + 5 + 4, so tr(Sigma) = 10 / 2 and |G|^2 = 8.
+    let losses = vec![a * 2.0 + b, a * 4.0 + b * 3.0, a * 0.0 + b * 2.0];
+    let scale = gradient_noise_scale(&params, losses);
+    assert!((scale - 5.0 / 8.0).abs() < 1e-12, "scale {}", scale);
+    assert!(params.iter().all(|p| p.get_grad() == 0.0));
+}
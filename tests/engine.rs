@@ -0,0 +1,415 @@
+use micrograd::engine::{
+    set_domain_policy, take_domain_error, DiffKind, DomainError, DomainPolicy, EXP_MAX_ARG,
+};
+use micrograd::gradcheck::gradcheck;
+use micrograd::{Op, Value};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+fn assert_close(actual: f64, expected: f64) {
+    assert!(
+        (actual - expected).abs() < 1e-9,
+        "expected {}, got {}",
+        expected,
+        actual
+    );
+}
+
+#[test]
+fn reused_node_through_tanh_and_exp() {
+    let x = Value::new(0.5);
+    let y = x.clone().tanh() + x.clone().exp();
+    y.backward();
+    assert_close(x.get_grad(), 1.0 - 0.5_f64.tanh().powi(2) + 0.5_f64.exp());
+}
+
+#[test]
+fn reused_node_through_pow() {
+    let x = Value::new(1.5);
+    let y = x.clone().pow(Value::new(2.0)) + x.clone().pow(Value::new(3.0));
+    y.backward();
+    assert_close(x.get_grad(), 2.0 * 1.5 + 3.0 * 1.5 * 1.5);
+}
+
+#[test]
+fn reused_intermediate_feeding_unary_ops() {
+    let x = Value::new(0.3);
+    let h = &x * 2.0;
+    let y = h.clone().tanh() * h.clone().exp() + h.clone().sigmoid();
+    y.backward();
+    let h0 = 0.6_f64;
+    let s = 1.0 / (1.0 + (-h0).exp());
+    let dy_dh = (1.0 - h0.tanh().powi(2)) * h0.exp() + h0.tanh() * h0.exp() + s * (1.0 - s);
+    assert_close(x.get_grad(), 2.0 * dy_dh);
+}
+
+#[test]
+fn node_used_as_both_operands() {
+    let x = Value::new(-1.25);
+    (&x * &x + &x + &x).backward();
+    assert_close(x.get_grad(), 2.0 * -1.25 + 2.0);
+}
+
+#[test]
+fn unary_ops_accumulate_under_audit() {
+    let x = Value::new(0.8);
+    let ops = [
+        x.clone().tanh(),
+        x.clone().exp(),
+        x.clone().ln(),
+        x.clone().relu(),
+        x.clone().sqrt(),
+        x.clone().abs(),
+        x.clone().sin(),
+        x.clone().cos(),
+        x.clone().selu(),
+        x.clone().sigmoid(),
+        x.clone().hard_sigmoid(),
+        x.clone().hard_tanh(),
+        x.clone().pow(Value::new(2.0)),
+    ];
+    let y = ops.iter().fold(Value::new(0.0), |acc, v| acc + v);
+    assert_eq!(y.audit_backward(), vec![]);
+}
+
+#[test]
+fn reused_nodes_match_finite_differences() {
+    let a = Value::new(0.7);
+    let b = Value::new(-0.4);
+    let report = gradcheck(
+        &[a.clone(), b.clone()],
+        || {
+            let h = (&a * &b).tanh();
+            (&h * &h + h.clone().exp()) / (a.clone().exp() + 1.0) - b.clone().pow(Value::new(3.0))
+        },
+        1e-6,
+    );
+    assert!(report.passed(1e-6), "{:?}", report);
+}
+
+#[test]
+fn constants_are_skipped_by_backward() {
+    let w = Value::new(2.0);
+    let c = Value::constant(3.0);
+    let k = &c * 4.0;
+    assert!(!k.requires_grad());
+    let y = &w * &k;
+    assert!(y.requires_grad() && !y.is_leaf());
+    y.backward();
+    assert_close(w.get_grad(), 12.0);
+    assert_close(c.get_grad(), 0.0);
+}
+
+#[test]
+fn constant_operands_get_no_grad() {
+    let w = Value::new(2.0);
+    let c = Value::constant(3.0);
+    let y = (&w * &c) + &c + w.clone().max(c.clone()) + c.clone().pow(w.clone());
+    y.backward();
+    // 3 + 0 (c wins the max) + 3^2 ln 3.
+    assert_close(w.get_grad(), 3.0 + 9.0 * 3f64.ln());
+    assert_eq!(c.get_grad(), 0.0);
+}
+
+#[test]
+fn intermediate_grads_are_released_unless_retained() {
+    let x = Value::new(1.5);
+    let h = &x * 2.0;
+    let kept = &x + 1.0;
+    kept.retain_grad();
+    let y = &h * &kept;
+    y.backward();
+    assert_close(h.get_grad(), 0.0);
+    assert_close(kept.get_grad(), 3.0);
+    assert_close(x.get_grad(), 2.0 * 2.5 + 3.0);
+}
+
+#[test]
+fn pow_differentiates_the_exponent() {
+    let a = Value::new(1.7);
+    let b = Value::new(0.6);
+    let report = gradcheck(
+        &[a.clone(), b.clone()],
+        || a.clone().pow(b.clone()) * b.clone().pow(a.clone()),
+        1e-6,
+    );
+    assert!(report.passed(1e-6), "{:?}", report);
+}
+
+#[test]
+fn diff_graph_reports_rewritten_nodes() {
+    let x = Value::new(2.0);
+    let y = Value::new(3.0);
+    let before = (&x * &y) + (&x * &y) + Value::constant(1.0) * Value::constant(4.0);
+    let xy = &x * &y;
+    let after = &xy + &xy + Value::constant(4.0);
+    assert!(before.diff_graph(&before, 0.0).is_empty());
+
+    let diffs = before.diff_graph(&after, 0.0);
+    let kinds: Vec<DiffKind> = diffs.into_iter().map(|d| d.kind).collect();
+    assert_eq!(
+        kinds,
+        vec![DiffKind::Sharing, DiffKind::Op(Some(Op::Mul), None)]
+    );
+}
+
+#[test]
+fn topo_order_is_post_order_in_operand_order() {
+    let a = Value::new(1.0);
+    let b = Value::new(2.0);
+    let c = Value::new(3.0);
+    let ab = &a * &b;
+    let root = &ab + &(&c + &a);
+    let order = root.topo_order();
+    let data: Vec<f64> = order.iter().map(|v| v.get_data()).collect();
+    assert_eq!(data, vec![1.0, 2.0, 2.0, 3.0, 4.0, 6.0]);
+    assert_eq!(order, root.topo_order());
+}
+
+#[test]
+fn sum_builds_a_balanced_tree() {
+    let xs: Vec<Value> = (1..=1000).map(|i| Value::new(i as f64)).collect();
+    let total = Value::sum(&xs);
+    assert_close(total.get_data(), 500_500.0);
+    let mean = Value::mean(&xs);
+    assert_close(mean.get_data(), 500.5);
+    mean.backward();
+    for x in xs.iter() {
+        assert_close(x.get_grad(), 1e-3);
+    }
+
+    let mut depth = 0;
+    let mut node = total;
+    while let Some(first) = node.get_prev().into_iter().next() {
+        node = first;
+        depth += 1;
+    }
+    assert_eq!(depth, 10);
+    assert_close(Value::sum(&[]).get_data(), 0.0);
+}
+
+#[test]
+fn softmax_is_stable_and_differentiable() {
+    let logits: Vec<Value> = [1000.0, 999.0, -5.0]
+        .iter()
+        .map(|&z| Value::new(z))
+        .collect();
+    let probs = Value::softmax(&logits);
+    let total: f64 = probs.iter().map(|p| p.get_data()).sum();
+    assert_close(total, 1.0);
+    assert_close(
+        probs[0].get_data(),
+        1.0 / (1.0 + (-1.0_f64).exp() + (-1005.0_f64).exp()),
+    );
+
+    // d(-ln p_class)/dz_i = p_i - [i == class]
+    let loss = micrograd::loss::softmax_cross_entropy(&logits, 1);
+    let expected: Vec<f64> = probs.iter().map(|p| p.get_data()).collect();
+    loss.backward();
+    for (i, z) in logits.iter().enumerate() {
+        let onehot = if i == 1 { 1.0 } else { 0.0 };
+        assert_close(z.get_grad(), expected[i] - onehot);
+    }
+}
+
+#[test]
+fn long_chain_backpropagates_and_drops() {
+    // Deep enough that recursive traversal or drop would overflow the stack.
+    let x = Value::new(0.5);
+    let mut y = x.clone();
+    for _ in 0..100_000 {
+        y = &y + &x;
+    }
+    assert_close(y.get_data(), 0.5 * 100_001.0);
+    // `backward` consumes the handle, dropping the whole chain with it.
+    y.backward();
+    assert_close(x.get_grad(), 100_001.0);
+}
+
+#[test]
+fn gaussian_samples_have_the_requested_moments() {
+    let mut rng = StdRng::seed_from_u64(11);
+    let (mu, std): (f64, f64) = (1.5, 0.5);
+    let xs: Vec<f64> = (0..20_000)
+        .map(|_| {
+            let log_var = Value::new((std * std).ln());
+            Value::gaussian(Value::new(mu), log_var, &mut rng).get_data()
+        })
+        .collect();
+    let mean = xs.iter().sum::<f64>() / xs.len() as f64;
+    let var = xs.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / xs.len() as f64;
+    assert!((mean - mu).abs() < 0.01, "mean {}", mean);
+    assert!((var - std * std).abs() < 0.01, "variance {}", var);
+
+    // Reparameterized: d/dmu = 1 and d/dlog_var = (x - mu) / 2.
+    let (m, log_var) = (Value::new(mu), Value::new((std * std).ln()));
+    let x = Value::gaussian(m.clone(), log_var.clone(), &mut rng);
+    let sample = x.get_data();
+    x.backward();
+    assert_close(m.get_grad(), 1.0);
+    assert_close(log_var.get_grad(), (sample - mu) / 2.0);
+}
+
+#[test]
+fn gumbel_softmax_samples_a_distribution() {
+    let logits: Vec<Value> = [0.5, -1.0, 2.0].iter().map(|&x| Value::new(x)).collect();
+    let mut rng = StdRng::seed_from_u64(4);
+    for _ in 0..20 {
+        let soft = Value::gumbel_softmax(&logits, 0.7, false, &mut rng);
+        assert_close(soft.iter().map(|y| y.get_data()).sum(), 1.0);
+        assert!(soft.iter().all(|y| y.get_data() > 0.0));
+
+        let hard: Vec<f64> = Value::gumbel_softmax(&logits, 0.7, true, &mut rng)
+            .iter()
+            .map(|y| y.get_data())
+            .collect();
+        assert_eq!(hard.iter().filter(|&&y| y == 1.0).count(), 1, "{:?}", hard);
+        assert_eq!(hard.iter().filter(|&&y| y == 0.0).count(), 2, "{:?}", hard);
+    }
+
+    // With the noise fixed by reseeding, the soft sample is a smooth
+    // function of the logits.
+    let weights = [1.0, -2.0, 0.5];
+    let report = gradcheck(
+        &logits,
+        || {
+            let soft = Value::gumbel_softmax(&logits, 0.7, false, &mut StdRng::seed_from_u64(9));
+            let terms: Vec<Value> = soft.iter().zip(weights).map(|(y, w)| y * w).collect();
+            Value::sum(&terms)
+        },
+        1e-6,
+    );
+    assert!(report.passed(1e-6), "{:?}", report);
+}
+
+#[test]
+fn hard_gumbel_softmax_passes_the_soft_gradient_through() {
+    let logits: Vec<Value> = [0.5, -1.0, 2.0].iter().map(|&x| Value::new(x)).collect();
+    let weights = [1.0, -2.0, 0.5];
+    let grads = |hard: bool| -> Vec<f64> {
+        let ys = Value::gumbel_softmax(&logits, 0.7, hard, &mut StdRng::seed_from_u64(9));
+        let terms: Vec<Value> = ys.iter().zip(weights).map(|(y, w)| y * w).collect();
+        for logit in logits.iter() {
+            logit.update_grad(0.0);
+        }
+        Value::sum(&terms).backward();
+        logits.iter().map(|l| l.get_grad()).collect()
+    };
+    let (soft, hard) = (grads(false), grads(true));
+    for (s, h) in soft.iter().zip(&hard) {
+        assert_close(*h, *s);
+    }
+}
+
+/// `tanh(a * b + a)`: nodes a, b, a*b, +, tanh in topological order.
+fn small_graph() -> Value {
+    let a = Value::new(2.0);
+    let b = Value::new(-3.0);
+    let c = &a * &b;
+    (c + a).tanh()
+}
+
+#[test]
+fn graphml_lists_every_node_and_operand_edge() {
+    let xml = small_graph().to_graphml();
+    assert!(xml.starts_with("<?xml"));
+    assert_eq!(xml.matches("<node ").count(), 5);
+    assert_eq!(xml.matches("<edge ").count(), 5);
+    for (source, target) in [(0, 2), (1, 2), (2, 3), (0, 3), (3, 4)] {
+        let edge = format!("<edge source=\"n{}\" target=\"n{}\"/>", source, target);
+        assert!(xml.contains(&edge), "missing {}", edge);
+    }
+    let ops: Vec<&str> = xml
+        .lines()
+        .filter_map(|l| l.trim().strip_prefix("<data key=\"op\">"))
+        .map(|l| l.trim_end_matches("</data>"))
+        .collect();
+    assert_eq!(ops, ["Mul", "Add", "Tanh"]);
+    assert!(xml.contains("<data key=\"data\">-3</data>"));
+}
+
+#[test]
+fn dot_has_a_record_per_value_and_a_node_per_op() {
+    let dot = small_graph().to_dot();
+    assert!(dot.starts_with("digraph {\n  rankdir=LR;\n") && dot.ends_with("}\n"));
+    let lines: Vec<&str> = dot.lines().map(str::trim).collect();
+    let records = lines.iter().filter(|l| l.contains("shape=record")).count();
+    assert_eq!(records, 5);
+    assert!(lines.contains(&"n1 [shape=record, label=\"{ data -3.0000 | grad 0.0000 }\"];"));
+    for (i, op) in [(2, "Mul"), (3, "Add"), (4, "Tanh")] {
+        assert!(lines.contains(&format!("n{}_op [label=\"{}\"];", i, op).as_str()));
+        assert!(lines.contains(&format!("n{}_op -> n{};", i, i).as_str()));
+    }
+    // Leaves have no op node; operands point into their result's op.
+    assert!(!dot.contains("n0_op") && !dot.contains("n1_op"));
+    for (source, target) in [(0, 2), (1, 2), (2, 3), (0, 3), (3, 4)] {
+        assert!(lines.contains(&format!("n{} -> n{}_op;", source, target).as_str()));
+    }
+    assert_eq!(lines.iter().filter(|l| l.contains("->")).count(), 3 + 5);
+}
+
+#[test]
+fn exp_clamps_large_arguments_to_finite_values_and_grads() {
+    for x in [1000.0, -1000.0] {
+        let a = Value::new(x);
+        let y = a.clone().exp();
+        y.clone().backward();
+        let expected = (EXP_MAX_ARG * x.signum()).exp();
+        assert!(y.get_data().is_finite() && y.get_data() > 0.0);
+        assert_eq!(y.get_data(), expected);
+        assert!(a.get_grad().is_finite());
+        assert_eq!(a.get_grad(), expected);
+    }
+}
+
+#[test]
+fn detach_blocks_gradient_flow() {
+    let a = Value::new(3.0);
+    let b = Value::new(2.0);
+    let hidden = a.clone() * b.clone();
+    let detached = hidden.detach();
+    assert_eq!(detached.get_data(), 6.0);
+    assert!(detached.get_prev().is_empty());
+
+    let y = detached * b.clone();
+    y.backward();
+    assert_eq!(a.get_grad(), 0.0);
+    assert_eq!(b.get_grad(), 6.0);
+}
+
+#[test]
+fn release_graph_unlinks_nodes_but_keeps_grads() {
+    let a = Value::new(3.0);
+    let b = Value::new(2.0);
+    let hidden = a.clone() * b.clone();
+    let y = hidden.clone() + a.clone();
+    y.clone().backward();
+    y.release_graph();
+
+    assert!(y.get_prev().is_empty());
+    assert!(hidden.get_prev().is_empty());
+    assert_eq!(y.get_data(), 9.0);
+    assert_eq!(a.get_grad(), 3.0);
+    assert_eq!(b.get_grad(), 3.0);
+}
+
+#[test]
+fn error_policy_records_the_first_violation_until_taken() {
+    let previous = set_domain_policy(DomainPolicy::Error);
+    take_domain_error();
+    let y = Value::new(-1.0).ln();
+    Value::new(-2.0).ln();
+    let error = take_domain_error();
+    let after = take_domain_error();
+    set_domain_policy(previous);
+
+    assert!(y.get_data().is_nan());
+    assert_eq!(
+        error,
+        Some(DomainError {
+            op: Op::Log,
+            input: -1.0
+        })
+    );
+    assert_eq!(after, None);
+}
@@ -0,0 +1,59 @@
+use micrograd::data::Dataset;
+use micrograd::explain::{integrated_gradients, permutation_importance};
+use micrograd::{Activation, Module, MLP};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+#[test]
+fn integrated_gradients_are_complete() {
+    let model = MLP::new_seeded(3, vec![5, 2], Activation::Tanh, 21);
+    let (input, baseline) = ([0.8, -1.2, 0.4], [0.0, 0.0, 0.0]);
+    for output in 0..2 {
+        let attributions = integrated_gradients(&model, &input, &baseline, output, 200);
+        let gap = model.call(&input)[output].get_data() - model.call(&baseline)[output].get_data();
+        let total: f64 = attributions.iter().sum();
+        assert!((total - gap).abs() < 1e-5, "{} vs {}", total, gap);
+    }
+    assert!(model.parameters().iter().all(|p| p.get_grad() == 0.0));
+
+    // For a linear model each attribution is exactly w_j (x_j - b_j).
+    let linear = MLP::new_seeded(3, vec![1], Activation::Linear, 2);
+    let w: Vec<f64> = linear.parameters()[..3]
+        .iter()
+        .map(|p| p.get_data())
+        .collect();
+    let baseline = [0.5, 0.5, -0.5];
+    let attributions = integrated_gradients(&linear, &input, &baseline, 0, 3);
+    for j in 0..3 {
+        let expected = w[j] * (input[j] - baseline[j]);
+        assert!((attributions[j] - expected).abs() < 1e-12);
+    }
+}
+
+#[test]
+fn permutation_importance_ignores_unused_features() {
+    let inputs: Vec<Vec<f64>> = (0..40)
+        .map(|i| vec![i as f64 / 20.0 - 1.0, ((i * 7) % 11) as f64])
+        .collect();
+    let targets = inputs.iter().map(|x| vec![3.0 * x[0]]).collect();
+    let data = Dataset::new(inputs, targets);
+    let neg_mse = |preds: &[Vec<f64>], targets: &[Vec<f64>]| {
+        -preds
+            .iter()
+            .zip(targets)
+            .map(|(p, t)| (p[0] - t[0]).powi(2))
+            .sum::<f64>()
+            / preds.len() as f64
+    };
+    // The model uses only feature 0.
+    let importance = permutation_importance(
+        |x: &[f64]| vec![3.0 * x[0]],
+        &data,
+        neg_mse,
+        5,
+        &mut StdRng::seed_from_u64(3),
+    );
+    assert_eq!(importance.len(), 2);
+    assert!(importance[0] > 1.0, "importance {:?}", importance);
+    assert_eq!(importance[1], 0.0);
+}
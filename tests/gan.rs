@@ -0,0 +1,55 @@
+use micrograd::gan::GanTrainer;
+use micrograd::optim::{Adam, SGD};
+use micrograd::{Activation, Init, Module, MLP};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+fn mean(samples: &[Vec<f64>]) -> f64 {
+    samples.iter().map(|s| s[0]).sum::<f64>() / samples.len() as f64
+}
+
+#[test]
+fn losses_at_an_undecided_discriminator() {
+    let g = MLP::new_seeded(2, vec![4, 1], Activation::Tanh, 0);
+    // All-zero weights: every logit is 0, so D = 1/2 everywhere.
+    let mut rng = StdRng::seed_from_u64(0);
+    let d = MLP::with_init(1, vec![4, 1], Activation::Tanh, None, Init::Zeros, &mut rng);
+    let real = vec![vec![1.0], vec![-1.0]];
+    let mut trainer = GanTrainer::new(
+        &g,
+        &d,
+        SGD::new(g.parameters(), 0.0),
+        SGD::new(d.parameters(), 0.0),
+    )
+    .batch_size(4)
+    .d_steps(3)
+    .seed(1);
+    let step = trainer.step(&real);
+    let ln2 = 2f64.ln();
+    assert!((step.d_loss - 2.0 * ln2).abs() < 1e-12);
+    assert!((step.g_loss - ln2).abs() < 1e-12);
+
+    let skipped = trainer.g_steps(0).step(&real);
+    assert!(skipped.g_loss.is_nan());
+}
+
+#[test]
+fn generator_matches_a_shifted_distribution() {
+    let mut rng = StdRng::seed_from_u64(0);
+    let real: Vec<Vec<f64>> = (0..200)
+        .map(|_| vec![3.0 + rng.gen_range(-0.5..0.5)])
+        .collect();
+    let g = MLP::new_seeded(1, vec![8, 1], Activation::Tanh, 1);
+    let d = MLP::new_seeded(1, vec![8, 1], Activation::Tanh, 2);
+    let adam = |net: &MLP| Adam::new(net.parameters(), 0.02).betas(0.5, 0.999);
+    let mut trainer = GanTrainer::new(&g, &d, adam(&g), adam(&d))
+        .batch_size(16)
+        .seed(3);
+    let before = mean(&trainer.generate(200));
+    assert!((before - 3.0).abs() > 1.0, "already at {}", before);
+
+    let steps = trainer.fit(&real, 150);
+    assert_eq!(steps.len(), 150);
+    let after = mean(&trainer.generate(200));
+    assert!((after - 3.0).abs() < 0.4, "generator mean {}", after);
+}
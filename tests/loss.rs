@@ -0,0 +1,137 @@
+use micrograd::loss::{
+    binary_cross_entropy, hinge, mae, mse, soft_cross_entropy, softmax_cross_entropy, BCELoss,
+    CrossEntropyLoss, HingeLoss, Loss, MAELoss, MSELoss, MultiTaskLoss, Reduction,
+};
+use micrograd::Value;
+
+fn values(xs: &[f64]) -> Vec<Value> {
+    xs.iter().map(|&x| Value::new(x)).collect()
+}
+
+#[test]
+fn mse_loss_averages_within_a_sample() {
+    let preds = vec![values(&[1.0, 3.0]), values(&[0.0, 0.0])];
+    let targets = vec![vec![2.0, 0.0], vec![1.0, 1.0]];
+    assert_eq!(mse(&preds[0], &targets[0]).get_data(), 5.0);
+    let per_sample: Vec<f64> = MSELoss::new(Reduction::Mean)
+        .per_sample(&preds, &targets)
+        .iter()
+        .map(|l| l.get_data())
+        .collect();
+    assert_eq!(per_sample, vec![5.0, 1.0]);
+    assert_eq!(
+        MSELoss::new(Reduction::Mean)
+            .call(&preds, &targets)
+            .get_data(),
+        3.0
+    );
+    assert_eq!(
+        MSELoss::new(Reduction::Sum)
+            .call(&preds, &targets)
+            .get_data(),
+        6.0
+    );
+}
+
+/// `loss` over a batch holding just this sample.
+fn single(loss: &dyn Loss, pred: &[Value], target: &[f64]) -> f64 {
+    loss.call(&[pred.to_vec()], &[target.to_vec()]).get_data()
+}
+
+#[test]
+fn loss_types_match_the_free_functions() {
+    let pred = values(&[0.2, 0.9]);
+    let (target, signs) = ([0.0, 1.0], [-1.0, 1.0]);
+    assert_eq!(
+        single(&MAELoss::new(Reduction::Mean), &pred, &target),
+        mae(&pred, &target).get_data()
+    );
+    assert_eq!(
+        single(&BCELoss::new(Reduction::Mean), &pred, &target),
+        binary_cross_entropy(&pred, &target).get_data()
+    );
+    assert_eq!(
+        single(&HingeLoss::new(Reduction::Mean), &pred, &signs),
+        hinge(&pred, &signs).get_data()
+    );
+    assert_eq!(
+        single(&CrossEntropyLoss::new(Reduction::Mean), &pred, &[1.0]),
+        softmax_cross_entropy(&pred, 1).get_data()
+    );
+}
+
+#[test]
+fn soft_cross_entropy_takes_probability_targets() {
+    let logits = values(&[0.5, -1.0, 2.0]);
+    let one_hot = soft_cross_entropy(&logits, &[0.0, 0.0, 1.0]).get_data();
+    assert!((one_hot - softmax_cross_entropy(&logits, 2).get_data()).abs() < 1e-12);
+
+    let probs = [0.2, 0.3, 0.5];
+    let loss = soft_cross_entropy(&logits, &probs);
+    let softmax: Vec<f64> = Value::softmax(&logits)
+        .iter()
+        .map(|p| p.get_data())
+        .collect();
+    let expected: f64 = -probs
+        .iter()
+        .zip(&softmax)
+        .map(|(p, q)| p * q.ln())
+        .sum::<f64>();
+    assert!((loss.get_data() - expected).abs() < 1e-12);
+    // The loss type dispatches to it for a target per output.
+    let single_loss = single(&CrossEntropyLoss::new(Reduction::Mean), &logits, &probs);
+    assert!((single_loss - expected).abs() < 1e-12);
+
+    loss.backward();
+    for ((z, q), p) in logits.iter().zip(&softmax).zip(&probs) {
+        assert!((z.get_grad() - (q - p)).abs() < 1e-12);
+    }
+}
+
+#[test]
+fn multi_task_loss_weights_each_head() {
+    // A regression head on output 0 and a 3-class head on outputs 1..4.
+    let loss = MultiTaskLoss::new(Reduction::Mean)
+        .task("value", MSELoss::new(Reduction::Mean), 1, 1, 2.0)
+        .task("class", CrossEntropyLoss::new(Reduction::Mean), 3, 1, 0.5);
+    let pred = values(&[1.5, 0.1, 2.0, -1.0]);
+    let target = [0.5, 1.0];
+    let class = softmax_cross_entropy(&pred[1..], 1).get_data();
+    assert!((single(&loss, &pred, &target) - (2.0 * 1.0 + 0.5 * class)).abs() < 1e-12);
+    assert_eq!(loss.weights(), [2.0, 0.5]);
+    assert!(loss.parameters().is_empty());
+
+    let tasks = loss.task_losses(&[pred.clone(), pred], &[target.to_vec(), target.to_vec()]);
+    assert_eq!(tasks[0], ("value".to_string(), 1.0));
+    assert_eq!(tasks[1].0, "class");
+    assert!((tasks[1].1 - class).abs() < 1e-12);
+}
+
+#[test]
+fn uncertainty_weighting_learns_log_variances() {
+    let loss = MultiTaskLoss::new(Reduction::Mean)
+        .task("a", MSELoss::new(Reduction::Mean), 1, 1, 1.0)
+        .task("b", MSELoss::new(Reduction::Mean), 1, 1, 1.0)
+        .uncertainty_weighting();
+    let log_vars = loss.parameters();
+    assert_eq!(log_vars.len(), 2);
+    log_vars[1].update_data(2f64.ln());
+
+    // Squared errors 4 and 9; e^-s * loss + s per task.
+    let total = loss.call(&[values(&[2.0, 3.0])], &[vec![0.0, 0.0]]);
+    assert!((total.get_data() - (4.0 + 4.5 + 2f64.ln())).abs() < 1e-12);
+    total.backward();
+    // d/ds = 1 - e^-s * loss.
+    assert!((log_vars[0].get_grad() - (1.0 - 4.0)).abs() < 1e-12);
+    assert!((log_vars[1].get_grad() - (1.0 - 4.5)).abs() < 1e-12);
+    assert_eq!(loss.weights(), [1.0, 0.5]);
+}
+
+#[test]
+#[should_panic(expected = "Tasks cover 2 outputs and 2 targets, got 3 and 2.")]
+fn multi_task_loss_checks_the_widths() {
+    let loss = MultiTaskLoss::new(Reduction::Mean)
+        .task("a", MSELoss::new(Reduction::Mean), 1, 1, 1.0)
+        .task("b", MSELoss::new(Reduction::Mean), 1, 1, 1.0);
+    loss.call(&[values(&[1.0, 2.0, 3.0])], &[vec![0.0, 0.0]]);
+}
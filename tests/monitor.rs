@@ -0,0 +1,88 @@
+#![cfg(feature = "monitor")]
+
+use micrograd::monitor::{Dashboard, MetricsExporter};
+use micrograd::train::{BatchEnd, Callback, EpochEnd};
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream};
+
+/// The status line and body of a request to `addr`.
+fn request(addr: SocketAddr, method: &str, path: &str) -> (String, String) {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    write!(
+        stream,
+        "{} {} HTTP/1.1\r\nHost: localhost\r\n\r\n",
+        method, path
+    )
+    .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    let (head, body) = response.split_once("\r\n\r\n").unwrap();
+    (head.lines().next().unwrap().to_string(), body.to_string())
+}
+
+#[test]
+fn metrics_exporter_serves_prometheus_text() {
+    let mut exporter = MetricsExporter::bind("127.0.0.1:0").unwrap();
+    let addr = exporter.local_addr();
+    let (status, body) = request(addr, "GET", "/metrics");
+    assert_eq!(status, "HTTP/1.1 200 OK");
+    assert!(body.contains("micrograd_steps_total 0\n"));
+    assert!(body.contains("micrograd_loss NaN\n"));
+
+    for batch in 0..3 {
+        exporter.on_batch_end(&BatchEnd {
+            epoch: 0,
+            batch,
+            loss: 0.5,
+            lr: 0.01,
+            grad_norm: 2.0,
+        });
+    }
+    exporter.on_epoch_end(&EpochEnd {
+        epoch: 0,
+        loss: 0.75,
+    });
+    let (_, body) = request(addr, "GET", "/metrics");
+    assert!(body.contains("# TYPE micrograd_steps_total counter\nmicrograd_steps_total 3\n"));
+    assert!(body.contains("micrograd_epochs_total 1\n"));
+    assert!(body.contains("micrograd_epoch_loss 0.75\n"));
+    assert!(body.contains("micrograd_learning_rate 0.01\n"));
+    assert!(body.contains("micrograd_grad_norm 2\n"));
+
+    assert_eq!(request(addr, "GET", "/nope").0, "HTTP/1.1 404 Not Found");
+    assert_eq!(
+        request(addr, "POST", "/metrics").0,
+        "HTTP/1.1 405 Method Not Allowed"
+    );
+}
+
+#[test]
+fn dashboard_serves_a_page_and_json_status() {
+    let mut dashboard = Dashboard::bind("127.0.0.1:0").unwrap();
+    let addr = dashboard.local_addr();
+    let (status, page) = request(addr, "GET", "/");
+    assert_eq!(status, "HTTP/1.1 200 OK");
+    assert!(page.contains("fetch(\"/status\")"));
+    assert_eq!(
+        request(addr, "GET", "/status").1,
+        r#"{"steps": 0, "epoch": 0, "batch": 0, "loss": null, "lr": null, "grad_norm": null, "history": []}"#
+    );
+
+    for epoch in 0..2 {
+        dashboard.on_batch_end(&BatchEnd {
+            epoch,
+            batch: 0,
+            loss: 1.5,
+            lr: 0.1,
+            grad_norm: 0.25,
+        });
+        dashboard.on_epoch_end(&EpochEnd {
+            epoch,
+            loss: 2.0 - epoch as f64,
+        });
+    }
+    assert_eq!(
+        request(addr, "GET", "/status").1,
+        r#"{"steps": 2, "epoch": 1, "batch": 0, "loss": 1.5, "lr": 0.1, "grad_norm": 0.25, "history": [2, 1]}"#
+    );
+}
@@ -0,0 +1,756 @@
+use micrograd::data::Dataset;
+use micrograd::loss::{Loss, MSELoss, Reduction};
+use micrograd::nn::{optimize_input, predict_mc, AlphaDropout, Ensemble, GradReverse, Precision};
+use micrograd::optim::{Adam, Optimizer, SGD};
+use micrograd::train::{truncated_bptt, Trainer};
+use micrograd::{
+    Activation, Autoencoder, Dropout, Init, LSTMCell, Layer, Module, RNNCell, RecurrentCell,
+    Residual, Sequential, StochasticDepth, Value, MLP, VAE,
+};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+
+/// A per-test file under the system temp dir.
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("micrograd-{}-{}", std::process::id(), name))
+}
+
+fn params(model: &MLP) -> Vec<f64> {
+    model.parameters().iter().map(|p| p.get_data()).collect()
+}
+
+#[test]
+fn save_load_round_trip() {
+    let path = temp_path("round-trip.txt");
+    let model = MLP::new_seeded(3, vec![4, 2], Activation::Tanh, 7);
+    model.save(&path).unwrap();
+    let loaded = MLP::load(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+
+    assert_eq!(params(&loaded), params(&model));
+    let x = [0.5, -1.0, 2.0];
+    let expected: Vec<f64> = model.call(&x).iter().map(|v| v.get_data()).collect();
+    let actual: Vec<f64> = loaded.call(&x).iter().map(|v| v.get_data()).collect();
+    assert_eq!(actual, expected);
+}
+
+#[test]
+fn load_rejects_mismatched_layer_widths() {
+    let path = temp_path("mismatched.txt");
+    let text = "micrograd-mlp v1\n\
+                layer 2 2 tanh\n\
+                0.1 0.2 0.0\n\
+                0.3 0.4 0.0\n\
+                layer 3 1 linear\n\
+                0.5 0.6 0.7 0.0\n";
+    fs::write(&path, text).unwrap();
+    let err = MLP::load(&path).err().unwrap();
+    fs::remove_file(&path).unwrap();
+
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+    assert_eq!(err.to_string(), "layer 1: expected 2 inputs, got 3");
+}
+
+/// A single linear neuron whose weights and bias are `values`.
+fn neuron_file(values: &[String], precision: Option<&str>) -> String {
+    let mut text = "micrograd-mlp v1\n".to_string();
+    if let Some(name) = precision {
+        text.push_str(&format!("precision {}\n", name));
+    }
+    text.push_str(&format!("layer {} 1 linear\n", values.len() - 1));
+    text.push_str(&values.join(" "));
+    text.push('\n');
+    text
+}
+
+/// The fields `save_with_precision` writes for `values`.
+fn encode(values: &[f64], precision: Precision, name: &str) -> Vec<String> {
+    let path = temp_path(name);
+    let fields: Vec<String> = values.iter().map(|v| v.to_string()).collect();
+    fs::write(&path, neuron_file(&fields, None)).unwrap();
+    MLP::load(&path)
+        .unwrap()
+        .save_with_precision(&path, precision)
+        .unwrap();
+    let text = fs::read_to_string(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    text.lines()
+        .last()
+        .unwrap()
+        .split(' ')
+        .map(String::from)
+        .collect()
+}
+
+/// The values `load` reads back from half-precision `fields`.
+fn decode(fields: &[&str], precision: &str, name: &str) -> Vec<f64> {
+    let path = temp_path(name);
+    let fields: Vec<String> = fields.iter().map(|f| f.to_string()).collect();
+    fs::write(&path, neuron_file(&fields, Some(precision))).unwrap();
+    let model = MLP::load(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    params(&model)
+}
+
+#[test]
+fn f16_encoding() {
+    let smallest_subnormal = 2f64.powi(-24);
+    let values = [
+        1.0,
+        -2.0,
+        65504.0,
+        1e6,
+        smallest_subnormal,
+        // Halfway between 1 and the next half, and between that and the one
+        // after: both round to the even mantissa.
+        1.0 + 2f64.powi(-11),
+        1.0 + 3.0 * 2f64.powi(-11),
+        0.0,
+    ];
+    assert_eq!(
+        encode(&values, Precision::F16, "f16-encode.txt"),
+        ["3c00", "c000", "7bff", "7c00", "0001", "3c00", "3c02", "0000"]
+    );
+    assert_eq!(
+        decode(
+            &["3c00", "7bff", "7c00", "0001", "fc00"],
+            "f16",
+            "f16-decode.txt"
+        ),
+        [
+            1.0,
+            65504.0,
+            f64::INFINITY,
+            smallest_subnormal,
+            f64::NEG_INFINITY
+        ]
+    );
+}
+
+#[test]
+fn bf16_encoding() {
+    let values = [1.0, -2.0, f64::NAN, f64::INFINITY, 1.0 + 2f64.powi(-8)];
+    let fields = encode(&values, Precision::BF16, "bf16-encode.txt");
+    assert_eq!(fields, ["3f80", "c000", "7fc0", "7f80", "3f80"]);
+
+    let decoded = decode(&["3f80", "7fc0", "7f80"], "bf16", "bf16-decode.txt");
+    assert_eq!(decoded[0], 1.0);
+    assert!(decoded[1].is_nan());
+    assert_eq!(decoded[2], f64::INFINITY);
+}
+
+#[test]
+fn save_load_round_trip_at_each_precision() {
+    // Relative error bounds: half a unit in the last place of each format.
+    let precisions = [
+        (Precision::F64, 0.0),
+        (Precision::F16, 2f64.powi(-11)),
+        (Precision::BF16, 2f64.powi(-8)),
+    ];
+    let model = MLP::new_seeded(4, vec![3, 2], Activation::ReLU, 11);
+    for (i, &(precision, tol)) in precisions.iter().enumerate() {
+        let path = temp_path(&format!("precision-{}.txt", i));
+        model.save_with_precision(&path, precision).unwrap();
+        let loaded = MLP::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        for (&a, &b) in params(&model).iter().zip(params(&loaded).iter()) {
+            assert!(
+                (a - b).abs() <= tol * a.abs(),
+                "{:?}: {} loaded as {}",
+                precision,
+                a,
+                b
+            );
+        }
+    }
+}
+
+/// Runs `MLP::load_keras_json` on `json`.
+fn load_keras(json: &str, name: &str) -> std::io::Result<MLP> {
+    let path = temp_path(name);
+    fs::write(&path, json).unwrap();
+    let result = MLP::load_keras_json(&path);
+    fs::remove_file(&path).unwrap();
+    result
+}
+
+#[test]
+fn keras_json_import() {
+    // Two inputs, two tanh units, one linear output; kernels are nin x nout.
+    let json = r#"[
+        {"kernel": [[0.5, -1.0], [0.25, 2.0]], "bias": [0.1, -0.2], "activation": "tanh"},
+        {"kernel": [[1.5], [-0.5]], "bias": [NaN]}
+    ]"#;
+    let model = load_keras(json, "keras.json").unwrap();
+    assert_eq!((model.n_inputs(), model.n_outputs()), (2, 1));
+    let p = params(&model);
+    // Per neuron: its weights (a kernel column), then its bias.
+    assert_eq!(p[..6], [0.5, 0.25, 0.1, -1.0, 2.0, -0.2]);
+    assert_eq!(p[6..8], [1.5, -0.5]);
+    assert!(p[8].is_nan());
+
+    let json = r#"[{"kernel": [[2.0]], "bias": [1.0], "activation": "relu"}]"#;
+    let model = load_keras(json, "keras-relu.json").unwrap();
+    assert_eq!(model.call(&[-3.0])[0].get_data(), 0.0);
+    assert_eq!(model.call(&[3.0])[0].get_data(), 7.0);
+}
+
+#[test]
+fn keras_json_errors() {
+    let message = |json: &str| {
+        let err = load_keras(json, "keras-error.json").err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        err.to_string()
+    };
+    assert_eq!(
+        message("[{\"kernel\": [[1.0]"),
+        "expected ',' or ']' at byte 18"
+    );
+    assert_eq!(message("[1, "), "unexpected end of input");
+    assert_eq!(message("[] x"), "trailing characters at byte 3");
+    assert_eq!(message("[1, 2,]"), "unexpected character at byte 6");
+    assert_eq!(message("{}"), "expected an array of layers");
+    assert_eq!(
+        message(r#"[{"kernel": [[1.0, 2.0]], "bias": [0.0]}]"#),
+        "layer 0: kernel rows must have 1 weights to match the bias"
+    );
+    assert_eq!(
+        message(
+            r#"[{"kernel": [[1.0, 2.0]], "bias": [0.0, 0.0]}, {"kernel": [[1.0]], "bias": [0.0]}]"#
+        ),
+        "layer 1: expected 2 inputs, got 1"
+    );
+    assert_eq!(
+        message(r#"[{"kernel": [[1.0]], "bias": "0"}]"#),
+        "layer 0: invalid bias"
+    );
+    assert_eq!(
+        message(r#"[{"kernel": [[1.0]], "bias": [0.0], "activation": "gelu"}]"#),
+        "layer 0: unsupported activation String(\"gelu\")"
+    );
+}
+
+#[test]
+fn with_init_takes_an_output_activation() {
+    let init = |output| {
+        let mut rng = StdRng::seed_from_u64(2);
+        MLP::with_init(
+            2,
+            vec![3, 4],
+            Activation::ReLU,
+            output,
+            Init::Xavier,
+            &mut rng,
+        )
+    };
+    // Same seed, so the same weights: only the output layer differs.
+    let (linear, sigmoid) = (init(None), init(Some(Activation::Sigmoid)));
+    let x = [0.3, -0.7];
+    for (l, s) in linear.call(&x).iter().zip(sigmoid.call(&x).iter()) {
+        let expected = 1.0 / (1.0 + (-l.get_data()).exp());
+        assert!((s.get_data() - expected).abs() < 1e-12);
+    }
+}
+
+#[test]
+fn builder_configures_layers_init_and_dropout() {
+    let build = || {
+        MLP::builder()
+            .input(3)
+            .hidden(16, Activation::ReLU)
+            .dropout(0.5)
+            .output(1, Activation::Sigmoid)
+            .init(Init::He)
+            .seed(42)
+            .build()
+    };
+    let mut model = build();
+    assert_eq!((model.n_inputs(), model.n_outputs()), (3, 1));
+    let p = params(&model);
+    assert_eq!(p.len(), 16 * 4 + 17);
+    assert_eq!(p, params(&build()));
+    // He with fan-in 3 draws from U(-sqrt(2), sqrt(2)); biases start at 0.
+    let (first, bias) = (&p[..3], p[3]);
+    assert!(first.iter().all(|w| w.abs() <= 2f64.sqrt()));
+    assert_eq!(bias, 0.0);
+
+    let x = [1.0, -0.5, 2.0];
+    let outputs =
+        |model: &MLP| -> Vec<f64> { (0..20).map(|_| model.call(&x)[0].get_data()).collect() };
+    let training = outputs(&model);
+    assert!(training.iter().any(|&y| y != training[0]));
+    model.eval();
+    let eval = outputs(&model);
+    assert!(eval.iter().all(|&y| y == eval[0] && y > 0.0 && y < 1.0));
+}
+
+#[test]
+#[should_panic(expected = "Dropout must follow a hidden layer.")]
+fn builder_rejects_dropout_on_the_input() {
+    MLP::builder().input(2).dropout(0.1);
+}
+
+#[test]
+fn autoencoder_learns_to_reconstruct() {
+    // Points on a curve in 3D, so a 1-wide code can capture them.
+    let inputs: Vec<Vec<f64>> = (0..24)
+        .map(|i| {
+            let t = i as f64 / 12.0 - 1.0;
+            vec![t, t * t - 0.5, 0.5 * t]
+        })
+        .collect();
+    let ae = Autoencoder::new_seeded(3, vec![8], 1, Activation::Tanh, 4);
+    let x: Vec<Value> = inputs[0].iter().map(|&v| Value::constant(v)).collect();
+    assert_eq!(ae.encode(&x).len(), 1);
+    assert_eq!(ae.forward(&x).len(), 3);
+
+    let error = |ae: &Autoencoder| -> f64 {
+        inputs
+            .iter()
+            .map(|x| ae.reconstruction_error(x))
+            .sum::<f64>()
+            / inputs.len() as f64
+    };
+    let before = error(&ae);
+    let data = Dataset::reconstruction(inputs.clone());
+    let adam = Adam::new(ae.parameters(), 0.02);
+    Trainer::new(&ae, MSELoss::new(Reduction::Mean), adam, &data)
+        .batch_size(8)
+        .epochs(300)
+        .shuffle(0)
+        .fit();
+    let after = error(&ae);
+    assert!(
+        after < 0.1 * before,
+        "error went from {} to {}",
+        before,
+        after
+    );
+}
+
+#[test]
+fn vae_training_lowers_the_loss() {
+    let data: Vec<Vec<f64>> = (0..16)
+        .map(|i| {
+            let t = i as f64 / 8.0 - 1.0;
+            vec![t, -t, 0.5 * t]
+        })
+        .collect();
+    let vae = VAE::new_seeded(3, vec![6], 1, Activation::Tanh, 9);
+    let mut rng = StdRng::seed_from_u64(1);
+    assert_eq!(vae.sample(&mut rng).len(), 3);
+
+    // Averaged over a few draws, since each loss samples the code.
+    let average_loss = |vae: &VAE, rng: &mut StdRng| -> f64 {
+        (0..20)
+            .map(|_| vae.loss(&data, 1.0, rng).get_data())
+            .sum::<f64>()
+            / 20.0
+    };
+    let before = average_loss(&vae, &mut rng);
+    let mut adam = Adam::new(vae.parameters(), 0.01);
+    for _ in 0..300 {
+        let loss = vae.loss(&data, 1.0, &mut rng);
+        adam.zero_grad();
+        loss.backward();
+        adam.step();
+    }
+    let after = average_loss(&vae, &mut rng);
+    assert!(
+        after < 0.5 * before,
+        "loss went from {} to {}",
+        before,
+        after
+    );
+}
+
+#[test]
+fn residual_adds_the_skip_connection() {
+    let block = Layer::new_with_rng(2, 2, Activation::Tanh, &mut StdRng::seed_from_u64(1));
+    let inputs = [Value::new(0.5), Value::new(-1.0)];
+    let inner: Vec<f64> = block
+        .forward(&inputs)
+        .iter()
+        .map(|v| v.get_data())
+        .collect();
+    let residual = Residual::new(block);
+    let outputs = residual.forward(&inputs);
+    assert!((outputs[0].get_data() - (0.5 + inner[0])).abs() < 1e-12);
+    assert!((outputs[1].get_data() - (-1.0 + inner[1])).abs() < 1e-12);
+    assert_eq!(residual.parameters().len(), 6);
+}
+
+#[test]
+fn grad_reverse_is_the_identity_with_a_flipped_gradient() {
+    let x = Value::new(0.8);
+    let outputs = GradReverse::new(0.5).forward(&[x.clone(), Value::constant(-2.0)]);
+    assert_eq!(data(&outputs), [0.8, -2.0]);
+    assert!(GradReverse::new(0.5).parameters().is_empty());
+
+    // d(3 y^2)/dy = 6 y = 4.8 upstream, reversed and scaled by lambda.
+    (&outputs[0] * &outputs[0] * 3.0).backward();
+    assert!((x.get_grad() - 4.8 * -0.5).abs() < 1e-12);
+}
+
+#[test]
+fn alpha_dropout_keeps_zero_mean_and_unit_variance() {
+    // Alternating +-1: mean 0 and variance 1 exactly.
+    let inputs: Vec<Value> = (0..20_000)
+        .map(|i| Value::constant(if i % 2 == 0 { 1.0 } else { -1.0 }))
+        .collect();
+    let mut dropout = AlphaDropout::new_seeded(0.2, 3);
+    let ys = data(&dropout.forward(&inputs));
+    let mean = ys.iter().sum::<f64>() / ys.len() as f64;
+    let var = ys.iter().map(|y| (y - mean).powi(2)).sum::<f64>() / ys.len() as f64;
+    assert!(mean.abs() < 0.02, "mean {}", mean);
+    assert!((var - 1.0).abs() < 0.03, "variance {}", var);
+    // Dropped units all share the lowest value, SELU's saturation after the
+    // affine correction.
+    let floor = ys.iter().cloned().fold(f64::INFINITY, f64::min);
+    let fraction = ys.iter().filter(|&&y| y == floor).count() as f64 / ys.len() as f64;
+    assert!((fraction - 0.2).abs() < 0.02, "dropped {}", fraction);
+
+    assert_eq!(ys, data(&AlphaDropout::new_seeded(0.2, 3).forward(&inputs)));
+    dropout.eval();
+    assert_eq!(data(&dropout.forward(&inputs)), data(&inputs));
+}
+
+#[test]
+fn widen_keeps_the_function_and_adds_parameters() {
+    let mut model = MLP::new_seeded(2, vec![3, 2], Activation::Tanh, 4);
+    let xs = [[0.3, -0.7], [1.2, 0.4], [-0.5, -1.5]];
+    let before: Vec<Vec<f64>> = xs.iter().map(|x| data(&model.call(x))).collect();
+    assert_eq!(model.parameters().len(), 3 * 3 + 2 * 4);
+
+    model.widen(0, 2);
+    // Five hidden neurons over two inputs, and six weights into each output.
+    assert_eq!(model.parameters().len(), 5 * 3 + 2 * 6);
+    for (x, before) in xs.iter().zip(&before) {
+        for (after, before) in data(&model.call(x)).iter().zip(before) {
+            assert!((after - before).abs() < 1e-12, "{} vs {}", after, before);
+        }
+    }
+}
+
+#[test]
+fn append_layer_stacks_a_linear_output_on_the_old_one() {
+    let mut model = MLP::new_seeded(2, vec![3, 2], Activation::Tanh, 4);
+    let x = [0.3, -0.7];
+    let old_out = data(&model.call(&x));
+    let old_params = params(&model);
+
+    model.append_layer(4, Activation::Tanh);
+    let new_params = params(&model);
+    assert_eq!(new_params.len(), old_params.len() + 4 * 3);
+    assert_eq!(new_params[..old_params.len()], old_params[..]);
+
+    // The old outputs, now through tanh, feed the new linear layer.
+    let hidden: Vec<f64> = old_out.iter().map(|y| y.tanh()).collect();
+    let out = data(&model.call(&x));
+    assert_eq!(out.len(), 4);
+    for (y, neuron) in out.iter().zip(new_params[old_params.len()..].chunks(3)) {
+        let expected = neuron[0] * hidden[0] + neuron[1] * hidden[1] + neuron[2];
+        assert!((y - expected).abs() < 1e-12);
+    }
+}
+
+#[test]
+fn optimize_input_climbs_a_linear_output() {
+    // y = 2 x0 - x1 + 0.5, so ascending moves x by lr * (2, -1) every step.
+    let model = MLP::new_seeded(2, vec![1], Activation::Linear, 1);
+    for (p, value) in model.parameters().iter().zip([2.0, -1.0, 0.5]) {
+        p.update_data(value);
+    }
+    let ascend = |ys: &[Value]| -ys[0].clone();
+    let y = |x: &[f64]| model.call(x)[0].get_data();
+    let mut previous = y(&[0.1, 0.2]);
+    for steps in 1..=5 {
+        let x = optimize_input(&model, &[0.1, 0.2], ascend, steps, 0.1);
+        let expected = [0.1 + 0.2 * steps as f64, 0.2 - 0.1 * steps as f64];
+        assert!((x[0] - expected[0]).abs() < 1e-12 && (x[1] - expected[1]).abs() < 1e-12);
+        assert!(y(&x) > previous);
+        previous = y(&x);
+    }
+    assert_eq!(params(&model), [2.0, -1.0, 0.5]);
+    assert_eq!(grads(&model.parameters()), [0.0; 3]);
+}
+
+#[test]
+fn sequential_matches_manual_composition() {
+    let layers = || {
+        (
+            Layer::new_with_rng(3, 4, Activation::Linear, &mut StdRng::seed_from_u64(5)),
+            Layer::new_with_rng(4, 2, Activation::Tanh, &mut StdRng::seed_from_u64(6)),
+        )
+    };
+    let (first, second) = layers();
+    let x = [
+        Value::constant(0.4),
+        Value::constant(-1.1),
+        Value::constant(0.9),
+    ];
+    let manual = second.forward(&Activation::ReLU.forward(&first.forward(&x)));
+
+    let (a, b) = layers();
+    let expected_params = [data(&a.parameters()), data(&b.parameters())].concat();
+    let mut model = Sequential::new()
+        .push(a)
+        .push(Activation::ReLU)
+        .push(Dropout::new(0.5))
+        .push(b);
+    assert_eq!(data(&model.parameters()), expected_params);
+    assert_eq!(model.parameters().len(), 4 * 4 + 2 * 5);
+
+    // `set_training` reaches the dropout inside: it only acts in training.
+    assert!((0..20).any(|_| data(&model.forward(&x)) != data(&manual)));
+    model.eval();
+    let outputs = model.forward(&x);
+    assert_eq!(data(&outputs), data(&manual));
+
+    Value::sum(&outputs).backward();
+    assert!(model.parameters()[..16].iter().any(|p| p.get_grad() != 0.0));
+}
+
+fn mc_model(p: f64) -> MLP {
+    MLP::builder()
+        .input(2)
+        .hidden(16, Activation::Tanh)
+        .dropout(p)
+        .output(1, Activation::Linear)
+        .seed(8)
+        .build()
+}
+
+#[test]
+fn predict_mc_is_reproducible_from_the_builder_seed() {
+    let x = [0.5, -0.3];
+    let (mean, var) = predict_mc(&mc_model(0.3), &x, 50);
+    assert!(var[0] > 0.0);
+    assert_eq!(predict_mc(&mc_model(0.3), &x, 50), (mean.clone(), var));
+    // Seeding the masks leaves the weights as before.
+    let unseeded = MLP::builder()
+        .input(2)
+        .hidden(16, Activation::Tanh)
+        .output(1, Activation::Linear)
+        .seed(8)
+        .build();
+    assert_eq!(params(&mc_model(0.3)), params(&unseeded));
+}
+
+#[test]
+fn predict_mc_has_no_variance_without_active_dropout() {
+    let x = [0.5, -0.3];
+    let (mean, var) = predict_mc(&mc_model(0.0), &x, 20);
+    assert_eq!(var, [0.0]);
+    assert_eq!(mean, data(&mc_model(0.0).call(&x)));
+
+    let mut model = mc_model(0.3);
+    model.eval();
+    let (mean, var) = predict_mc(&model, &x, 20);
+    assert_eq!(var, [0.0]);
+    assert_eq!(mean, data(&model.call(&x)));
+}
+
+#[test]
+fn stochastic_depth_skips_only_in_training() {
+    let block = Residual::new(Layer::new_with_rng(
+        1,
+        1,
+        Activation::Linear,
+        &mut StdRng::seed_from_u64(2),
+    ));
+    let mut layer = StochasticDepth::new(block, 0.7);
+    let x = [Value::new(1.0)];
+    let skipped = (0..2000)
+        .filter(|_| layer.forward(&x)[0].get_data() == 1.0)
+        .count() as f64
+        / 2000.0;
+    assert!((skipped - 0.3).abs() < 0.04, "skipped {}", skipped);
+
+    layer.eval();
+    let full = layer.forward(&x)[0].get_data();
+    assert_ne!(full, 1.0);
+    assert!((0..50).all(|_| layer.forward(&x)[0].get_data() == full));
+}
+
+fn grads(params: &[Value]) -> Vec<f64> {
+    params.iter().map(|p| p.get_grad()).collect()
+}
+
+fn data(values: &[Value]) -> Vec<f64> {
+    values.iter().map(|v| v.get_data()).collect()
+}
+
+#[test]
+fn rnn_cell_matches_a_hand_unrolled_graph() {
+    let cell = RNNCell::new_seeded(1, 2, 3);
+    let xs = vec![vec![0.5], vec![-1.0], vec![2.0]];
+    let (hiddens, state) = cell.unroll(&xs, cell.initial_state());
+    assert_eq!(hiddens.len(), 3);
+    let total = Value::sum(&state);
+    let expected_state = data(&state);
+    total.backward();
+    let p = cell.parameters();
+    let expected_grads = grads(&p);
+    cell.zero_grad();
+
+    // Each neuron holds weights over [x, h0, h1] and then its bias.
+    let mut h = vec![Value::constant(0.0), Value::constant(0.0)];
+    for x in xs.iter() {
+        h = (0..2)
+            .map(|j| {
+                let w = &p[4 * j..4 * j + 4];
+                (&w[0] * x[0] + &w[1] * &h[0] + &w[2] * &h[1] + &w[3]).tanh()
+            })
+            .collect();
+    }
+    assert_eq!(data(&h), expected_state);
+    Value::sum(&h).backward();
+    assert_eq!(grads(&p), expected_grads);
+}
+
+#[test]
+fn lstm_cell_matches_a_hand_unrolled_graph() {
+    let cell = LSTMCell::new_seeded(1, 1, 8);
+    assert_eq!((cell.hidden_size(), cell.state_size()), (1, 2));
+    let xs = vec![vec![1.5], vec![-0.5]];
+    let (hiddens, state) = cell.unroll(&xs, cell.initial_state());
+    let last = hiddens[1][0].clone();
+    let expected = (last.get_data(), state[1].get_data());
+    last.backward();
+    let p = cell.parameters();
+    let expected_grads = grads(&p);
+    cell.zero_grad();
+
+    // Gates in the order input, forget, candidate, output, each one neuron
+    // over [x, h] followed by its bias.
+    let gate = |k: usize, x: f64, h: &Value| &p[3 * k] * x + &p[3 * k + 1] * h + &p[3 * k + 2];
+    let (mut h, mut c) = (Value::constant(0.0), Value::constant(0.0));
+    for x in xs.iter() {
+        let i = gate(0, x[0], &h).sigmoid();
+        let f = gate(1, x[0], &h).sigmoid();
+        let g = gate(2, x[0], &h).tanh();
+        let o = gate(3, x[0], &h).sigmoid();
+        c = &f * &c + &i * &g;
+        h = &o * c.clone().tanh();
+    }
+    assert_eq!((h.get_data(), c.get_data()), expected);
+    h.backward();
+    assert_eq!(grads(&p), expected_grads);
+}
+
+#[test]
+fn truncated_bptt_stops_grads_at_the_boundary() {
+    let cell = RNNCell::new_seeded(1, 2, 5);
+    let head = Layer::new_with_rng(2, 1, Activation::Linear, &mut StdRng::seed_from_u64(6));
+    let sequence = Dataset::new(
+        vec![vec![0.3], vec![-0.8], vec![1.1], vec![0.4]],
+        vec![vec![0.1], vec![0.5], vec![-0.2], vec![0.7]],
+    );
+    let loss = MSELoss::new(Reduction::Mean);
+    let mut params = cell.parameters();
+    params.extend(head.parameters());
+    // A zero learning rate keeps the weights fixed, so the grads left after
+    // the last chunk can be recomputed by hand.
+    let mut sgd = SGD::new(params.clone(), 0.0);
+    let losses = truncated_bptt(&cell, &head, &loss, &mut sgd, &sequence, 2);
+    assert_eq!(losses.len(), 2);
+    let truncated = grads(&params);
+
+    let (_, state) = cell.unroll(&sequence.inputs[..2], cell.initial_state());
+    let chunk_loss = |start: Vec<Value>| {
+        let (hiddens, _) = cell.unroll(&sequence.inputs[2..], start);
+        let preds: Vec<Vec<Value>> = hiddens.iter().map(|h| head.forward(h)).collect();
+        loss.call(&preds, &sequence.targets[2..])
+    };
+    let detached: Vec<Value> = state
+        .iter()
+        .map(|s| Value::constant(s.get_data()))
+        .collect();
+    sgd.zero_grad();
+    let second = chunk_loss(detached);
+    assert!((second.get_data() - losses[1]).abs() < 1e-12);
+    second.backward();
+    assert_eq!(grads(&params), truncated);
+
+    // Without the cut, the second chunk's loss also reaches the weights
+    // through the first chunk's steps.
+    sgd.zero_grad();
+    chunk_loss(state).backward();
+    assert_ne!(grads(&params), truncated);
+}
+
+#[test]
+fn ensemble_averages_its_members() {
+    let a = MLP::new_seeded(2, vec![3, 2], Activation::Tanh, 1);
+    let b = MLP::new_seeded(2, vec![3, 2], Activation::Tanh, 2);
+    let x = [0.4, -0.7];
+    let ya: Vec<f64> = a.call(&x).iter().map(|v| v.get_data()).collect();
+    let yb: Vec<f64> = b.call(&x).iter().map(|v| v.get_data()).collect();
+    let ensemble = Ensemble::new().push(a).push(b);
+
+    let (mean, var) = ensemble.predict(&x);
+    for j in 0..2 {
+        assert!((mean[j] - (ya[j] + yb[j]) / 2.0).abs() < 1e-12);
+        assert!((var[j] - ((ya[j] - yb[j]) / 2.0).powi(2)).abs() < 1e-12);
+    }
+    let inputs: Vec<Value> = x.iter().map(|&v| Value::constant(v)).collect();
+    let forward: Vec<f64> = ensemble
+        .forward(&inputs)
+        .iter()
+        .map(|v| v.get_data())
+        .collect();
+    for j in 0..2 {
+        assert!((forward[j] - mean[j]).abs() < 1e-12);
+    }
+
+    // Two members either agree on the argmax or split the vote evenly.
+    let argmax = |y: &[f64]| if y[0] >= y[1] { 0 } else { 1 };
+    let expected = if argmax(&ya) == argmax(&yb) { 0.0 } else { 0.5 };
+    assert_eq!(ensemble.disagreement(&x), expected);
+}
+
+#[test]
+fn to_pmml_lists_every_layer_neuron_and_weight() {
+    let model = MLP::builder()
+        .input(2)
+        .hidden(3, Activation::Tanh)
+        .output(1, Activation::Linear)
+        .seed(5)
+        .build();
+    let pmml = model.to_pmml();
+    assert!(pmml.starts_with("<?xml"));
+    assert!(pmml.trim_end().ends_with("</PMML>"));
+    assert_eq!(pmml.matches("<NeuralInput id=").count(), 2);
+    assert!(pmml.contains("<NeuralLayer numberOfNeurons=\"3\" activationFunction=\"tanh\">"));
+    assert!(pmml.contains("<NeuralLayer numberOfNeurons=\"1\" activationFunction=\"identity\">"));
+    assert_eq!(pmml.matches("<Neuron id=").count(), 4);
+    assert_eq!(pmml.matches("<Con from=").count(), 3 * 2 + 3);
+    assert!(pmml.contains("<NeuralOutput outputNeuron=\"2,0\">"));
+
+    // Parameters come neuron by neuron: incoming weights, then the bias.
+    let p = params(&model);
+    assert!(pmml.contains(&format!("<Neuron id=\"1,0\" bias=\"{}\">", p[2])));
+    assert!(pmml.contains(&format!("<Con from=\"0,1\" weight=\"{}\"/>", p[1])));
+    assert!(pmml.contains(&format!("<Neuron id=\"2,0\" bias=\"{}\">", p[12])));
+    assert!(pmml.contains(&format!("<Con from=\"1,2\" weight=\"{}\"/>", p[11])));
+}
+
+#[test]
+fn predict_batch_parallel_matches_call_in_order() {
+    let model = MLP::new_seeded(3, vec![5, 4, 2], Activation::Tanh, 11);
+    let rows: Vec<Vec<f64>> = (0..7)
+        .map(|i| vec![i as f64 * 0.3 - 1.0, (i as f64).sin(), 0.5])
+        .collect();
+    let expected: Vec<Vec<f64>> = rows
+        .iter()
+        .map(|row| model.call(row).iter().map(|v| v.get_data()).collect())
+        .collect();
+    for n_threads in [1, 3, 16] {
+        assert_eq!(model.predict_batch_parallel(&rows, n_threads), expected);
+    }
+}
@@ -0,0 +1,318 @@
+#![cfg(feature = "parquet")]
+
+use micrograd::data::load_parquet;
+use std::io;
+use std::path::PathBuf;
+
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("micrograd-{}-{}", std::process::id(), name))
+}
+
+/// Just enough of the Thrift compact protocol to write Parquet metadata.
+enum T {
+    Bool(bool),
+    I32(i32),
+    I64(i64),
+    Bin(Vec<u8>),
+    List(u8, Vec<T>),
+    Struct(Vec<(i16, T)>),
+}
+
+const I32: u8 = 5;
+const BINARY: u8 = 8;
+const STRUCT: u8 = 12;
+
+fn kind(t: &T) -> u8 {
+    match t {
+        T::Bool(true) => 1,
+        T::Bool(false) => 2,
+        T::I32(_) => I32,
+        T::I64(_) => 6,
+        T::Bin(_) => BINARY,
+        T::List(..) => 9,
+        T::Struct(_) => STRUCT,
+    }
+}
+
+fn varint(out: &mut Vec<u8>, mut v: u64) {
+    while v >= 0x80 {
+        out.push(v as u8 | 0x80);
+        v >>= 7;
+    }
+    out.push(v as u8);
+}
+
+fn zigzag(v: i64) -> u64 {
+    ((v << 1) ^ (v >> 63)) as u64
+}
+
+fn encode(t: &T, out: &mut Vec<u8>) {
+    match t {
+        T::Bool(_) => {}
+        T::I32(v) => varint(out, zigzag(*v as i64)),
+        T::I64(v) => varint(out, zigzag(*v)),
+        T::Bin(bytes) => {
+            varint(out, bytes.len() as u64);
+            out.extend(bytes);
+        }
+        T::List(elem, items) => {
+            assert!(items.len() < 15);
+            out.push((items.len() as u8) << 4 | elem);
+            for item in items {
+                encode(item, out);
+            }
+        }
+        T::Struct(fields) => {
+            let mut last = 0;
+            for (id, value) in fields {
+                match id - last {
+                    delta @ 1..=15 => out.push((delta as u8) << 4 | kind(value)),
+                    _ => {
+                        out.push(kind(value));
+                        varint(out, zigzag(*id as i64));
+                    }
+                }
+                last = *id;
+                encode(value, out);
+            }
+            out.push(0);
+        }
+    }
+}
+
+fn name(s: &str) -> T {
+    T::Bin(s.as_bytes().to_vec())
+}
+
+/// One column of a row group, stored as a single PLAIN data page.
+struct Chunk {
+    kind: i32,
+    values: Vec<u8>,
+    /// Encoded definition levels, for optional columns.
+    levels: Option<Vec<u8>>,
+    n: i32,
+    v2: bool,
+}
+
+fn doubles(values: &[f64]) -> Vec<u8> {
+    values.iter().flat_map(|v| v.to_le_bytes()).collect()
+}
+
+fn required(kind: i32, values: Vec<u8>, n: i32) -> Chunk {
+    Chunk {
+        kind,
+        values,
+        levels: None,
+        n,
+        v2: false,
+    }
+}
+
+const COLUMNS: [(&str, i32, i32); 5] = [
+    ("x", 5, 0),
+    ("count", 1, 1),
+    ("flag", 0, 0),
+    ("label", 2, 0),
+    ("name", 6, 0),
+];
+
+/// Writes a two-row-group file with the columns in `COLUMNS`.
+fn write_file(path: &PathBuf, codec: i32) {
+    let names = |s: &[&str]| -> Vec<u8> {
+        s.iter()
+            .flat_map(|s| {
+                let mut b = (s.len() as u32).to_le_bytes().to_vec();
+                b.extend(s.as_bytes());
+                b
+            })
+            .collect()
+    };
+    let groups = vec![
+        vec![
+            required(5, doubles(&[1.5, -2.0, 3.25]), 3),
+            Chunk {
+                kind: 1,
+                values: [1i32, 3].iter().flat_map(|v| v.to_le_bytes()).collect(),
+                // One bit-packed group: levels 1, 0, 1.
+                levels: Some(vec![3, 0b101]),
+                n: 3,
+                v2: false,
+            },
+            required(0, vec![0b101], 3),
+            required(
+                2,
+                [0i64, 1, 0].iter().flat_map(|v| v.to_le_bytes()).collect(),
+                3,
+            ),
+            required(6, names(&["a", "b", "c"]), 3),
+        ],
+        vec![
+            required(5, doubles(&[0.5, 4.0]), 2),
+            Chunk {
+                kind: 1,
+                values: [7i32, 8].iter().flat_map(|v| v.to_le_bytes()).collect(),
+                // An RLE run of two 1s.
+                levels: Some(vec![4, 1]),
+                n: 2,
+                v2: false,
+            },
+            required(0, vec![0b10], 2),
+            Chunk {
+                v2: true,
+                ..required(
+                    2,
+                    [1i64, 1].iter().flat_map(|v| v.to_le_bytes()).collect(),
+                    2,
+                )
+            },
+            required(6, names(&["d", "e"]), 2),
+        ],
+    ];
+
+    let mut file = b"PAR1".to_vec();
+    let mut row_groups = Vec::new();
+    for group in &groups {
+        let mut chunks = Vec::new();
+        for chunk in group {
+            let offset = file.len() as i64;
+            let mut body = Vec::new();
+            if let (Some(levels), false) = (&chunk.levels, chunk.v2) {
+                body.extend((levels.len() as u32).to_le_bytes());
+                body.extend(levels);
+            }
+            body.extend(&chunk.values);
+            let size = T::I32(body.len() as i32);
+            let header = if chunk.v2 {
+                T::Struct(vec![
+                    (1, T::I32(3)),
+                    (2, T::I32(body.len() as i32)),
+                    (3, size),
+                    (
+                        8,
+                        T::Struct(vec![
+                            (1, T::I32(chunk.n)),
+                            (2, T::I32(0)),
+                            (3, T::I32(chunk.n)),
+                            (4, T::I32(0)),
+                            (5, T::I32(0)),
+                            (6, T::I32(0)),
+                            (7, T::Bool(false)),
+                        ]),
+                    ),
+                ])
+            } else {
+                T::Struct(vec![
+                    (1, T::I32(0)),
+                    (2, T::I32(body.len() as i32)),
+                    (3, size),
+                    (
+                        5,
+                        T::Struct(vec![
+                            (1, T::I32(chunk.n)),
+                            (2, T::I32(0)),
+                            (3, T::I32(3)),
+                            (4, T::I32(3)),
+                        ]),
+                    ),
+                ])
+            };
+            encode(&header, &mut file);
+            file.extend(&body);
+            let total = file.len() as i64 - offset;
+            let column = COLUMNS[chunks.len()].0;
+            chunks.push(T::Struct(vec![
+                (2, T::I64(offset)),
+                (
+                    3,
+                    T::Struct(vec![
+                        (1, T::I32(chunk.kind)),
+                        (2, T::List(I32, vec![T::I32(0), T::I32(3)])),
+                        (3, T::List(BINARY, vec![name(column)])),
+                        (4, T::I32(codec)),
+                        (5, T::I64(chunk.n as i64)),
+                        (6, T::I64(total)),
+                        (7, T::I64(total)),
+                        (9, T::I64(offset)),
+                    ]),
+                ),
+            ]));
+        }
+        row_groups.push(T::Struct(vec![
+            (1, T::List(STRUCT, chunks)),
+            (2, T::I64(0)),
+            (3, T::I64(group[0].n as i64)),
+        ]));
+    }
+    let mut schema = vec![T::Struct(vec![
+        (4, name("schema")),
+        (5, T::I32(COLUMNS.len() as i32)),
+    ])];
+    for (column, kind, repetition) in COLUMNS {
+        schema.push(T::Struct(vec![
+            (1, T::I32(kind)),
+            (3, T::I32(repetition)),
+            (4, name(column)),
+        ]));
+    }
+    let meta = T::Struct(vec![
+        (1, T::I32(1)),
+        (2, T::List(STRUCT, schema)),
+        (3, T::I64(5)),
+        (4, T::List(STRUCT, row_groups)),
+        (6, name("micrograd tests")),
+    ]);
+    let start = file.len();
+    encode(&meta, &mut file);
+    let len = (file.len() - start) as u32;
+    file.extend(len.to_le_bytes());
+    file.extend(b"PAR1");
+    std::fs::write(path, file).unwrap();
+}
+
+#[test]
+fn load_parquet_selects_and_orders_columns_across_row_groups() {
+    let path = temp_path("columns.parquet");
+    write_file(&path, 0);
+    let data = load_parquet(&path, &["count", "x", "flag"], &["label"]).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    let expected = [
+        [1.0, 1.5, 1.0],
+        [f64::NAN, -2.0, 0.0],
+        [3.0, 3.25, 1.0],
+        [7.0, 0.5, 0.0],
+        [8.0, 4.0, 1.0],
+    ];
+    assert_eq!(data.len(), expected.len());
+    for (row, want) in data.inputs.iter().zip(&expected) {
+        assert_eq!(row.len(), 3);
+        for (got, want) in row.iter().zip(want) {
+            // The null in the optional column comes back as NaN.
+            assert!(got == want || (got.is_nan() && want.is_nan()), "{:?}", row);
+        }
+    }
+    assert_eq!(
+        data.targets,
+        vec![vec![0.0], vec![1.0], vec![0.0], vec![1.0], vec![1.0]]
+    );
+}
+
+#[test]
+fn load_parquet_rejects_what_it_cannot_read() {
+    let path = temp_path("errors.parquet");
+    write_file(&path, 0);
+    let message = |result: io::Result<_>| {
+        let err = result.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        err.to_string()
+    };
+    assert!(message(load_parquet(&path, &["missing"], &[])).contains("no column named"));
+    assert!(message(load_parquet(&path, &["name"], &[])).contains("not numeric"));
+
+    write_file(&path, 1);
+    assert!(message(load_parquet(&path, &["x"], &[])).contains("compressed"));
+
+    std::fs::write(&path, "x,y\n1,2\n").unwrap();
+    assert!(message(load_parquet(&path, &["x"], &[])).contains("not a Parquet file"));
+    std::fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,55 @@
+use micrograd::gradcheck::gradcheck;
+use micrograd::pinn::{initial_condition_loss, ode_residual_loss, value_and_derivative};
+use micrograd::{Activation, Module, MLP};
+
+#[test]
+fn derivative_matches_finite_differences_for_each_activation() {
+    for activation in [Activation::Tanh, Activation::Sigmoid, Activation::ReLU] {
+        let model = MLP::new_seeded(1, vec![6, 4, 1], activation, 12);
+        for x in [-0.9, -0.2, 0.35, 1.1] {
+            let (y, dy) = value_and_derivative(&model, x);
+            assert_eq!(y.get_data(), model.call(&[x])[0].get_data());
+            let h = 1e-6;
+            let numeric = (model.call(&[x + h])[0].get_data() - model.call(&[x - h])[0].get_data())
+                / (2.0 * h);
+            assert!(
+                (dy.get_data() - numeric).abs() < 1e-6,
+                "{:?} at {}: {} vs {}",
+                activation,
+                x,
+                dy.get_data(),
+                numeric
+            );
+        }
+    }
+}
+
+#[test]
+fn residual_vanishes_on_an_analytic_solution() {
+    // y = tanh(x) solves dy/dx = 1 - y^2 with y(0) = 0.
+    let model = MLP::new_seeded(1, vec![1, 1], Activation::Tanh, 0);
+    for (p, value) in model.parameters().iter().zip([1.0, 0.0, 1.0, 0.0]) {
+        p.update_data(value);
+    }
+    let xs: Vec<f64> = (0..9).map(|i| i as f64 / 4.0 - 1.0).collect();
+    let loss = ode_residual_loss(&model, &xs, |_, y, dy| dy - (1.0 - y * y));
+    assert!(loss.get_data() < 1e-28, "residual loss {}", loss.get_data());
+    assert!(initial_condition_loss(&model, 0.0, 0.0).get_data() < 1e-28);
+
+    // Off the solution the loss is positive.
+    model.parameters()[0].update_data(1.5);
+    let loss = ode_residual_loss(&model, &xs, |_, y, dy| dy - (1.0 - y * y));
+    assert!(loss.get_data() > 1e-3);
+}
+
+#[test]
+fn residual_loss_is_differentiable_in_the_weights() {
+    let model = MLP::new_seeded(1, vec![3, 1], Activation::Tanh, 5);
+    let xs = [-0.5, 0.1, 0.7];
+    let report = gradcheck(
+        &model.parameters(),
+        || ode_residual_loss(&model, &xs, |_, y, dy| dy + y),
+        1e-6,
+    );
+    assert!(report.passed(1e-6), "{:?}", report);
+}
@@ -0,0 +1,84 @@
+use micrograd::optim::{Optimizer, SGD};
+use micrograd::regularize::{l1_penalty, l2_penalty, LotteryTicket, EWC};
+use micrograd::Value;
+
+fn assert_close(actual: f64, expected: f64) {
+    assert!(
+        (actual - expected).abs() < 1e-9,
+        "expected {}, got {}",
+        expected,
+        actual
+    );
+}
+
+#[test]
+fn ewc_penalty_weights_drift_by_fisher_importance() {
+    let params = vec![Value::new(0.4), Value::new(-1.0)];
+    let (a, b) = (&params[0], &params[1]);
+    // Per-sample gradients (2, 1) and (-1, 3): importances (2.5, 5).
+    let losses = vec![a * 2.0 + b, a * -1.0 + b * 3.0];
+    let ewc = EWC::new(&params, losses, 0.8);
+    assert!(params.iter().all(|p| p.get_grad() == 0.0));
+
+    assert_close(ewc.penalty(&params).get_data(), 0.0);
+
+    a.update_data(0.4 + 0.3);
+    b.update_data(-1.0 - 0.2);
+    let penalty = ewc.penalty(&params);
+    assert_close(penalty.get_data(), 0.8 / 2.0 * (2.5 * 0.09 + 5.0 * 0.04));
+    penalty.backward();
+    // lambda * F_i * (p_i - p*_i).
+    assert_close(a.get_grad(), 0.8 * 2.5 * 0.3);
+    assert_close(b.get_grad(), 0.8 * 5.0 * -0.2);
+}
+
+#[test]
+fn lottery_ticket_prunes_by_magnitude_and_rewinds() {
+    let initial = [0.5, -0.1, 2.0, 0.05, -1.0, 0.3];
+    let params: Vec<Value> = initial.iter().map(|&x| Value::new(x)).collect();
+    let mut ticket = LotteryTicket::new(&params);
+    let mut sgd = SGD::new(params.clone(), 0.1);
+    // Training moves every weight; pruning then ranks the trained values.
+    let train = |sgd: &mut SGD, ticket: &LotteryTicket| {
+        for _ in 0..3 {
+            sgd.zero_grad();
+            let terms: Vec<Value> = params.iter().map(|p| p * 1.0).collect();
+            Value::sum(&terms).backward();
+            sgd.step();
+            ticket.apply_mask(&params);
+        }
+    };
+    train(&mut sgd, &ticket);
+    // Trained: 0.2, -0.4, 1.7, -0.25, -1.3, 0.0.
+    ticket.prune(&params, 0.5);
+    assert!((ticket.sparsity() - 0.5).abs() < 1e-12);
+    let pruned: Vec<bool> = params.iter().map(|p| p.get_data() == 0.0).collect();
+    assert_eq!(pruned, [true, false, false, true, false, true]);
+
+    ticket.rewind(&params);
+    let rewound: Vec<f64> = params.iter().map(|p| p.get_data()).collect();
+    assert_eq!(rewound, [0.0, -0.1, 2.0, 0.0, -1.0, 0.0]);
+
+    // Masked weights stay at zero while the others keep training.
+    train(&mut sgd, &ticket);
+    for (p, &was_pruned) in params.iter().zip(&pruned) {
+        assert_eq!(p.get_data() == 0.0, was_pruned);
+    }
+}
+
+#[test]
+fn l1_and_l2_penalties_and_their_gradients() {
+    let params = vec![Value::new(0.5), Value::new(-2.0)];
+    let l1 = l1_penalty(&params, 0.1);
+    assert_close(l1.get_data(), 0.25);
+    l1.backward();
+    assert_close(params[0].get_grad(), 0.1);
+    assert_close(params[1].get_grad(), -0.1);
+
+    let params = vec![Value::new(0.5), Value::new(-2.0)];
+    let l2 = l2_penalty(&params, 0.1);
+    assert_close(l2.get_data(), 0.425);
+    l2.backward();
+    assert_close(params[0].get_grad(), 0.1);
+    assert_close(params[1].get_grad(), -0.4);
+}
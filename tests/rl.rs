@@ -0,0 +1,162 @@
+use micrograd::optim::{Adam, SGD};
+use micrograd::rl::{
+    discounted_returns, reinforce_step, run_episode, CartPole, Environment, GridWorld, Policy,
+    ReplayBuffer, Step, Transition, DQN,
+};
+use micrograd::{Activation, Module, MLP};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+/// A one-step episode paying +1 for action 1 and -1 for action 0.
+struct Bandit;
+
+impl Environment for Bandit {
+    fn n_actions(&self) -> usize {
+        2
+    }
+
+    fn reset(&mut self) -> Vec<f64> {
+        vec![1.0]
+    }
+
+    fn step(&mut self, action: usize) -> Step {
+        Step {
+            state: vec![1.0],
+            reward: if action == 1 { 1.0 } else { -1.0 },
+            done: true,
+        }
+    }
+}
+
+#[test]
+fn returns_are_discounted_from_the_end() {
+    let returns = discounted_returns(&[1.0, 0.0, 2.0], 0.5);
+    assert_eq!(returns, [1.5, 1.0, 2.0]);
+    assert_eq!(discounted_returns(&[], 0.9), Vec::<f64>::new());
+}
+
+#[test]
+fn policy_log_probs_match_probabilities() {
+    let policy = Policy::new(MLP::new_seeded(2, vec![4, 3], Activation::Tanh, 1));
+    let state = [0.3, -0.8];
+    let probs = policy.probabilities(&state);
+    assert!((probs.iter().sum::<f64>() - 1.0).abs() < 1e-12);
+    for (a, &p) in probs.iter().enumerate() {
+        assert!((policy.log_prob(&state, a).get_data() - p.ln()).abs() < 1e-12);
+    }
+}
+
+#[test]
+fn reinforce_learns_the_better_arm() {
+    let policy = Policy::new(MLP::new_seeded(1, vec![2], Activation::Tanh, 0));
+    let mut sgd = SGD::new(policy.net().parameters(), 0.1);
+    let mut rng = StdRng::seed_from_u64(3);
+    assert!(policy.probabilities(&[1.0])[1] < 0.9);
+    for _ in 0..200 {
+        let episode = run_episode(&mut Bandit, &policy, &mut rng);
+        reinforce_step(episode, 0.99, &mut sgd);
+    }
+    assert!(policy.probabilities(&[1.0])[1] > 0.95);
+}
+
+#[test]
+fn cart_pole_episodes_end() {
+    let mut env = CartPole::new(5).max_steps(50);
+    let state = env.reset();
+    assert!(state.iter().all(|s| s.abs() <= 0.05));
+
+    // Always pushing right tips the pole well before the step limit.
+    let mut steps = 0;
+    loop {
+        steps += 1;
+        let step = env.step(1);
+        assert_eq!(step.reward, 1.0);
+        if step.done {
+            break;
+        }
+    }
+    assert!(steps < 50, "still balanced after {} steps", steps);
+
+    let policy = Policy::new(MLP::new_seeded(4, vec![8, 2], Activation::Tanh, 2));
+    let mut adam = Adam::new(policy.net().parameters(), 0.01);
+    let episode = run_episode(&mut env, &policy, &mut StdRng::seed_from_u64(0));
+    assert_eq!(episode.log_probs.len(), episode.len());
+    assert!(episode.len() <= 50);
+    assert!(reinforce_step(episode, 0.99, &mut adam).is_finite());
+}
+
+#[test]
+fn grid_world_walls_and_goal() {
+    let mut env = GridWorld::new(2);
+    assert_eq!(env.reset(), [1.0, 0.0, 0.0, 0.0]);
+    let bump = env.step(0);
+    assert_eq!(
+        (bump.state, bump.reward, bump.done),
+        (vec![1.0, 0.0, 0.0, 0.0], -0.01, false)
+    );
+    env.step(1);
+    let goal = env.step(2);
+    assert_eq!(
+        (goal.state, goal.reward, goal.done),
+        (vec![0.0, 0.0, 0.0, 1.0], 1.0, true)
+    );
+
+    let mut env = GridWorld::new(3).max_steps(2);
+    env.reset();
+    env.step(3);
+    assert!(env.step(3).done);
+}
+
+#[test]
+fn replay_buffer_overwrites_the_oldest() {
+    let transition = |reward| Transition {
+        state: vec![],
+        action: 0,
+        reward,
+        next_state: vec![],
+        done: false,
+    };
+    let mut buffer = ReplayBuffer::new(2);
+    for reward in [1.0, 2.0, 3.0] {
+        buffer.push(transition(reward));
+    }
+    assert_eq!(buffer.len(), 2);
+    let mut rewards: Vec<f64> = buffer
+        .sample(50, &mut StdRng::seed_from_u64(0))
+        .iter()
+        .map(|t| t.reward)
+        .collect();
+    rewards.sort_by(f64::total_cmp);
+    rewards.dedup();
+    assert_eq!(rewards, [2.0, 3.0]);
+}
+
+#[test]
+fn dqn_finds_the_shortest_path() {
+    let q = MLP::new_seeded(9, vec![8, 4], Activation::Tanh, 0);
+    let target = MLP::new_seeded(9, vec![8, 4], Activation::Tanh, 1);
+    let adam = Adam::new(q.parameters(), 0.01);
+    let mut dqn = DQN::new(q, target, adam)
+        .gamma(0.9)
+        .batch_size(8)
+        .target_update(20)
+        .epsilon(0.3);
+    let params =
+        |net: &MLP| -> Vec<f64> { net.parameters().iter().map(|p| p.get_data()).collect() };
+    assert_eq!(params(dqn.target()), params(dqn.q()));
+
+    let mut env = GridWorld::new(3);
+    let mut rng = StdRng::seed_from_u64(2);
+    for _ in 0..25 {
+        dqn.run_episode(&mut env, &mut rng);
+    }
+    assert!(dqn.buffer().len() >= 25 * 4);
+
+    // The greedy policy walks straight to the far corner: 4 moves.
+    let mut state = env.reset();
+    for moves in 1..=4 {
+        let step = env.step(dqn.greedy_action(&state));
+        assert_eq!(step.done, moves == 4, "after {} moves", moves);
+        state = step.state;
+    }
+}
@@ -0,0 +1,96 @@
+use micrograd::data::Dataset;
+use micrograd::sampler::{
+    inverse_class_frequency, BatchSampler, CurriculumSampler, RandomSampler, Sampler,
+    SequentialSampler, WeightedRandomSampler,
+};
+
+fn dataset(n: usize) -> Dataset {
+    Dataset::new(vec![vec![0.0]; n], vec![vec![0.0]; n])
+}
+
+#[test]
+fn sequential_and_random_orders() {
+    let data = dataset(6);
+    assert_eq!(SequentialSampler.indices(&data), [0, 1, 2, 3, 4, 5]);
+
+    let mut random = RandomSampler::new(7);
+    let (first, second) = (random.indices(&data), random.indices(&data));
+    for epoch in [&first, &second] {
+        let mut sorted = epoch.clone();
+        sorted.sort_unstable();
+        assert_eq!(sorted, [0, 1, 2, 3, 4, 5]);
+    }
+    assert_ne!(first, second);
+    assert_eq!(RandomSampler::new(7).indices(&data), first);
+}
+
+#[test]
+fn batch_sampler_keeps_or_drops_the_remainder() {
+    let data = dataset(7);
+    let batches = BatchSampler::new(SequentialSampler, 3).batches(&data);
+    assert_eq!(batches, [vec![0, 1, 2], vec![3, 4, 5], vec![6]]);
+    let batches = BatchSampler::new(SequentialSampler, 3)
+        .drop_last(true)
+        .batches(&data);
+    assert_eq!(batches, [vec![0, 1, 2], vec![3, 4, 5]]);
+}
+
+#[test]
+#[should_panic(expected = "Batch size must be positive.")]
+fn batch_sampler_rejects_empty_batches() {
+    BatchSampler::new(SequentialSampler, 0);
+}
+
+#[test]
+fn weighted_sampler_follows_the_weights() {
+    let data = dataset(3);
+    let mut sampler = WeightedRandomSampler::new(vec![0.0, 1.0, 3.0], 4000, 1);
+    let draws = sampler.indices(&data);
+    assert_eq!(draws.len(), 4000);
+    let share = |i| draws.iter().filter(|&&d| d == i).count() as f64 / 4000.0;
+    assert_eq!(share(0), 0.0);
+    assert!((share(1) - 0.25).abs() < 0.03, "share of 1: {}", share(1));
+    assert_ne!(sampler.indices(&data), draws);
+}
+
+#[test]
+fn inverse_class_frequency_balances_the_classes() {
+    // Nine samples of class 0 and one of class 1.
+    let targets: Vec<Vec<f64>> = (0..10)
+        .map(|i| vec![if i == 9 { 1.0 } else { 0.0 }])
+        .collect();
+    let data = Dataset::new(vec![vec![0.0]; 10], targets);
+    let weights = inverse_class_frequency(&data);
+    assert_eq!(weights[0], 1.0 / 9.0);
+    assert_eq!(weights[9], 1.0);
+
+    let draws = WeightedRandomSampler::new(weights, 2000, 2).indices(&data);
+    let minority = draws.iter().filter(|&&d| d == 9).count() as f64 / 2000.0;
+    assert!((minority - 0.5).abs() < 0.05, "minority share {}", minority);
+}
+
+#[test]
+#[should_panic(expected = "Need one weight per sample.")]
+fn weighted_sampler_checks_the_weight_count() {
+    WeightedRandomSampler::new(vec![1.0, 1.0], 4, 0).indices(&dataset(3));
+}
+
+#[test]
+fn curriculum_grows_from_the_easiest_samples() {
+    let xs = [3.0, -1.0, 0.5, -4.0, 2.0, 0.0, -2.5, 1.5];
+    let data = Dataset::new(xs.iter().map(|&x| vec![x]).collect(), vec![vec![0.0]; 8]);
+    let mut sampler = CurriculumSampler::new(|x: &[f64], _: &[f64]| x[0].abs(), 3);
+    let epochs: Vec<Vec<usize>> = (0..5).map(|_| sampler.indices(&data)).collect();
+    assert_eq!(epochs[0], [5, 2]);
+    assert_eq!(epochs[1], [5, 2, 1, 7]);
+    assert_eq!(epochs[2], [5, 2, 1, 7, 4, 6]);
+    assert_eq!(epochs[3], [5, 2, 1, 7, 4, 6, 0, 3]);
+    assert_eq!(epochs[4], epochs[3]);
+
+    let mut shuffled = CurriculumSampler::new(|x: &[f64], _: &[f64]| x[0].abs(), 2)
+        .start_fraction(0.5)
+        .shuffle(4);
+    let mut first = shuffled.indices(&data);
+    first.sort_unstable();
+    assert_eq!(first, [1, 2, 5, 7]);
+}
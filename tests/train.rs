@@ -0,0 +1,756 @@
+use micrograd::augment::{Mixup, Transform};
+use micrograd::data::Dataset;
+use micrograd::diagnostics::suggest_lr;
+use micrograd::loss::{soft_cross_entropy, CrossEntropyLoss, MSELoss, MultiTaskLoss, Reduction};
+use micrograd::lr_scheduler::{LrScheduler, Schedule};
+use micrograd::nn::Constraint;
+use micrograd::optim::{
+    clip_grad_norm, clip_grad_value, grad_norm, Adam, Constrained, Optimizer, SGD,
+};
+use micrograd::sampler::Sampler;
+use micrograd::train::{
+    predict_sequence, BatchEnd, Callback, Control, EarlyStopping, EpochEnd, PrintLoss, RunManifest,
+    Trainer,
+};
+use micrograd::{Activation, Module, Value, MLP};
+use std::cell::RefCell;
+use std::fs;
+use std::rc::Rc;
+
+fn assert_close(actual: f64, expected: f64) {
+    assert!(
+        (actual - expected).abs() < 1e-9,
+        "expected {}, got {}",
+        expected,
+        actual
+    );
+}
+
+/// `y = w * x`, logging every input it sees.
+struct Recorder {
+    w: Value,
+    seen: RefCell<Vec<f64>>,
+}
+
+impl Recorder {
+    fn new() -> Self {
+        Recorder {
+            w: Value::new(0.0),
+            seen: RefCell::new(vec![]),
+        }
+    }
+}
+
+impl Module for Recorder {
+    fn forward(&self, inputs: &[Value]) -> Vec<Value> {
+        self.seen.borrow_mut().push(inputs[0].get_data());
+        vec![&self.w * &inputs[0]]
+    }
+
+    fn parameters(&self) -> Vec<Value> {
+        vec![self.w.clone()]
+    }
+}
+
+/// Samples `0, 1, ..., n - 1` with targets `2x`.
+fn counting_dataset(n: usize) -> Dataset {
+    let xs: Vec<f64> = (0..n).map(|i| i as f64).collect();
+    Dataset::new(
+        xs.iter().map(|&x| vec![x]).collect(),
+        xs.iter().map(|&x| vec![2.0 * x]).collect(),
+    )
+}
+
+/// Logs every callback and stops at the given batch or epoch.
+#[derive(Default)]
+struct Log {
+    batches: Rc<RefCell<Vec<BatchEnd>>>,
+    epochs: Rc<RefCell<Vec<EpochEnd>>>,
+    stop_at_batch: Option<(usize, usize)>,
+    stop_at_epoch: Option<usize>,
+}
+
+impl Callback for Log {
+    fn on_batch_end(&mut self, stats: &BatchEnd) -> Control {
+        self.batches.borrow_mut().push(*stats);
+        if self.stop_at_batch == Some((stats.epoch, stats.batch)) {
+            Control::Stop
+        } else {
+            Control::Continue
+        }
+    }
+
+    fn on_epoch_end(&mut self, stats: &EpochEnd) -> Control {
+        self.epochs.borrow_mut().push(*stats);
+        if self.stop_at_epoch == Some(stats.epoch) {
+            Control::Stop
+        } else {
+            Control::Continue
+        }
+    }
+}
+
+#[test]
+fn batches_cover_the_dataset_in_order() {
+    let model = Recorder::new();
+    let data = counting_dataset(10);
+    let batches = Rc::new(RefCell::new(vec![]));
+    let log = Log {
+        batches: batches.clone(),
+        ..Log::default()
+    };
+    let sgd = SGD::new(model.parameters(), 0.0);
+    let loss = MSELoss::new(Reduction::Mean);
+    let history = Trainer::new(&model, loss, sgd, &data)
+        .batch_size(4)
+        .epochs(2)
+        .callback(log)
+        .fit();
+
+    assert_eq!(history.len(), 2);
+    let seen: Vec<f64> = (0..10).chain(0..10).map(|i| i as f64).collect();
+    assert_eq!(*model.seen.borrow(), seen);
+    let ids: Vec<(usize, usize)> = batches
+        .borrow()
+        .iter()
+        .map(|b| (b.epoch, b.batch))
+        .collect();
+    assert_eq!(ids, [(0, 0), (0, 1), (0, 2), (1, 0), (1, 1), (1, 2)]);
+    // The epoch loss is the mean of its batch losses.
+    let first: f64 = batches.borrow()[..3].iter().map(|b| b.loss).sum::<f64>() / 3.0;
+    assert_close(history[0], first);
+}
+
+#[test]
+fn shuffling_permutes_each_epoch_reproducibly() {
+    let orders = |seed| {
+        let model = Recorder::new();
+        let data = counting_dataset(20);
+        let sgd = SGD::new(model.parameters(), 0.0);
+        Trainer::new(&model, MSELoss::new(Reduction::Mean), sgd, &data)
+            .batch_size(3)
+            .epochs(2)
+            .shuffle(seed)
+            .fit();
+        let seen = model.seen.borrow().clone();
+        seen
+    };
+    let seen = orders(5);
+    let (first, second) = seen.split_at(20);
+    for epoch in [first, second] {
+        let mut sorted = epoch.to_vec();
+        sorted.sort_by(f64::total_cmp);
+        assert_eq!(sorted, (0..20).map(|i| i as f64).collect::<Vec<_>>());
+    }
+    assert_ne!(first, second);
+    assert_ne!(first, (0..20).map(|i| i as f64).collect::<Vec<_>>());
+    assert_eq!(orders(5), seen);
+    assert_ne!(orders(6), seen);
+}
+
+/// Visits the samples in reverse.
+struct Reverse;
+
+impl Sampler for Reverse {
+    fn indices(&mut self, dataset: &Dataset) -> Vec<usize> {
+        (0..dataset.len()).rev().collect()
+    }
+}
+
+#[test]
+fn trainer_follows_a_custom_sampler() {
+    let model = Recorder::new();
+    let data = counting_dataset(5);
+    let sgd = SGD::new(model.parameters(), 0.0);
+    let history = Trainer::new(&model, MSELoss::new(Reduction::Mean), sgd, &data)
+        .batch_size(2)
+        .sampler(Reverse)
+        .fit();
+
+    assert_eq!(history.len(), 1);
+    assert_eq!(*model.seen.borrow(), [4.0, 3.0, 2.0, 1.0, 0.0]);
+}
+
+#[test]
+fn trainer_curriculum_starts_with_easy_samples() {
+    let model = Recorder::new();
+    let data = counting_dataset(8);
+    let sgd = SGD::new(model.parameters(), 0.0);
+    // Larger inputs are harder.
+    Trainer::new(&model, MSELoss::new(Reduction::Mean), sgd, &data)
+        .epochs(2)
+        .curriculum(|x, _| x[0], 1)
+        .fit();
+
+    let seen: Vec<f64> = (0..2).chain(0..8).map(|i| i as f64).collect();
+    assert_eq!(*model.seen.borrow(), seen);
+}
+
+/// Shifts every input by 100.
+struct Shift;
+
+impl Transform for Shift {
+    fn apply(&mut self, inputs: &mut [Vec<f64>], _targets: &mut [Vec<f64>]) {
+        for x in inputs.iter_mut().flatten() {
+            *x += 100.0;
+        }
+    }
+}
+
+#[test]
+fn transforms_augment_training_batches_only() {
+    let model = Recorder::new();
+    let data = counting_dataset(3);
+    let sgd = SGD::new(model.parameters(), 0.0);
+    Trainer::new(&model, MSELoss::new(Reduction::Mean), sgd, &data)
+        .transform(Shift)
+        .fit();
+
+    assert_eq!(*model.seen.borrow(), [100.0, 101.0, 102.0]);
+    assert_eq!(data, counting_dataset(3));
+}
+
+#[test]
+fn batch_stats_carry_lr_and_grad_norm() {
+    let model = Recorder::new();
+    let data = Dataset::new(vec![vec![1.0]], vec![vec![2.0]]);
+    let batches = Rc::new(RefCell::new(vec![]));
+    let log = Log {
+        batches: batches.clone(),
+        ..Log::default()
+    };
+    let sgd = SGD::new(model.parameters(), 0.1);
+    Trainer::new(&model, MSELoss::new(Reduction::Mean), sgd, &data)
+        .callback(log)
+        .fit();
+
+    // d/dw (w - 2)^2 at w = 0.
+    let stats = batches.borrow()[0];
+    assert_close(stats.grad_norm, 4.0);
+    assert_close(stats.lr, 0.1);
+}
+
+#[test]
+fn batch_callback_stops_mid_epoch() {
+    let model = Recorder::new();
+    let data = counting_dataset(10);
+    let (batches, epochs) = (Rc::new(RefCell::new(vec![])), Rc::new(RefCell::new(vec![])));
+    let log = Log {
+        batches: batches.clone(),
+        epochs: epochs.clone(),
+        stop_at_batch: Some((1, 0)),
+        ..Log::default()
+    };
+    let sgd = SGD::new(model.parameters(), 0.0);
+    let history = Trainer::new(&model, MSELoss::new(Reduction::Mean), sgd, &data)
+        .batch_size(5)
+        .epochs(10)
+        .callback(log)
+        .fit();
+
+    // The cut-short epoch still ends, so it is reported and recorded.
+    assert_eq!(batches.borrow().len(), 3);
+    assert_eq!(epochs.borrow().len(), 2);
+    assert_eq!(history.len(), 2);
+    assert_eq!(model.seen.borrow().len(), 15);
+}
+
+#[test]
+fn epoch_callback_stops_after_its_epoch() {
+    let model = Recorder::new();
+    let data = counting_dataset(10);
+    let batches = Rc::new(RefCell::new(vec![]));
+    let log = Log {
+        batches: batches.clone(),
+        stop_at_epoch: Some(2),
+        ..Log::default()
+    };
+    let sgd = SGD::new(model.parameters(), 0.0);
+    let history = Trainer::new(&model, MSELoss::new(Reduction::Mean), sgd, &data)
+        .batch_size(5)
+        .epochs(10)
+        .callback(log)
+        .fit();
+
+    assert_eq!(history.len(), 3);
+    assert_eq!(batches.borrow().len(), 6);
+}
+
+#[test]
+fn trainer_advances_the_lr_schedule() {
+    let model = Recorder::new();
+    let data = counting_dataset(4);
+    let optimizer = LrScheduler::new(
+        SGD::new(model.parameters(), 0.1),
+        Schedule::StepLR {
+            step_size: 1,
+            gamma: 0.5,
+        },
+    );
+    let mut trainer = Trainer::new(&model, MSELoss::new(Reduction::Mean), optimizer, &data)
+        .batch_size(2)
+        .epochs(3);
+    trainer.fit();
+
+    assert_eq!(trainer.optimizer().epoch(), 3);
+    assert_close(trainer.optimizer().lr(), 0.1 * 0.125);
+}
+
+#[test]
+fn schedule_values() {
+    let step = Schedule::StepLR {
+        step_size: 2,
+        gamma: 0.5,
+    };
+    let lrs: Vec<f64> = (0..5).map(|e| step.lr(1.0, e)).collect();
+    assert_eq!(lrs, [1.0, 1.0, 0.5, 0.5, 0.25]);
+
+    let exponential = Schedule::ExponentialLR { gamma: 0.9 };
+    assert_close(exponential.lr(2.0, 0), 2.0);
+    assert_close(exponential.lr(2.0, 3), 2.0 * 0.729);
+
+    let cosine = Schedule::CosineAnnealing {
+        t_max: 10,
+        eta_min: 0.1,
+    };
+    assert_close(cosine.lr(1.0, 0), 1.0);
+    assert_close(cosine.lr(1.0, 5), 0.55);
+    assert_close(cosine.lr(1.0, 10), 0.1);
+    assert_close(cosine.lr(1.0, 25), 0.1);
+
+    let restarts = Schedule::CosineWarmRestarts {
+        t_0: 4,
+        eta_min: 0.0,
+    };
+    let lrs: Vec<f64> = (0..9).map(|e| restarts.lr(1.0, e)).collect();
+    for (lr, expected) in lrs
+        .iter()
+        .zip([1.0, 0.8535533905932737, 0.5, 0.1464466094067262, 1.0])
+    {
+        assert_close(*lr, expected);
+    }
+    assert_eq!(lrs[1], lrs[5]);
+    assert_close(lrs[8], 1.0);
+}
+
+#[test]
+fn early_stopping_waits_for_patience() {
+    let mut stopper = EarlyStopping::new(2, 0.01);
+    let losses = [1.0, 0.5, 0.495, 0.3, 0.3, 0.295];
+    let controls: Vec<Control> = losses
+        .iter()
+        .enumerate()
+        .map(|(epoch, &loss)| stopper.on_epoch_end(&EpochEnd { epoch, loss }))
+        .collect();
+    use Control::{Continue, Stop};
+    assert_eq!(
+        controls,
+        [Continue, Continue, Continue, Continue, Continue, Stop]
+    );
+}
+
+#[test]
+fn print_loss_never_stops() {
+    let mut printer = PrintLoss::new(2);
+    for epoch in 0..4 {
+        assert_eq!(
+            printer.on_epoch_end(&EpochEnd { epoch, loss: 1.0 }),
+            Control::Continue
+        );
+    }
+}
+
+#[test]
+#[should_panic(expected = "Print interval must be positive.")]
+fn print_loss_rejects_a_zero_interval() {
+    PrintLoss::new(0);
+}
+
+#[test]
+fn sgd_step_with_momentum_and_weight_decay() {
+    let p = Value::new(1.0);
+    let mut sgd = SGD::new(vec![p.clone()], 0.1).momentum(0.9);
+    p.update_grad(2.0);
+    sgd.step();
+    assert_close(p.get_data(), 0.8);
+    sgd.step();
+    // Velocity 0.9 * 2 + 2.
+    assert_close(p.get_data(), 0.8 - 0.1 * 3.8);
+
+    let p = Value::new(1.0);
+    let mut sgd = SGD::new(vec![p.clone()], 0.1).weight_decay(0.5);
+    p.update_grad(2.0);
+    sgd.step();
+    assert_close(p.get_data(), 1.0 - 0.1 * 2.5);
+    sgd.zero_grad();
+    assert_eq!(p.get_grad(), 0.0);
+}
+
+#[test]
+fn adam_steps_match_hand_computation() {
+    let p = Value::new(1.0);
+    let mut adam = Adam::new(vec![p.clone()], 0.1);
+    // Step 1 with g = 0.5: m = 0.05 and v = 0.00025, so the bias-corrected
+    // moments are 0.5 and 0.25 and the step is lr * 0.5 / (0.5 + eps).
+    p.update_grad(0.5);
+    adam.step();
+    assert_close(p.get_data(), 1.0 - 0.1 * 0.5 / (0.5 + 1e-8));
+    // Step 2 with g = -1: m = -0.055, v = 0.00124975, corrected by 0.19 and
+    // 0.001999.
+    p.update_grad(-1.0);
+    adam.step();
+    assert_close(p.get_data(), 0.9366103542405654);
+}
+
+/// Fits `y = -3 x0 + 4 x1`, which pulls the weights negative and their
+/// norm well past 1, optionally under `constraint`.
+fn fit_constrained(constraint: Option<Constraint>) -> MLP {
+    let xs: Vec<Vec<f64>> = (0..12)
+        .map(|i| vec![i as f64 / 6.0 - 1.0, (i % 4) as f64 / 2.0 - 0.75])
+        .collect();
+    let data = Dataset::new(
+        xs.clone(),
+        xs.iter().map(|x| vec![-3.0 * x[0] + 4.0 * x[1]]).collect(),
+    );
+    let model = MLP::new_seeded(2, vec![3, 1], Activation::Linear, 5);
+    let sgd = SGD::new(model.parameters(), 0.05);
+    let loss = MSELoss::new(Reduction::Mean);
+    match constraint {
+        Some(c) => {
+            let optimizer = Constrained::new(sgd, model.weight_vectors(), c);
+            Trainer::new(&model, loss, optimizer, &data)
+                .epochs(30)
+                .fit()
+        }
+        None => Trainer::new(&model, loss, sgd, &data).epochs(30).fit(),
+    };
+    model
+}
+
+fn norms(model: &MLP) -> Vec<f64> {
+    model
+        .weight_vectors()
+        .iter()
+        .map(|w| w.iter().map(|w| w.get_data().powi(2)).sum::<f64>().sqrt())
+        .collect()
+}
+
+#[test]
+fn constrained_optimizer_keeps_max_norm_through_fit() {
+    assert!(norms(&fit_constrained(None)).iter().any(|&n| n > 1.0));
+    for n in norms(&fit_constrained(Some(Constraint::MaxNorm(1.0)))) {
+        assert!(n <= 1.0 + 1e-12, "norm {}", n);
+    }
+}
+
+#[test]
+fn constrained_optimizer_keeps_weights_non_negative_through_fit() {
+    let weights = |model: &MLP| -> Vec<f64> {
+        model
+            .weight_vectors()
+            .concat()
+            .iter()
+            .map(|w| w.get_data())
+            .collect()
+    };
+    assert!(weights(&fit_constrained(None)).iter().any(|&w| w < 0.0));
+    for w in weights(&fit_constrained(Some(Constraint::NonNeg))) {
+        assert!(w >= 0.0, "weight {}", w);
+    }
+}
+
+#[test]
+fn weight_noise_steps_from_the_clean_weights() {
+    let data = Dataset::new(
+        vec![vec![0.5, -1.0], vec![1.0, 2.0]],
+        vec![vec![1.0], vec![-1.0]],
+    );
+    let fit = |noise: bool| {
+        let model = MLP::new_seeded(2, vec![3, 1], Activation::Tanh, 7);
+        let initial: Vec<f64> = model.parameters().iter().map(|p| p.get_data()).collect();
+        let sgd = SGD::new(model.parameters(), 0.1);
+        let mut trainer = Trainer::new(&model, MSELoss::new(Reduction::Mean), sgd, &data);
+        if noise {
+            trainer = trainer.weight_noise(0.5, 1);
+        }
+        let history = trainer.fit();
+        drop(trainer);
+        (model, initial, history)
+    };
+    let (noisy, initial, noisy_history) = fit(true);
+    let (clean, _, clean_history) = fit(false);
+
+    // The loss and gradients come from the perturbed weights...
+    assert!(noisy_history[0] != clean_history[0]);
+    let grads =
+        |model: &MLP| -> Vec<f64> { model.parameters().iter().map(|p| p.get_grad()).collect() };
+    assert!(grads(&noisy) != grads(&clean));
+    // ...but the one full-batch step starts from the clean ones.
+    for (p, w) in noisy.parameters().iter().zip(initial) {
+        assert_eq!(p.get_data(), w - 0.1 * p.get_grad());
+    }
+}
+
+#[test]
+fn mixup_trains_cross_entropy_on_soft_labels() {
+    let data = Dataset::new(
+        (0..6).map(|i| vec![i as f64 / 3.0 - 1.0]).collect(),
+        (0..6)
+            .map(|i| {
+                if i < 3 {
+                    vec![1.0, 0.0]
+                } else {
+                    vec![0.0, 1.0]
+                }
+            })
+            .collect(),
+    );
+    let model = MLP::new_seeded(1, vec![4, 2], Activation::Tanh, 2);
+    let sgd = SGD::new(model.parameters(), 0.0);
+    let history = Trainer::new(&model, CrossEntropyLoss::new(Reduction::Mean), sgd, &data)
+        .mixup(0.4, 9)
+        .fit();
+
+    // The same blend, scored by hand.
+    let (mut inputs, mut targets) = (data.inputs.clone(), data.targets.clone());
+    Mixup::new(0.4, 9).apply(&mut inputs, &mut targets);
+    assert!(targets.iter().any(|t| t[0].fract() != 0.0));
+    let expected = inputs
+        .iter()
+        .zip(&targets)
+        .map(|(x, t)| {
+            let x: Vec<Value> = x.iter().map(|&x| Value::constant(x)).collect();
+            soft_cross_entropy(&model.forward(&x), t).get_data()
+        })
+        .sum::<f64>()
+        / 6.0;
+    assert_close(history[0], expected);
+}
+
+#[test]
+fn fitting_reduces_the_loss() {
+    let xs: Vec<f64> = (0..12).map(|i| i as f64 / 6.0 - 1.0).collect();
+    let data = Dataset::new(
+        xs.iter().map(|&x| vec![x]).collect(),
+        xs.iter().map(|&x| vec![2.0 * x + 1.0]).collect(),
+    );
+    let model = MLP::new_seeded(1, vec![8, 1], Activation::Tanh, 3);
+    let adam = Adam::new(model.parameters(), 0.05);
+    let history = Trainer::new(&model, MSELoss::new(Reduction::Mean), adam, &data)
+        .batch_size(4)
+        .epochs(100)
+        .shuffle(1)
+        .fit();
+
+    assert_eq!(history.len(), 100);
+    assert!(
+        history[99] < 0.05 * history[0],
+        "loss went from {} to {}",
+        history[0],
+        history[99]
+    );
+}
+
+#[test]
+fn lr_suggestions_scale_weights_by_grads() {
+    let (p, q) = (Value::new(2.0), Value::new(1.0));
+    let loss = &p * &Value::new(4.0);
+    let groups = vec![
+        ("p".to_string(), vec![p.clone()]),
+        ("q".to_string(), vec![q]),
+    ];
+    let suggestions = suggest_lr(&groups, loss);
+
+    assert_eq!(suggestions[0].group, "p");
+    assert_close(suggestions[0].grad_rms, 4.0);
+    assert_close(suggestions[0].lr.unwrap(), 1e-3 * 2.0 / 4.0);
+    assert_eq!(suggestions[1].lr, None);
+    assert_eq!(p.get_grad(), 0.0);
+
+    // The trainer measures before its first step and then trains as usual.
+    let model = MLP::new_seeded(1, vec![4, 1], Activation::Tanh, 1);
+    assert_eq!(model.parameter_groups().len(), 2);
+    let data = counting_dataset(4);
+    let sgd = SGD::new(model.parameters(), 0.01);
+    let history = Trainer::new(&model, MSELoss::new(Reduction::Mean), sgd, &data)
+        .suggest_lr()
+        .fit();
+    assert_eq!(history.len(), 1);
+}
+
+#[test]
+fn manifest_records_the_run() {
+    let model = Recorder::new();
+    let data = counting_dataset(6);
+    let sgd = SGD::new(model.parameters(), 0.01);
+    let mut trainer = Trainer::new(&model, MSELoss::new(Reduction::Mean), sgd, &data)
+        .batch_size(4)
+        .epochs(3)
+        .shuffle(9);
+    let history = trainer.fit();
+    let manifest: RunManifest = trainer.manifest(&history).checkpoint("runs/model.txt");
+
+    assert_eq!(
+        (manifest.batch_size, manifest.epochs, manifest.seed),
+        (4, 3, Some(9))
+    );
+    assert_eq!((manifest.n_samples, manifest.epochs_run), (6, 3));
+    assert_eq!(manifest.dataset_hash, data.fingerprint());
+    assert_eq!(manifest.final_loss, history[2]);
+    assert_eq!(
+        manifest.best_loss,
+        history.iter().copied().fold(f64::INFINITY, f64::min)
+    );
+    assert_eq!(manifest.checkpoints, ["runs/model.txt"]);
+
+    let json = manifest.to_json();
+    assert!(
+        json.contains(r#""config": {"batch_size": 4, "epochs": 3, "initial_lr": 0.01, "seed": 9}"#)
+    );
+    assert!(json.contains(&format!(r#""hash": "{:016x}""#, data.fingerprint())));
+    assert!(json.contains(r#""checkpoints": ["runs/model.txt"]"#));
+}
+
+#[test]
+fn snapshot_ensemble_from_cosine_restarts() {
+    let dir = std::env::temp_dir().join(format!("micrograd-{}-snapshots", std::process::id()));
+    let xs: Vec<f64> = (0..8).map(|i| i as f64 / 4.0 - 1.0).collect();
+    let data = Dataset::new(
+        xs.iter().map(|&x| vec![x]).collect(),
+        xs.iter().map(|&x| vec![x * x]).collect(),
+    );
+    let model = MLP::new_seeded(1, vec![6, 1], Activation::Tanh, 5);
+    let optimizer = LrScheduler::new(
+        SGD::new(model.parameters(), 0.1),
+        Schedule::CosineWarmRestarts {
+            t_0: 3,
+            eta_min: 0.001,
+        },
+    );
+    let (history, ensemble) = Trainer::new(&model, MSELoss::new(Reduction::Mean), optimizer, &data)
+        .epochs(7)
+        .fit_snapshots(3, &dir)
+        .unwrap();
+
+    assert_eq!(history.len(), 7);
+    // Snapshots after epochs 2 and 5; the seventh epoch starts a new cycle.
+    assert!(dir.join("snapshot_1.txt").exists());
+    assert!(!dir.join("snapshot_2.txt").exists());
+    let last = MLP::load(dir.join("snapshot_1.txt")).unwrap();
+    assert_ne!(
+        last.call(&[0.5])[0].get_data(),
+        model.call(&[0.5])[0].get_data()
+    );
+    let (mean, variance) = ensemble.predict(&[0.5]);
+    assert_eq!((mean.len(), variance.len()), (1, 1));
+    assert!(variance[0] > 0.0);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn trainer_reports_per_task_losses() {
+    // Two regression heads: y0 = x and y1 = -x.
+    let xs: Vec<f64> = (0..8).map(|i| i as f64 / 4.0 - 1.0).collect();
+    let data = Dataset::new(
+        xs.iter().map(|&x| vec![x]).collect(),
+        xs.iter().map(|&x| vec![x, -x]).collect(),
+    );
+    let model = MLP::new_seeded(1, vec![6, 2], Activation::Tanh, 2);
+    let loss = MultiTaskLoss::new(Reduction::Mean)
+        .task("up", MSELoss::new(Reduction::Mean), 1, 1, 1.0)
+        .task("down", MSELoss::new(Reduction::Mean), 1, 1, 3.0);
+    let adam = Adam::new(model.parameters(), 0.05);
+    let mut trainer = Trainer::new(&model, loss, adam, &data)
+        .batch_size(4)
+        .epochs(30);
+    let history = trainer.fit();
+
+    let tasks = trainer.task_history();
+    assert_eq!(tasks.len(), 30);
+    let names: Vec<&str> = tasks[0].iter().map(|(name, _)| name.as_str()).collect();
+    assert_eq!(names, ["up", "down"]);
+    // The epoch loss is the weighted sum of the task losses.
+    for (epoch, loss) in history.iter().enumerate() {
+        assert_close(tasks[epoch][0].1 + 3.0 * tasks[epoch][1].1, *loss);
+    }
+    assert!(tasks[29][1].1 < tasks[0][1].1);
+}
+
+/// `y = previous + w * context + 1` for one-value steps, logging the
+/// previous step it is fed.
+struct Stepper {
+    w: Value,
+    previous: RefCell<Vec<f64>>,
+}
+
+impl Module for Stepper {
+    fn forward(&self, inputs: &[Value]) -> Vec<Value> {
+        self.previous.borrow_mut().push(inputs[1].get_data());
+        vec![&inputs[1] + &self.w * &inputs[0] + 1.0]
+    }
+
+    fn parameters(&self) -> Vec<Value> {
+        vec![self.w.clone()]
+    }
+}
+
+#[test]
+fn sequence_mode_teacher_forcing() {
+    let fed = |ratio: f64| {
+        let model = Stepper {
+            w: Value::new(0.5),
+            previous: RefCell::new(vec![]),
+        };
+        let data = Dataset::new(vec![vec![2.0]], vec![vec![10.0, 20.0, 30.0]]);
+        let sgd = SGD::new(model.parameters(), 0.0);
+        let history = Trainer::new(&model, MSELoss::new(Reduction::Mean), sgd, &data)
+            .sequence(1)
+            .teacher_forcing(ratio, 3)
+            .fit();
+        let fed = model.previous.borrow().clone();
+        (fed, history[0], model)
+    };
+
+    // Ground truth: each step sees the previous target.
+    let (forced, loss, _) = fed(1.0);
+    assert_eq!(forced, [0.0, 10.0, 20.0]);
+    // Predictions 2, 12 and 22 each miss their target by 8.
+    assert_close(loss, 64.0);
+
+    // Free running: each step sees the model's own output, as when
+    // generating with `predict_sequence`.
+    let (own, _, model) = fed(0.0);
+    assert_eq!(own, [0.0, 2.0, 4.0]);
+    model.previous.borrow_mut().clear();
+    let generated: Vec<f64> = predict_sequence(&model, &[2.0], 1, 3)
+        .iter()
+        .map(|y| y.get_data())
+        .collect();
+    assert_eq!(generated, [2.0, 4.0, 6.0]);
+    assert_eq!(*model.previous.borrow(), [0.0, 2.0, 4.0]);
+}
+
+#[test]
+fn clip_grad_norm_rescales_and_keeps_direction() {
+    let params = vec![Value::new(0.0), Value::new(0.0)];
+    params[0].update_grad(3.0);
+    params[1].update_grad(-4.0);
+    assert_close(clip_grad_norm(&params, 10.0), 5.0);
+    assert_eq!(params[1].get_grad(), -4.0);
+
+    assert_close(clip_grad_norm(&params, 2.0), 5.0);
+    assert!(grad_norm(&params) <= 2.0 + 1e-12);
+    assert_close(params[0].get_grad(), 1.2);
+    assert_close(params[1].get_grad(), -1.6);
+}
+
+#[test]
+fn clip_grad_value_clamps_each_gradient() {
+    let params = vec![Value::new(0.0), Value::new(0.0), Value::new(0.0)];
+    for (p, g) in params.iter().zip([3.0, -0.5, -7.0]) {
+        p.update_grad(g);
+    }
+    clip_grad_value(&params, 1.0);
+    let grads: Vec<f64> = params.iter().map(|p| p.get_grad()).collect();
+    assert_eq!(grads, vec![1.0, -0.5, -1.0]);
+}